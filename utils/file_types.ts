@@ -15,9 +15,41 @@ export const outputFileEndings = {
   'image/x-portable-anymap': 'pnm',
 }
 
+/**
+ * Outputs of the converter that wrap the (rasterized) image in a document instead of re-encoding it.
+ */
+export const documentFileEndings = {
+  'image/svg+xml': 'svg',
+  'application/pdf': 'pdf',
+}
+
+/**
+ * Outputs of the converter that are encoded outside of `image`, and so can't be resized to.
+ */
+export const extraOutputFileEndings = {
+  'image/jxl': 'jxl',
+  'image/vnd-ms.dds': 'dds',
+  'image/ktx2': 'ktx2',
+}
+
+export const convertOutputFileEndings = {
+  ...outputFileEndings,
+  ...extraOutputFileEndings,
+  ...documentFileEndings,
+}
+
 export const inputFileEndings = {
   ...outputFileEndings,
   'image/svg+xml': 'svg',
+  'image/svg+xml-compressed': 'svgz',
+  'image/heic': 'heic',
+  'image/heif': 'heif',
+  'image/jxl': 'jxl',
+  'image/vnd-ms.dds': 'dds',
+  'image/x-adobe-dng': 'dng',
+  'image/x-canon-cr2': 'cr2',
+  'image/x-nikon-nef': 'nef',
+  'image/x-sony-arw': 'arw',
 }
 
 export const acceptList = [
@@ -34,6 +66,20 @@ export const acceptList = [
   '.pnm',
   // EXR
   '.exr',
+  // SVGZ
+  '.svgz',
+  // HEIC/HEIF
+  '.heic',
+  '.heif',
+  // JPEG XL
+  '.jxl',
+  // DDS
+  '.dds',
+  // Camera RAW
+  '.dng',
+  '.cr2',
+  '.nef',
+  '.arw',
 ].join(',')
 
 export type MimeTypes = keyof typeof outputFileEndings | keyof typeof inputFileEndings | 'application/octet-stream'
@@ -68,31 +114,48 @@ export function getEndingMimeType(ending: string): MimeTypes | undefined {
  */
 export const supportedFormats = [
   { format: 'AVIF', decoding: 'No', encoding: 'Yes (lossy only)' },
+  { format: 'ARW', decoding: 'Yes (preview or basic development)', encoding: 'No' },
   { format: 'BMP', decoding: 'Yes', encoding: 'Yes' },
-  /* { format: 'DDS', decoding: 'Yes', encoding: '---' }, */
+  { format: 'CR2', decoding: 'Yes (preview or basic development)', encoding: 'No' },
+  { format: 'DDS', decoding: 'Yes (DXT1/3/5, uncompressed)', encoding: 'Yes (uncompressed)' },
+  { format: 'DNG', decoding: 'Yes (preview or basic development)', encoding: 'No' },
   { format: 'Farbfeld', decoding: 'Yes', encoding: 'Yes' },
   { format: 'GIF', decoding: 'Yes', encoding: 'Yes' },
   { format: 'HDR', decoding: 'Yes', encoding: 'Yes' },
+  { format: 'HEIC', decoding: 'Yes', encoding: 'No' },
   { format: 'ICO', decoding: 'Yes', encoding: 'Yes' },
   { format: 'JPEG', decoding: 'Yes', encoding: 'Yes' },
+  { format: 'JPEG XL', decoding: 'Yes', encoding: 'Yes (lossless only, no JPEG recompression)' },
+  { format: 'KTX2', decoding: 'No', encoding: 'Yes (uncompressed)' },
+  { format: 'NEF', decoding: 'Yes (preview or basic development)', encoding: 'No' },
   { format: 'EXR', decoding: 'Yes', encoding: 'Yes' },
   { format: 'PNG', decoding: 'Yes', encoding: 'Yes' },
   { format: 'PNM', decoding: 'Yes', encoding: 'Yes' },
   { format: 'QOI', decoding: 'Yes', encoding: 'Yes' },
   { format: 'TGA', decoding: 'Yes', encoding: 'Yes' },
   { format: 'TIFF', decoding: 'Yes', encoding: 'Yes' },
+  // Lossy WebP needs libwebp (the `webp-lossy` feature), which doesn't build for wasm32.
   { format: 'WebP', decoding: 'Yes', encoding: 'Yes (lossless only)' },
-  { format: 'SVG', decoding: 'Yes', encoding: 'No' },
+  { format: 'SVG', decoding: 'Yes', encoding: 'Yes (embedded image)' },
+  { format: 'PDF', decoding: 'No', encoding: 'Yes (single page)' },
 ]
 
 export const formatEndingMapping = {
+  ARW: 'arw',
   AVIF: 'avif',
   BMP: 'bmp',
+  CR2: 'cr2',
+  DDS: 'dds',
+  DNG: 'dng',
   Farbfeld: 'ff',
   GIF: 'gif',
   HDR: 'hdr',
+  HEIC: 'heic',
   ICO: 'ico',
   JPEG: 'jpeg',
+  'JPEG XL': 'jxl',
+  KTX2: 'ktx2',
+  NEF: 'nef',
   EXR: 'exr',
   PNG: 'png',
   PNM: 'pnm',
@@ -101,6 +164,7 @@ export const formatEndingMapping = {
   TIFF: 'tiff',
   WebP: 'webp',
   SVG: 'svg',
+  PDF: 'pdf',
 } as const
 
 export type FormatEndingKey = keyof typeof formatEndingMapping
@@ -38,7 +38,8 @@ export async function runWorker<TData, TRequest = object>(
       else if (type === WorkerMessageType.ERROR) {
         clearTimeout(timer)
         worker.terminate()
-        reject(new Error(payload.error))
+        // Keep `code`, `format` and `stage` so callers can branch on them like on wasm errors
+        reject(Object.assign(new Error(payload.error.message), payload.error))
       }
     }
 
@@ -4,9 +4,9 @@ import { readPackageJSON, writePackageJSON } from 'pkg-types'
 const wasmDir = import.meta.dir + '/..'
 
 await Promise.all([
-  $`wasm-pack build --target bundler --out-dir pkg/bundler --release --features wasm`.cwd(wasmDir),
-  $`wasm-pack build --target nodejs  --out-dir pkg/node    --release --features wasm`.cwd(wasmDir),
-  $`wasm-pack build --target web     --out-dir pkg/web     --release --features wasm`.cwd(wasmDir),
+  $`wasm-pack build --target bundler --out-dir pkg/bundler --release --features wasm,heic,jxl,jxl-encode,raw`.cwd(wasmDir),
+  $`wasm-pack build --target nodejs  --out-dir pkg/node    --release --features wasm,heic,jxl,jxl-encode,raw`.cwd(wasmDir),
+  $`wasm-pack build --target web     --out-dir pkg/web     --release --features wasm,heic,jxl,jxl-encode,raw`.cwd(wasmDir),
 ])
 
 const base = await readPackageJSON(`${wasmDir}/pkg/bundler/package.json`)
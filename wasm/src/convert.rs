@@ -1,26 +1,46 @@
 use std::io::Cursor;
 
+use crate::detect::resolve_mime_type;
 use crate::error::WasmImageError;
 use crate::load::{load_image, SourceImage};
+use crate::pages::select_page;
 use crate::source_type::SourceType;
+use crate::target_type::TargetType;
 use image::ImageFormat;
 pub use settings::Settings;
 
 #[cfg(feature = "wasm")]
-use {js_sys::Uint8Array, wasm_bindgen::prelude::*};
-
+use {
+    crate::input::{ImageInput, Input},
+    js_sys::Uint8Array,
+    settings::SettingsInput,
+    wasm_bindgen::prelude::*,
+};
+
+pub(crate) mod pdf;
 pub mod settings;
 pub(crate) mod svg;
+pub(crate) mod vector;
+pub(crate) mod webp;
 
 fn write_image(
     img: &image::DynamicImage,
-    file_type: Option<ImageFormat>,
+    target_type: ImageFormat,
 ) -> Result<Vec<u8>, WasmImageError> {
     let mut output: Vec<u8> = Vec::new();
 
-    let target_type = file_type.unwrap_or(ImageFormat::Png);
-
-    img.write_to(&mut Cursor::new(&mut output), target_type)?;
+    img.write_to(&mut Cursor::new(&mut output), target_type)
+        .map_err(|e| match e {
+            image::ImageError::Unsupported(e)
+                if matches!(e.kind(), image::error::UnsupportedErrorKind::Format(_)) =>
+            {
+                WasmImageError::UnsupportedTargetFormat(
+                    target_type.to_mime_type().to_string(),
+                    e.to_string(),
+                )
+            }
+            e => e.into(),
+        })?;
 
     Ok(output)
 }
@@ -56,50 +76,112 @@ fn process_image(
     Ok(processed)
 }
 
+/// Encodes a loaded image as `target_type`.
+pub(crate) fn encode_target(
+    img: &SourceImage,
+    file: &[u8],
+    src_type: &str,
+    target_type: &str,
+    settings: Option<&Settings>,
+) -> Result<Vec<u8>, WasmImageError> {
+    let source_format = ImageFormat::from_mime_type(src_type);
+
+    // Files recompressed from JPEG convert back to the original JPEG without loss.
+    #[cfg(feature = "jxl")]
+    if matches!(SourceType::from_mime_type(src_type), Some(SourceType::Jxl))
+        && target_type == "image/jpeg"
+    {
+        if let Some(jpeg) = crate::jxl::reconstruct_jpeg(file)? {
+            return Ok(jpeg);
+        }
+    }
+
+    match TargetType::from_mime_type(target_type) {
+        Some(TargetType::Svg) => vector::write_svg(img, file, source_format, settings),
+        Some(TargetType::Pdf) => vector::write_single_page_pdf(img, file, source_format, settings),
+        // JPEG input is stored as lossless pixels, JPEG recompression isn't supported.
+        #[cfg(feature = "jxl-encode")]
+        Some(TargetType::Jxl) => {
+            let img = process_image(img, source_format, None, settings)?;
+            crate::jxl::encode_jxl(&img)
+        }
+        Some(target @ (TargetType::Dds | TargetType::Ktx2)) => {
+            let img = process_image(img, source_format, None, settings)?;
+            let texture = match settings {
+                Some(Settings::Texture(texture)) => texture.clone(),
+                _ => settings::TextureSettings::default(),
+            };
+            let levels = crate::resize::mip_chain(&img, texture.mipmaps);
+            match target {
+                TargetType::Dds => crate::texture::encode_dds(&levels),
+                _ => Ok(crate::texture::encode_ktx2(&levels)),
+            }
+        }
+        Some(TargetType::Raster(target_format)) => {
+            let img = process_image(img, source_format, Some(target_format), settings)?;
+            if target_format == ImageFormat::WebP {
+                let webp = webp::resolve_settings(settings, file, src_type);
+                if webp.lossy == Some(true) {
+                    return webp::encode_webp(&img, &webp, None, None);
+                }
+            }
+            write_image(&img, target_format)
+        }
+        None => Err(WasmImageError::UnsupportedTargetFormat(
+            target_type.to_string(),
+            format!("{target_type} is not a known image type"),
+        )),
+    }
+}
+
 #[cfg(feature = "wasm")]
 #[wasm_bindgen(js_name = convertImage)]
 #[allow(clippy::needless_pass_by_value)]
 /// Convert an image from one format to another.
 /// # Arguments
-/// * `file` - The image file to convert.
+/// * `file` - The image file to convert, as a `Uint8Array` or a `Blob`.
 /// * `src_type` - The MIME type of the source image.
-/// * `target_type` - The MIME type of the target image.
+/// * `target_type` - The MIME type of the target image, or `image/svg+xml` / `application/pdf`
+///   to embed the image in an SVG or a single-page PDF.
 /// * `cb` - A callback function to report progress.
 /// * `convert_settings` - Settings for the conversion.
+/// * `page` - Zero-based page of a multi-page TIFF to convert. Defaults to the first page.
 /// # Errors
-/// Returns an error if the conversion fails.
+/// Returns an error if the conversion fails or the page does not exist.
 pub fn convert_image(
-    file: &Uint8Array,
+    file: &ImageInput,
     src_type: &str,
     target_type: &str,
     cb: &js_sys::Function,
-    convert_settings: Option<Settings>,
+    convert_settings: Option<SettingsInput>,
+    page: Option<u32>,
 ) -> Result<Uint8Array, JsValue> {
-    let src_mime_type = SourceType::from_mime_type(src_type);
-
     crate::progress::report(cb, 10.0, "Starting conversion");
 
-    let file = file.to_vec();
+    let convert_settings = convert_settings
+        .as_ref()
+        .map(Settings::from_input)
+        .transpose()?;
+
+    let file = Input::new(file)?.read()?;
+    let src_type = resolve_mime_type(&file, src_type).map_err(JsValue::from)?;
+    let src_mime_type = SourceType::from_mime_type(src_type);
 
     crate::progress::report(cb, 35.0, "Loading image");
 
-    let img = load_image(&file, src_mime_type.as_ref())
-        .map_err(|e| JsValue::from_str(e.to_string().as_str()))?;
+    let file = select_page(&file, src_mime_type.as_ref(), page).map_err(JsValue::from)?;
+    let img = load_image(&file, src_mime_type.as_ref()).map_err(JsValue::from)?;
 
-    crate::progress::report(cb, 50.0, "Processing image");
+    crate::progress::report(cb, 50.0, "Converting image");
 
-    let img = process_image(
+    let output = encode_target(
         &img,
-        ImageFormat::from_mime_type(src_type),
-        ImageFormat::from_mime_type(target_type),
+        &file,
+        src_type,
+        target_type,
         convert_settings.as_ref(),
     )
-    .map_err(|e| JsValue::from_str(e.to_string().as_str()))?;
-
-    crate::progress::report(cb, 70.0, "Converting image");
-
-    let output = write_image(&img, ImageFormat::from_mime_type(target_type))
-        .map_err(|e| JsValue::from_str(e.to_string().as_str()))?;
+    .map_err(JsValue::from)?;
 
     crate::progress::report(cb, 100.0, "Conversion complete");
 
@@ -111,10 +193,12 @@ pub fn convert_image(
 /// # Arguments
 /// * `file` - The image file to convert.
 /// * `src_type` - The MIME type of the source image.
-/// * `target_type` - The MIME type of the target image.
+/// * `target_type` - The MIME type of the target image, or `image/svg+xml` / `application/pdf`
+///   to embed the image in an SVG or a single-page PDF.
 /// * `convert_settings` - Settings for the conversion.
+/// * `page` - Zero-based page of a multi-page TIFF to convert. Defaults to the first page.
 /// # Errors
-/// Returns an error if the conversion fails.
+/// Returns an error if the conversion fails or the page does not exist.
 /// # Example
 /// ```rust
 /// ```
@@ -123,17 +207,19 @@ pub fn convert_image(
     src_type: &str,
     target_type: &str,
     convert_settings: &Option<Settings>,
+    page: Option<u32>,
 ) -> Result<Vec<u8>, WasmImageError> {
+    let src_type = resolve_mime_type(file, src_type)?;
     let src_mime_type = SourceType::from_mime_type(src_type);
 
-    let img = load_image(file, src_mime_type.as_ref())?;
+    let file = select_page(file, src_mime_type.as_ref(), page)?;
+    let img = load_image(&file, src_mime_type.as_ref())?;
 
-    let img = process_image(
+    encode_target(
         &img,
-        ImageFormat::from_mime_type(src_type),
-        ImageFormat::from_mime_type(target_type),
+        &file,
+        src_type,
+        target_type,
         convert_settings.as_ref(),
-    )?;
-
-    write_image(&img, ImageFormat::from_mime_type(target_type))
+    )
 }
@@ -0,0 +1,300 @@
+//! Minimal PDF writer: one image per page, no external dependencies beyond zlib compression.
+
+use std::fmt::Write as _;
+use std::io::Write as _;
+
+use flate2::{write::ZlibEncoder, Compression};
+use image::DynamicImage;
+
+use crate::error::WasmImageError;
+use crate::metadata::container::jpeg_header_segments;
+
+/// Points per inch, the PDF user space unit.
+const POINTS_PER_INCH: f32 = 72.0;
+/// Pixel density assumed when sizing a page from an image.
+pub(crate) const DEFAULT_DPI: f32 = 96.0;
+
+/// Converts a length in pixels at [`DEFAULT_DPI`] to points.
+pub(crate) fn px_to_pt(px: u32) -> f32 {
+    px as f32 * POINTS_PER_INCH / DEFAULT_DPI
+}
+
+/// An image `XObject`, stored either as the original JPEG stream or as zlib-compressed samples.
+pub(crate) struct PdfImage {
+    width: u32,
+    height: u32,
+    gray: bool,
+    dct: bool,
+    data: Vec<u8>,
+    /// Compressed 8-bit alpha channel, written as a soft mask.
+    alpha: Option<Vec<u8>>,
+}
+
+fn deflate(data: &[u8]) -> Result<Vec<u8>, WasmImageError> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(data)
+        .and_then(|()| encoder.finish())
+        .map_err(|e| WasmImageError::EncodingError(e.to_string()))
+}
+
+/// Number of colour components declared in the frame header of a baseline or
+/// progressive JPEG.
+fn jpeg_components(jpeg: &[u8]) -> Option<u8> {
+    let (segments, _) = jpeg_header_segments(jpeg).ok()?;
+    let frame = segments
+        .iter()
+        .find(|segment| matches!(segment.marker, 0xC0..=0xC2))?;
+    // Precision, height and width come before the component count.
+    frame.payload(jpeg).get(5).copied()
+}
+
+impl PdfImage {
+    /// Stores the pixels losslessly, as grayscale when the image has no colour.
+    pub(crate) fn from_image(img: &DynamicImage) -> Result<Self, WasmImageError> {
+        let gray = !img.color().has_color();
+        let data = if gray {
+            img.to_luma8().into_raw()
+        } else {
+            img.to_rgb8().into_raw()
+        };
+        let alpha = if img.color().has_alpha() {
+            let alpha: Vec<u8> = img.to_rgba8().pixels().map(|p| p.0[3]).collect();
+            // Fully opaque images don't need a mask.
+            if alpha.iter().all(|&a| a == u8::MAX) {
+                None
+            } else {
+                Some(deflate(&alpha)?)
+            }
+        } else {
+            None
+        };
+
+        Ok(Self {
+            width: img.width(),
+            height: img.height(),
+            gray,
+            dct: false,
+            data: deflate(&data)?,
+            alpha,
+        })
+    }
+
+    /// Embeds a JPEG file as is. Returns `None` for JPEGs that PDF viewers would need
+    /// extra colour space information for (CMYK).
+    pub(crate) fn from_jpeg(jpeg: &[u8], width: u32, height: u32) -> Option<Self> {
+        let gray = match jpeg_components(jpeg)? {
+            1 => true,
+            3 => false,
+            _ => return None,
+        };
+        Some(Self {
+            width,
+            height,
+            gray,
+            dct: true,
+            data: jpeg.to_vec(),
+            alpha: None,
+        })
+    }
+
+    pub(crate) fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub(crate) fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+/// A page holding one image, drawn at `rect` (`x`, `y`, `width`, `height` in points,
+/// origin at the bottom left) and clipped to `clip`.
+pub(crate) struct PdfPage {
+    pub width: f32,
+    pub height: f32,
+    pub image: PdfImage,
+    pub rect: [f32; 4],
+    pub clip: Option<[f32; 4]>,
+}
+
+impl PdfPage {
+    /// A page exactly the size of the image.
+    pub(crate) fn fitted(image: PdfImage) -> Self {
+        let (width, height) = (px_to_pt(image.width), px_to_pt(image.height));
+        Self {
+            width,
+            height,
+            image,
+            rect: [0.0, 0.0, width, height],
+            clip: None,
+        }
+    }
+}
+
+struct PdfBuilder {
+    output: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl PdfBuilder {
+    fn object(&mut self, id: usize, dict: &str, stream: Option<&[u8]>) {
+        self.offsets[id - 1] = self.output.len();
+        let _ = write!(self.output, "{id} 0 obj\n{dict}\n");
+        if let Some(stream) = stream {
+            self.output.extend_from_slice(b"stream\n");
+            self.output.extend_from_slice(stream);
+            self.output.extend_from_slice(b"\nendstream\n");
+        }
+        self.output.extend_from_slice(b"endobj\n");
+    }
+}
+
+fn image_dict(image: &PdfImage, smask: Option<usize>) -> String {
+    let mut dict = format!(
+        "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /{} \
+         /BitsPerComponent 8 /Filter /{} /Length {}",
+        image.width,
+        image.height,
+        if image.gray {
+            "DeviceGray"
+        } else {
+            "DeviceRGB"
+        },
+        if image.dct {
+            "DCTDecode"
+        } else {
+            "FlateDecode"
+        },
+        image.data.len()
+    );
+    if let Some(smask) = smask {
+        let _ = write!(dict, " /SMask {smask} 0 R");
+    }
+    dict.push_str(" >>");
+    dict
+}
+
+/// Writes `pages` as a PDF document.
+pub(crate) fn write_pdf(pages: &[PdfPage]) -> Vec<u8> {
+    // Objects: 1 catalog, 2 page tree, then per page: page, content, image, soft mask.
+    const OBJECTS_PER_PAGE: usize = 4;
+    let page_id = |index: usize| 3 + index * OBJECTS_PER_PAGE;
+    let object_count = 2 + pages.len() * OBJECTS_PER_PAGE;
+
+    let mut pdf = PdfBuilder {
+        output: b"%PDF-1.4\n%\xE2\xE3\xCF\xD3\n".to_vec(),
+        offsets: vec![0; object_count],
+    };
+
+    pdf.object(1, "<< /Type /Catalog /Pages 2 0 R >>", None);
+    let kids: Vec<String> = (0..pages.len())
+        .map(|i| format!("{} 0 R", page_id(i)))
+        .collect();
+    pdf.object(
+        2,
+        &format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            pages.len()
+        ),
+        None,
+    );
+
+    for (index, page) in pages.iter().enumerate() {
+        let id = page_id(index);
+        let (content_id, image_id, smask_id) = (id + 1, id + 2, id + 3);
+        let [x, y, width, height] = page.rect;
+
+        pdf.object(
+            id,
+            &format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] \
+                 /Resources << /XObject << /Im0 {image_id} 0 R >> >> /Contents {content_id} 0 R >>",
+                page.width, page.height
+            ),
+            None,
+        );
+        let clip = page.clip.map_or(String::new(), |[x, y, width, height]| {
+            format!("{x:.2} {y:.2} {width:.2} {height:.2} re W n ")
+        });
+        let content = format!("q {clip}{width:.2} 0 0 {height:.2} {x:.2} {y:.2} cm /Im0 Do Q");
+        pdf.object(
+            content_id,
+            &format!("<< /Length {} >>", content.len()),
+            Some(content.as_bytes()),
+        );
+
+        let image = &page.image;
+        pdf.object(
+            image_id,
+            &image_dict(image, image.alpha.as_ref().map(|_| smask_id)),
+            Some(&image.data),
+        );
+        // Unused soft mask slots are written as null objects to keep the numbering simple.
+        match &image.alpha {
+            Some(alpha) => pdf.object(
+                smask_id,
+                &format!(
+                    "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceGray \
+                     /BitsPerComponent 8 /Filter /FlateDecode /Length {} >>",
+                    image.width,
+                    image.height,
+                    alpha.len()
+                ),
+                Some(alpha),
+            ),
+            None => pdf.object(smask_id, "null", None),
+        }
+    }
+
+    let xref_offset = pdf.output.len();
+    let mut xref = format!("xref\n0 {}\n0000000000 65535 f \n", object_count + 1);
+    for offset in &pdf.offsets {
+        let _ = writeln!(xref, "{offset:010} 00000 n ");
+    }
+    let _ = write!(
+        xref,
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref_offset}\n%%EOF\n",
+        object_count + 1
+    );
+    pdf.output.extend_from_slice(xref.as_bytes());
+    pdf.output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn writes_pages_with_soft_masks() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 2, Rgba([255, 0, 0, 128])));
+        let pages = vec![
+            PdfPage::fitted(PdfImage::from_image(&img).unwrap()),
+            PdfPage::fitted(PdfImage::from_image(&img.to_rgb8().into()).unwrap()),
+        ];
+
+        let pdf = write_pdf(&pages);
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.starts_with("%PDF-1.4"));
+        assert!(text.contains("/Count 2"));
+        assert!(text.contains("/MediaBox [0 0 3.00 1.50]"));
+        assert_eq!(text.matches("/SMask").count(), 1);
+
+        // Every xref entry must point at the start of its object.
+        let xref = text.rfind("xref\n").unwrap();
+        for (id, line) in text[xref..].lines().skip(3).take(10).enumerate() {
+            let offset: usize = line[..10].parse().unwrap();
+            assert!(text[offset..].starts_with(&format!("{} 0 obj", id + 1)));
+        }
+    }
+
+    #[test]
+    fn embeds_jpeg_without_reencoding() {
+        let jpeg = include_bytes!("../../assets/test.jpeg");
+        let img = image::load_from_memory(jpeg).unwrap();
+        let image = PdfImage::from_jpeg(jpeg, img.width(), img.height()).unwrap();
+        assert!(image.dct);
+        assert_eq!(image.data.len(), jpeg.len());
+    }
+}
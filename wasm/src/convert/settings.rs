@@ -1,31 +1,226 @@
+use std::sync::Arc;
+
+use serde_bytes::ByteBuf;
+
+#[cfg(feature = "wasm")]
+use {crate::error::WasmImageError, wasm_bindgen::prelude::*};
+
 #[cfg(feature = "wasm")]
 #[derive(tsify::Tsify, serde::Deserialize, serde::Serialize)]
-#[tsify(into_wasm_abi, from_wasm_abi)]
+#[tsify(into_wasm_abi)]
 #[serde(tag = "type")]
 pub enum Settings {
     #[serde(rename = "svg")]
     Svg(SvgSettings),
+    #[serde(rename = "raw")]
+    Raw(RawSettings),
+    #[serde(rename = "texture")]
+    Texture(TextureSettings),
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+extern "C" {
+    /// Conversion settings. Read with `serde-wasm-bindgen` rather than through JSON, so font
+    /// files can be passed as `Uint8Array`s.
+    #[wasm_bindgen(typescript_type = "Settings")]
+    pub type SettingsInput;
+}
+
+#[cfg(feature = "wasm")]
+impl Settings {
+    pub(crate) fn from_input(input: &SettingsInput) -> Result<Self, WasmImageError> {
+        serde_wasm_bindgen::from_value(input.into())
+            .map_err(|e| WasmImageError::InvalidSettings(e.to_string()))
+    }
 }
 
 #[cfg(not(feature = "wasm"))]
-#[derive(Clone, Copy, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
 pub enum Settings {
     Svg(SvgSettings),
+    WebP(WebPSettings),
+    Raw(RawSettings),
+    Texture(TextureSettings),
 }
 
+/// How the rasterized size of an SVG is derived from its intrinsic size.
 #[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
 #[cfg_attr(feature = "wasm", tsify(into_wasm_abi, from_wasm_abi))]
-#[derive(Clone, Copy, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SvgFit {
+    /// The SVG's own size (its `width`/`height` or `viewBox`), multiplied by `scale`.
+    Intrinsic,
+    /// `width` pixels wide, height following the aspect ratio.
+    Width,
+    /// `height` pixels high, width following the aspect ratio.
+    Height,
+    /// The largest size within `width` x `height` that keeps the aspect ratio.
+    Contain,
+}
+
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[cfg_attr(feature = "wasm", tsify(into_wasm_abi, from_wasm_abi))]
+#[derive(Clone, serde::Deserialize, serde::Serialize)]
 pub struct SvgSettings {
-    pub width: u32,
-    pub height: u32,
+    /// Sizing mode. When omitted it is inferred from the given dimensions: both give
+    /// `contain`, only one gives `width`/`height`, none gives `intrinsic`.
+    #[serde(default)]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub fit: Option<SvgFit>,
+    #[serde(default)]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub width: Option<u32>,
+    #[serde(default)]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub height: Option<u32>,
+    /// Multiplier for the intrinsic size.
+    #[serde(default = "default_scale")]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub scale: f32,
+    /// Resolution used to convert physical units (`mm`, `in`, `pt`) to pixels.
+    #[serde(default = "default_dpi")]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub dpi: f32,
+    /// Fill behind the drawing as `#rgb`, `#rrggbb` or `#rrggbbaa`. Transparent by default.
+    #[serde(default)]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub background: Option<String>,
+    /// Empty space in pixels added on every side, on top of the fitted size.
+    #[serde(default)]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub padding: u32,
+    /// TrueType/OpenType font files used for `<text>`. Each generic family (`serif`,
+    /// `sans-serif`, `monospace`, ...) maps to the first font of that style, and families
+    /// that match no font fall back to the first one, so text renders even if the SVG
+    /// names a font that was not supplied. Shared with the font database, not copied.
+    #[serde(default)]
+    #[cfg_attr(feature = "wasm", tsify(optional, type = "Uint8Array[]"))]
+    pub fonts: Vec<Arc<ByteBuf>>,
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+fn default_dpi() -> f32 {
+    96.0
+}
+
+impl SvgSettings {
+    #[must_use]
+    pub fn fit(&self) -> SvgFit {
+        self.fit.unwrap_or(match (self.width, self.height) {
+            (Some(_), Some(_)) => SvgFit::Contain,
+            (Some(_), None) => SvgFit::Width,
+            (None, Some(_)) => SvgFit::Height,
+            (None, None) => SvgFit::Intrinsic,
+        })
+    }
 }
 
 impl Default for SvgSettings {
     fn default() -> Self {
         Self {
-            width: 100,
-            height: 100,
+            fit: None,
+            width: None,
+            height: None,
+            scale: default_scale(),
+            dpi: default_dpi(),
+            background: None,
+            padding: 0,
+            fonts: Vec::new(),
+        }
+    }
+}
+
+/// WebP encoder settings. Lossy encoding requires the `webp-lossy` feature, which doesn't
+/// build for wasm32, so the JS API has no WebP settings and always writes lossless WebP.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct WebPSettings {
+    /// Encode lossy (VP8) instead of lossless (VP8L). When omitted, lossy sources (JPEG,
+    /// lossy WebP, AVIF, HEIC) stay lossy if the `webp-lossy` feature is enabled, and
+    /// everything else is encoded losslessly.
+    #[serde(default)]
+    pub lossy: Option<bool>,
+    /// Lossy quality from 0 (smallest) to 100 (best).
+    #[serde(default = "default_quality")]
+    pub quality: f32,
+    /// Compression effort from 0 (fastest) to 6 (smallest).
+    #[serde(default = "default_method")]
+    pub method: u8,
+}
+
+fn default_quality() -> f32 {
+    80.0
+}
+
+fn default_method() -> u8 {
+    4
+}
+
+impl Default for WebPSettings {
+    fn default() -> Self {
+        Self {
+            lossy: None,
+            quality: default_quality(),
+            method: default_method(),
+        }
+    }
+}
+
+/// How a camera RAW file is turned into an image.
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[cfg_attr(feature = "wasm", tsify(into_wasm_abi, from_wasm_abi))]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RawMode {
+    /// The largest JPEG preview embedded by the camera. Fast, and rendered like the camera
+    /// would. Files without a preview are developed instead.
+    #[default]
+    Preview,
+    /// Demosaic the sensor data, with the white balance and exposure settings.
+    Develop,
+}
+
+/// Camera RAW (DNG, CR2, NEF, ARW) settings. RAW decoding requires the `raw` feature.
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[cfg_attr(feature = "wasm", tsify(into_wasm_abi, from_wasm_abi))]
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+pub struct RawSettings {
+    #[serde(default)]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub mode: RawMode,
+    /// Red, green and blue multipliers. Defaults to the white balance the camera recorded.
+    #[serde(default)]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub white_balance: Option<[f32; 3]>,
+    /// Exposure correction in stops (EV) when developing.
+    #[serde(default)]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub exposure: f32,
+}
+
+/// DDS and KTX2 texture export settings.
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[cfg_attr(feature = "wasm", tsify(into_wasm_abi, from_wasm_abi))]
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct TextureSettings {
+    /// Store a mipmap chain, halving the size down to 1x1, after the full-size image.
+    #[serde(default = "default_mipmaps")]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub mipmaps: bool,
+}
+
+fn default_mipmaps() -> bool {
+    true
+}
+
+impl Default for TextureSettings {
+    fn default() -> Self {
+        Self {
+            mipmaps: default_mipmaps(),
         }
     }
 }
@@ -1,39 +1,300 @@
+use std::collections::{hash_map::Entry, HashMap};
+
 use resvg::render;
-use resvg::tiny_skia::{IntSize, Pixmap};
-use resvg::usvg::{Options, Size, Transform, Tree};
+use resvg::tiny_skia::{Color, IntSize, Pixmap};
+use resvg::usvg::fontdb::{Family, Query, Source, Weight};
+use resvg::usvg::roxmltree::{Document, Node as XmlNode, NodeId, ParsingOptions};
+use resvg::usvg::{FontResolver, Options, Size, Transform, Tree};
+use ttf_parser::{RawFace, Tag};
+
+use super::settings::{SvgFit, SvgSettings};
+use crate::error::{ErrorStage, WasmImageError};
+use crate::limits::{check_dimensions, check_svg_bytes, check_svg_nodes};
+
+/// Size assumed for SVGs that declare neither `width`/`height` nor a `viewBox`.
+const FALLBACK_SIZE: f32 = 100.0;
+
+/// Resolves the pixel size of the rasterized SVG from its intrinsic size and the settings.
+fn output_size(intrinsic: Size, settings: &SvgSettings) -> Result<IntSize, WasmImageError> {
+    let (width, height) = (intrinsic.width(), intrinsic.height());
+    let required = |value: Option<u32>, name: &str| {
+        value.map(|v| v as f32).ok_or_else(|| {
+            WasmImageError::InvalidSettings(format!("SVG fit mode requires a {name}"))
+        })
+    };
 
-use super::settings::SvgSettings;
-use crate::error::WasmImageError;
+    let (out_width, out_height) = match settings.fit() {
+        SvgFit::Intrinsic => (width * settings.scale, height * settings.scale),
+        SvgFit::Width => {
+            let target = required(settings.width, "width")?;
+            (target, height * target / width)
+        }
+        SvgFit::Height => {
+            let target = required(settings.height, "height")?;
+            (width * target / height, target)
+        }
+        SvgFit::Contain => {
+            let factor = (required(settings.width, "width")? / width)
+                .min(required(settings.height, "height")? / height);
+            (width * factor, height * factor)
+        }
+    };
+
+    IntSize::from_wh(
+        out_width.round().max(1.0) as u32,
+        out_height.round().max(1.0) as u32,
+    )
+    .ok_or(WasmImageError::SvgError(resvg::usvg::Error::InvalidSize))
+}
 
-pub(crate) fn svg_to_png(svg: &[u8], svg_settings: SvgSettings) -> Result<Vec<u8>, WasmImageError> {
-    let SvgSettings { width, height } = svg_settings;
+/// Rejects a scale or DPI that isn't a positive number, whatever the fit mode.
+fn check_settings(settings: &SvgSettings) -> Result<(), WasmImageError> {
+    for (value, name) in [(settings.scale, "scale"), (settings.dpi, "DPI")] {
+        if !(value.is_finite() && value > 0.0) {
+            return Err(WasmImageError::InvalidSettings(format!(
+                "SVG {name} must be greater than 0"
+            )));
+        }
+    }
+    Ok(())
+}
 
-    let options = Options {
-        dpi: 96.0,
+/// Parses a `#rgb`, `#rrggbb` or `#rrggbbaa` colour.
+fn parse_color(color: &str) -> Result<Color, WasmImageError> {
+    let invalid = || WasmImageError::InvalidSettings(format!("Invalid background colour: {color}"));
+    let hex = color.trim().strip_prefix('#').ok_or_else(invalid)?;
+    if !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let channel = |i: usize, len: usize| {
+        let value = u8::from_str_radix(&hex[i * len..(i + 1) * len], 16).map_err(|_| invalid())?;
+        Ok::<_, WasmImageError>(if len == 1 { value * 17 } else { value })
+    };
+
+    let (len, alpha) = match hex.len() {
+        3 => (1, 255),
+        6 => (2, 255),
+        8 => (2, channel(3, 2)?),
+        _ => return Err(invalid()),
+    };
+    Ok(Color::from_rgba8(
+        channel(0, len)?,
+        channel(1, len)?,
+        channel(2, len)?,
+        alpha,
+    ))
+}
+
+/// Builds the parser options, with the supplied fonts loaded into the font database.
+fn svg_options(svg_settings: &SvgSettings) -> Options<'static> {
+    let mut options = Options {
+        dpi: svg_settings.dpi,
         shape_rendering: resvg::usvg::ShapeRendering::GeometricPrecision,
         text_rendering: resvg::usvg::TextRendering::OptimizeLegibility,
         image_rendering: resvg::usvg::ImageRendering::OptimizeQuality,
-        default_size: Size::from_wh(width as f32, height as f32).unwrap(),
+        default_size: Size::from_wh(FALLBACK_SIZE, FALLBACK_SIZE).unwrap(),
         ..Default::default()
     };
 
-    let tree = Tree::from_data(svg, &options)?;
+    if svg_settings.fonts.is_empty() {
+        return options;
+    }
+
+    let fontdb = options.fontdb_mut();
+    for font in &svg_settings.fonts {
+        fontdb.load_font_source(Source::Binary(font.clone()));
+    }
+    let faces: Vec<_> = fontdb
+        .faces()
+        .filter_map(|face| Some((face.id, face.families.first()?.0.clone(), face.monospaced)))
+        .collect();
+    let default_family = faces.first().map(|(_, family, _)| family.clone());
+
+    // Each generic family goes to the first font of that style, and keeps its default otherwise.
+    let mut assigned = Vec::new();
+    for (id, family, monospaced) in faces {
+        let generics = fontdb
+            .with_face_data(id, |data, index| generic_families(data, index, monospaced))
+            .unwrap_or_default();
+        for generic in generics {
+            if assigned.contains(&generic) {
+                continue;
+            }
+            assigned.push(generic);
+            match generic {
+                GenericFamily::Serif => fontdb.set_serif_family(family.clone()),
+                GenericFamily::SansSerif => fontdb.set_sans_serif_family(family.clone()),
+                GenericFamily::Monospace => fontdb.set_monospace_family(family.clone()),
+                GenericFamily::Cursive => fontdb.set_cursive_family(family.clone()),
+                GenericFamily::Fantasy => fontdb.set_fantasy_family(family.clone()),
+            }
+        }
+    }
+    if let Some(family) = default_family {
+        // Families that match no supplied font fall back to the first one, not to `serif`.
+        let select_font = FontResolver::default_font_selector();
+        let fallback = family.clone();
+        options.font_resolver.select_font = Box::new(move |font, fontdb| {
+            select_font(font, fontdb).or_else(|| {
+                fontdb.query(&Query {
+                    families: &[Family::Name(&fallback)],
+                    weight: Weight(font.weight()),
+                    ..Query::default()
+                })
+            })
+        });
+        options.font_family = family;
+    }
+    options
+}
+
+/// CSS generic font families.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum GenericFamily {
+    Serif,
+    SansSerif,
+    Monospace,
+    Cursive,
+    Fantasy,
+}
+
+/// Generic families a font face covers: `monospace` for fixed-pitch faces, and the style given
+/// by the OS/2 family class, or by the PANOSE classification when the class is unset.
+fn generic_families(data: &[u8], index: u32, monospaced: bool) -> Vec<GenericFamily> {
+    let mut families = Vec::new();
+    if monospaced {
+        families.push(GenericFamily::Monospace);
+    }
+    let Some(os2) = RawFace::parse(data, index)
+        .ok()
+        .and_then(|face| face.table(Tag::from_bytes(b"OS/2")))
+    else {
+        return families;
+    };
+    // High byte of `sFamilyClass`, then the first two PANOSE digits.
+    let class = os2.get(30).copied().unwrap_or(0);
+    let panose = os2.get(32..34).unwrap_or(&[0, 0]);
+    let style = match (class, panose) {
+        (1..=5 | 7, _) | (0, [2, 2..=10]) => Some(GenericFamily::Serif),
+        (8, _) | (0, [2, 11..=13]) => Some(GenericFamily::SansSerif),
+        (9, _) | (0, [4, _]) => Some(GenericFamily::Fantasy),
+        (10, _) | (0, [3, _]) => Some(GenericFamily::Cursive),
+        _ => None,
+    };
+    families.extend(style);
+    families
+}
+
+/// Element referenced by a `<use>` element, if any.
+fn use_target<'a, 'input>(
+    node: XmlNode<'a, 'input>,
+    ids: &HashMap<&str, XmlNode<'a, 'input>>,
+) -> Option<XmlNode<'a, 'input>> {
+    if node.tag_name().name() != "use" {
+        return None;
+    }
+    let href = node
+        .attribute(("http://www.w3.org/1999/xlink", "href"))
+        .or_else(|| node.attribute("href"))?;
+    ids.get(href.strip_prefix('#')?).copied()
+}
+
+/// Child elements of a node, followed by the target of a `<use>` element.
+fn dependencies<'a, 'input>(
+    node: XmlNode<'a, 'input>,
+    ids: &HashMap<&str, XmlNode<'a, 'input>>,
+) -> Vec<XmlNode<'a, 'input>> {
+    node.children()
+        .filter(XmlNode::is_element)
+        .chain(use_target(node, ids))
+        .collect()
+}
+
+/// Number of elements in the document once every `<use>` is expanded into a copy of its target.
+///
+/// Recursive references count as empty, since usvg drops them. The walk uses an explicit stack
+/// so that long reference chains can't overflow the call stack.
+fn count_expanded_elements(doc: &Document) -> usize {
+    let ids: HashMap<&str, XmlNode> = doc
+        .descendants()
+        .filter_map(|node| Some((node.attribute("id")?, node)))
+        .collect();
+    // Elements that are still being expanded hold 0 until all their dependencies are counted.
+    let mut counts: HashMap<NodeId, usize> = HashMap::new();
+    let mut stack = vec![(doc.root_element(), false)];
+    while let Some((node, counted)) = stack.pop() {
+        if counted {
+            let count = dependencies(node, &ids)
+                .iter()
+                .fold(1usize, |sum, dependency| {
+                    sum.saturating_add(counts[&dependency.id()])
+                });
+            counts.insert(node.id(), count);
+        } else if let Entry::Vacant(entry) = counts.entry(node.id()) {
+            entry.insert(0);
+            stack.push((node, true));
+            stack.extend(
+                dependencies(node, &ids)
+                    .into_iter()
+                    .filter(|dependency| !counts.contains_key(&dependency.id()))
+                    .map(|dependency| (dependency, false)),
+            );
+        }
+    }
+    counts[&doc.root_element().id()]
+}
 
-    let original_svg_size = tree.size().to_int_size();
-    let original_svg_size_f32 = original_svg_size.to_size();
-    let fit_to_size = IntSize::from_wh(width, height).map(|s| original_svg_size.scale_to(s));
-    let scaled_svg_size = match fit_to_size {
-        Some(v) => v.to_size(),
-        None => original_svg_size.to_size(),
+/// Parses an SVG, rejecting documents whose expanded element count exceeds the decode limits
+/// before usvg builds the tree.
+pub(crate) fn parse_svg(svg: &[u8], options: &Options) -> Result<Tree, WasmImageError> {
+    check_svg_bytes(svg.len())?;
+    let text = std::str::from_utf8(svg).map_err(|_| resvg::usvg::Error::NotAnUtf8Str)?;
+    let xml_options = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
     };
+    let doc = Document::parse_with_options(text, xml_options)
+        .map_err(resvg::usvg::Error::ParsingFailed)?;
+    check_svg_nodes(count_expanded_elements(&doc))?;
+    Ok(Tree::from_xmltree(&doc, options)?)
+}
+
+pub(crate) fn svg_to_png(
+    svg: &[u8],
+    svg_settings: &SvgSettings,
+) -> Result<Vec<u8>, WasmImageError> {
+    check_settings(svg_settings)?;
+    let background = svg_settings
+        .background
+        .as_deref()
+        .map(parse_color)
+        .transpose()?;
+    let options = svg_options(svg_settings);
+
+    let tree = parse_svg(svg, &options)?;
 
+    let intrinsic_size = tree.size();
+    let size = output_size(intrinsic_size, svg_settings)?;
+    let padding = svg_settings.padding;
+
+    // Both axes are scaled independently so the drawing fills the pixmap exactly,
+    // without letterboxing from rounding.
     let transform = Transform::from_scale(
-        scaled_svg_size.width() / original_svg_size_f32.width(),
-        scaled_svg_size.height() / original_svg_size_f32.height(),
-    );
+        size.width() as f32 / intrinsic_size.width(),
+        size.height() as f32 / intrinsic_size.height(),
+    )
+    .post_translate(padding as f32, padding as f32);
 
+    let padded = |length: u32| length.checked_add(padding.checked_mul(2)?);
+    let (width, height) = padded(size.width())
+        .zip(padded(size.height()))
+        .ok_or(WasmImageError::SvgError(resvg::usvg::Error::InvalidSize))?;
+    check_dimensions(width, height, ErrorStage::Rasterize)?;
     let mut pixmap = Pixmap::new(width, height)
         .ok_or(WasmImageError::SvgError(resvg::usvg::Error::InvalidSize))?;
+    if let Some(background) = background {
+        pixmap.fill(background);
+    }
 
     render(&tree, transform, &mut pixmap.as_mut());
 
@@ -46,15 +307,189 @@ pub(crate) fn svg_to_png(svg: &[u8], svg_settings: SvgSettings) -> Result<Vec<u8
 mod tests {
     use super::*;
     use image::{GenericImageView, ImageFormat};
+    use std::fmt::Write;
+
+    fn rasterized_dimensions(svg: &[u8], settings: &SvgSettings) -> (u32, u32) {
+        let result = svg_to_png(svg, settings).unwrap();
+        image::load_from_memory_with_format(&result, ImageFormat::Png)
+            .unwrap()
+            .dimensions()
+    }
 
     #[test]
     fn test_rasterize_svg() {
         let svg = include_bytes!("../../assets/test.svg");
 
-        let result = svg_to_png(svg, SvgSettings::default()).unwrap();
+        assert_eq!(
+            rasterized_dimensions(svg, &SvgSettings::default()),
+            (256, 434)
+        );
+    }
+
+    #[test]
+    fn test_svg_fit_modes() {
+        let svg = include_bytes!("../../assets/test.svg");
+        let settings = |fit, width, height| SvgSettings {
+            fit,
+            width,
+            height,
+            ..SvgSettings::default()
+        };
+
+        assert_eq!(
+            rasterized_dimensions(svg, &settings(None, Some(128), None)),
+            (128, 217)
+        );
+        assert_eq!(
+            rasterized_dimensions(svg, &settings(Some(SvgFit::Height), None, Some(868))),
+            (512, 868)
+        );
+        assert_eq!(
+            rasterized_dimensions(svg, &settings(None, Some(100), Some(100))),
+            (59, 100)
+        );
+        assert_eq!(
+            rasterized_dimensions(
+                svg,
+                &SvgSettings {
+                    scale: 0.5,
+                    ..SvgSettings::default()
+                }
+            ),
+            (128, 217)
+        );
+        assert!(svg_to_png(svg, &settings(Some(SvgFit::Width), None, Some(10))).is_err());
+    }
+
+    #[test]
+    fn test_svg_background_and_padding() {
+        let svg = br##"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10">
+            <rect width="10" height="10" fill="#00f"/></svg>"##;
+        let settings = SvgSettings {
+            background: Some("#ff000088".to_string()),
+            padding: 5,
+            ..SvgSettings::default()
+        };
+
+        let png = svg_to_png(svg, &settings).unwrap();
+        let img = image::load_from_memory_with_format(&png, ImageFormat::Png)
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(img.dimensions(), (20, 20));
+        assert_eq!(img.get_pixel(0, 0).0, [255, 0, 0, 136]);
+        assert_eq!(img.get_pixel(10, 10).0, [0, 0, 255, 255]);
+
+        assert!(parse_color("red").is_err());
+        assert!(parse_color("#12345").is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_scale_and_dpi() {
+        let svg = include_bytes!("../../assets/test.svg");
+        for (scale, dpi) in [
+            (0.0, 96.0),
+            (f32::NAN, 96.0),
+            (1.0, 0.0),
+            (1.0, f32::INFINITY),
+        ] {
+            let settings = SvgSettings {
+                fit: Some(SvgFit::Width),
+                width: Some(100),
+                scale,
+                dpi,
+                ..SvgSettings::default()
+            };
+            assert!(matches!(
+                svg_to_png(svg, &settings),
+                Err(WasmImageError::InvalidSettings(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn classifies_generic_families() {
+        // A font holding only an OS/2 table with the given family class and PANOSE digits.
+        let font = |class: u8, panose: [u8; 2]| {
+            let mut font = vec![0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+            font.extend(b"OS/2");
+            font.extend([0; 4]);
+            font.extend(28u32.to_be_bytes());
+            font.extend(42u32.to_be_bytes());
+            let mut os2 = [0; 42];
+            os2[30] = class;
+            os2[32..34].copy_from_slice(&panose);
+            font.extend(os2);
+            font
+        };
 
-        let img = image::load_from_memory_with_format(&result, ImageFormat::Png).unwrap();
+        assert_eq!(
+            generic_families(&font(8, [0, 0]), 0, true),
+            [GenericFamily::Monospace, GenericFamily::SansSerif]
+        );
+        assert_eq!(
+            generic_families(&font(0, [2, 2]), 0, false),
+            [GenericFamily::Serif]
+        );
+        assert_eq!(
+            generic_families(&font(10, [2, 11]), 0, false),
+            [GenericFamily::Cursive]
+        );
+        assert!(generic_families(&font(0, [0, 0]), 0, false).is_empty());
+    }
 
-        assert_eq!(img.dimensions(), (100, 100));
+    #[test]
+    fn test_svg_physical_units_use_dpi() {
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="1in" height="0.5in"/>"#;
+
+        assert_eq!(
+            rasterized_dimensions(
+                svg,
+                &SvgSettings {
+                    dpi: 300.0,
+                    ..SvgSettings::default()
+                }
+            ),
+            (300, 150)
+        );
+    }
+
+    #[test]
+    fn rejects_oversized_output() {
+        let svg = include_bytes!("../../assets/test.svg");
+        let settings = SvgSettings {
+            width: Some(60_000),
+            ..SvgSettings::default()
+        };
+        assert!(matches!(
+            svg_to_png(svg, &settings),
+            Err(WasmImageError::LimitExceeded(..))
+        ));
+    }
+
+    #[test]
+    fn rejects_use_bombs_before_building_the_tree() {
+        let mut svg = String::from(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="10" height="10"><rect id="l0" width="1" height="1"/>"#,
+        );
+        for level in 1..=20 {
+            let previous = level - 1;
+            write!(
+                svg,
+                r##"<g id="l{level}"><use xlink:href="#l{previous}"/><use href="#l{previous}"/></g>"##
+            )
+            .unwrap();
+        }
+        svg.push_str("</svg>");
+        assert!(matches!(
+            parse_svg(svg.as_bytes(), &Options::default()),
+            Err(WasmImageError::LimitExceeded(..))
+        ));
+    }
+
+    #[test]
+    fn counts_recursive_uses_once() {
+        let svg = br##"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10"><g id="a"><use href="#a"/></g></svg>"##;
+        let doc = Document::parse(std::str::from_utf8(svg).unwrap()).unwrap();
+        assert_eq!(count_expanded_elements(&doc), 3);
     }
 }
@@ -0,0 +1,180 @@
+//! Vector and document outputs: SVG wrappers around raster images and PDF pages.
+
+use std::io::Cursor;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::{metadata::Orientation, DynamicImage, ImageDecoder, ImageFormat};
+
+use super::pdf::{write_pdf, PdfImage, PdfPage};
+use super::settings::Settings;
+use crate::error::WasmImageError;
+use crate::limits::decoder;
+use crate::load::SourceImage;
+
+/// Formats that browsers display inside an SVG `<image>`, so the original file can be
+/// embedded without re-encoding.
+fn embeddable_format(format: Option<ImageFormat>) -> Option<ImageFormat> {
+    format.filter(|f| {
+        matches!(
+            f,
+            ImageFormat::Png | ImageFormat::Jpeg | ImageFormat::Gif | ImageFormat::WebP
+        )
+    })
+}
+
+/// EXIF orientation of a raster file, `NoTransforms` if it has none or can't be read.
+fn file_orientation(file: &[u8], format: Option<ImageFormat>) -> Orientation {
+    format
+        .and_then(|format| decoder(file, format).ok())
+        .and_then(|mut decoder| decoder.orientation().ok())
+        .unwrap_or(Orientation::NoTransforms)
+}
+
+fn encode_png(img: &DynamicImage) -> Result<Vec<u8>, WasmImageError> {
+    let mut output = Vec::new();
+    img.write_to(&mut Cursor::new(&mut output), ImageFormat::Png)?;
+    Ok(output)
+}
+
+/// Wraps an image in an SVG document of the same size, embedding it as a data URI.
+/// Browser-supported files are embedded as is; everything else, and files with an EXIF
+/// orientation, is re-encoded upright as PNG. Viewers disagree on whether the orientation
+/// applies inside an SVG, so the `<image>` size could otherwise stretch the picture.
+/// SVG input is returned unchanged, camera RAW files are decoded with the given settings.
+pub(crate) fn write_svg(
+    img: &SourceImage,
+    file: &[u8],
+    source_type: Option<ImageFormat>,
+    settings: Option<&Settings>,
+) -> Result<Vec<u8>, WasmImageError> {
+    if let SourceImage::Svg(svg) = img {
+        return Ok(svg.to_vec());
+    }
+    let mut img = img.rasterize(settings)?;
+
+    let orientation = file_orientation(file, source_type);
+    let (mime_type, data) = match embeddable_format(source_type) {
+        Some(format) if orientation == Orientation::NoTransforms => {
+            (format.to_mime_type(), STANDARD.encode(file))
+        }
+        _ => {
+            img.apply_orientation(orientation);
+            ("image/png", STANDARD.encode(encode_png(&img)?))
+        }
+    };
+    let (width, height) = (img.width(), img.height());
+
+    Ok(format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" xmlns:xlink=\"http://www.w3.org/1999/xlink\" \
+         width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\">\
+         <image width=\"{width}\" height=\"{height}\" xlink:href=\"data:{mime_type};base64,{data}\"/>\
+         </svg>"
+    )
+    .into_bytes())
+}
+
+/// Builds a PDF image, keeping JPEG files compressed as they are.
+pub(crate) fn pdf_image(
+    img: &DynamicImage,
+    file: &[u8],
+    source_type: Option<ImageFormat>,
+) -> Result<PdfImage, WasmImageError> {
+    if source_type == Some(ImageFormat::Jpeg) {
+        if let Some(image) = PdfImage::from_jpeg(file, img.width(), img.height()) {
+            return Ok(image);
+        }
+    }
+    PdfImage::from_image(img)
+}
+
+/// Writes a single-page PDF with the page sized to the image at 96 DPI. SVG and camera RAW
+/// input are rasterized with the given settings first.
+pub(crate) fn write_single_page_pdf(
+    img: &SourceImage,
+    file: &[u8],
+    source_type: Option<ImageFormat>,
+    settings: Option<&Settings>,
+) -> Result<Vec<u8>, WasmImageError> {
+    let image = if let SourceImage::Raster(raster) = img {
+        pdf_image(raster, file, source_type)?
+    } else {
+        PdfImage::from_image(&img.rasterize(settings)?)?
+    };
+    Ok(write_pdf(&[PdfPage::fitted(image)]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_raster_in_svg() {
+        let jpeg = include_bytes!("../../assets/test.jpeg");
+        let img = image::load_from_memory(jpeg).unwrap();
+        let (width, height) = (img.width(), img.height());
+
+        let svg = write_svg(
+            &SourceImage::Raster(img),
+            jpeg,
+            Some(ImageFormat::Jpeg),
+            None,
+        )
+        .unwrap();
+
+        let tree = resvg::usvg::Tree::from_data(&svg, &resvg::usvg::Options::default()).unwrap();
+        assert_eq!(
+            (tree.size().width() as u32, tree.size().height() as u32),
+            (width, height)
+        );
+        assert!(String::from_utf8(svg)
+            .unwrap()
+            .contains("data:image/jpeg;base64,/9j/"));
+    }
+
+    #[test]
+    fn stores_rotated_jpegs_upright() {
+        use little_exif::{exif_tag::ExifTag, filetype::FileExtension, metadata::Metadata};
+
+        let mut jpeg = include_bytes!("../../assets/test.jpeg").to_vec();
+        let mut exif = Metadata::new_from_vec(&jpeg, FileExtension::JPEG).unwrap();
+        // Rotated 90° clockwise.
+        exif.set_tag(ExifTag::Orientation(vec![6]));
+        exif.write_to_vec(&mut jpeg, FileExtension::JPEG).unwrap();
+        let img = image::load_from_memory(&jpeg).unwrap();
+        let (width, height) = (img.width(), img.height());
+
+        let svg = write_svg(
+            &SourceImage::Raster(img),
+            &jpeg,
+            Some(ImageFormat::Jpeg),
+            None,
+        )
+        .unwrap();
+
+        let tree = resvg::usvg::Tree::from_data(&svg, &resvg::usvg::Options::default()).unwrap();
+        assert_eq!(
+            (tree.size().width() as u32, tree.size().height() as u32),
+            (height, width)
+        );
+        assert!(String::from_utf8(svg)
+            .unwrap()
+            .contains("data:image/png;base64,"));
+    }
+
+    #[test]
+    fn decompresses_svgz() {
+        use std::io::Write;
+
+        let svg = br#"<svg xmlns="http://www.w3.org/2000/svg" width="6" height="4"/>"#;
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(svg).unwrap();
+        let svgz = encoder.finish().unwrap();
+
+        let source_type =
+            crate::source_type::SourceType::from_mime_type("image/svg+xml-compressed");
+        let img = crate::load::load_image(&svgz, source_type.as_ref()).unwrap();
+        assert_eq!(write_svg(&img, &svgz, None, None).unwrap(), svg);
+        let raster = img.rasterize(None).unwrap();
+        assert_eq!((raster.width(), raster.height()), (6, 4));
+    }
+}
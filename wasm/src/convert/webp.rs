@@ -0,0 +1,234 @@
+//! WebP output. `image` only encodes lossless WebP, lossy encoding goes through libwebp and is
+//! enabled by the `webp-lossy` feature.
+
+use std::io::Cursor;
+
+use image::{codecs::webp::WebPEncoder, DynamicImage, ImageEncoder, ImageFormat};
+
+use super::settings::{Settings, WebPSettings};
+use crate::error::WasmImageError;
+use crate::source_type::SourceType;
+
+/// Whether lossy WebP encoding is compiled in.
+pub(crate) const LOSSY_SUPPORTED: bool = cfg!(feature = "webp-lossy");
+
+/// Whether a WebP file stores a lossy (`VP8 `) bitstream rather than a lossless (`VP8L`) one.
+pub(crate) fn is_lossy_webp(file: &[u8]) -> bool {
+    if file.len() < 12 || &file[..4] != b"RIFF" || &file[8..12] != b"WEBP" {
+        return false;
+    }
+    let mut pos = 12;
+    while pos + 8 <= file.len() {
+        let fourcc = &file[pos..pos + 4];
+        if fourcc == b"VP8 " {
+            return true;
+        }
+        if fourcc == b"VP8L" {
+            return false;
+        }
+        let len = u32::from_le_bytes([file[pos + 4], file[pos + 5], file[pos + 6], file[pos + 7]])
+            as usize;
+        // Stops on lengths that run past the address space, which wrap on 32-bit targets.
+        let Some(next) = len
+            .checked_add(8 + (len & 1))
+            .and_then(|size| pos.checked_add(size))
+        else {
+            return false;
+        };
+        pos = next;
+    }
+    false
+}
+
+/// Whether the source was lossily compressed, so re-encoding it losslessly would only grow it.
+fn is_lossy_source(file: &[u8], src_type: &str) -> bool {
+    match SourceType::from_mime_type(src_type) {
+        Some(SourceType::Raster(ImageFormat::Jpeg | ImageFormat::Avif)) => true,
+        Some(SourceType::Raster(ImageFormat::WebP)) => is_lossy_webp(file),
+        #[cfg(feature = "heic")]
+        Some(SourceType::Heif) => true,
+        _ => false,
+    }
+}
+
+/// Resolves the WebP settings for a conversion, filling in `lossy` from the source when it
+/// wasn't set explicitly.
+pub(crate) fn resolve_settings(
+    settings: Option<&Settings>,
+    file: &[u8],
+    src_type: &str,
+) -> WebPSettings {
+    let mut webp = match settings {
+        #[cfg(not(feature = "wasm"))]
+        Some(Settings::WebP(webp)) => webp.clone(),
+        _ => WebPSettings::default(),
+    };
+    webp.lossy = Some(
+        webp.lossy
+            .unwrap_or_else(|| LOSSY_SUPPORTED && is_lossy_source(file, src_type)),
+    );
+    webp
+}
+
+/// Settings for re-encoding a WebP file, e.g. after a metadata edit, keeping lossy files lossy.
+/// Returns `None` when the file should be re-encoded losslessly.
+pub(crate) fn reencode_settings(file: &[u8]) -> Option<WebPSettings> {
+    (LOSSY_SUPPORTED && is_lossy_webp(file)).then(|| WebPSettings {
+        lossy: Some(true),
+        ..WebPSettings::default()
+    })
+}
+
+#[cfg(feature = "webp-lossy")]
+fn encode_lossy(img: &DynamicImage, settings: &WebPSettings) -> Result<Vec<u8>, WasmImageError> {
+    let encoding_error = |e: &dyn std::fmt::Debug| WasmImageError::EncodingError(format!("{e:?}"));
+
+    let mut config =
+        webp::WebPConfig::new().map_err(|()| encoding_error(&"Invalid WebP config"))?;
+    config.lossless = 0;
+    config.quality = settings.quality.clamp(0.0, 100.0);
+    config.method = i32::from(settings.method.min(6));
+    config.alpha_compression = 1;
+
+    let output = if img.color().has_alpha() {
+        let rgba = img.to_rgba8();
+        webp::Encoder::from_rgba(&rgba, img.width(), img.height()).encode_advanced(&config)
+    } else {
+        let rgb = img.to_rgb8();
+        webp::Encoder::from_rgb(&rgb, img.width(), img.height()).encode_advanced(&config)
+    };
+    Ok(output.map_err(|e| encoding_error(&e))?.to_vec())
+}
+
+#[cfg(not(feature = "webp-lossy"))]
+fn encode_lossy(_img: &DynamicImage, _settings: &WebPSettings) -> Result<Vec<u8>, WasmImageError> {
+    Err(WasmImageError::UnsupportedTargetFormat(
+        ImageFormat::WebP.to_mime_type().to_string(),
+        "Lossy WebP encoding requires the `webp-lossy` feature".to_string(),
+    ))
+}
+
+/// Encodes an image as WebP, lossy if `settings.lossy` is set, embedding the EXIF and ICC
+/// profile if given.
+pub(crate) fn encode_webp(
+    img: &DynamicImage,
+    settings: &WebPSettings,
+    exif: Option<Vec<u8>>,
+    icc: Option<Vec<u8>>,
+) -> Result<Vec<u8>, WasmImageError> {
+    if settings.lossy != Some(true) {
+        let mut output = Vec::new();
+        let mut encoder = WebPEncoder::new_lossless(Cursor::new(&mut output));
+        if let Some(exif) = exif {
+            let _ = encoder.set_exif_metadata(exif);
+        }
+        if let Some(icc) = icc {
+            let _ = encoder.set_icc_profile(icc);
+        }
+        encoder.write_image(
+            img.as_bytes(),
+            img.width(),
+            img.height(),
+            img.color().into(),
+        )?;
+        return Ok(output);
+    }
+
+    // libwebp's simple encoder has no metadata support, the chunks are added afterwards.
+    let mut output = encode_lossy(img, settings)?;
+    let has_alpha = img.color().has_alpha();
+    for (chunk, payload) in [(*b"ICCP", icc), (*b"EXIF", exif)] {
+        if let Some(payload) = payload {
+            output = crate::metadata::container::set_webp_chunk(
+                &output,
+                chunk,
+                &payload,
+                img.width(),
+                img.height(),
+                has_alpha,
+            )?;
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    /// A noisy image, which lossless compression can't shrink much.
+    fn noise() -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_fn(64, 64, |x, y| {
+            let v = (x * 31 + y * 17) ^ (x * y * 7);
+            Rgb([v as u8, (v >> 3) as u8, (x * 4 + y) as u8])
+        }))
+    }
+
+    #[test]
+    fn resolves_lossy_from_source() {
+        let jpeg = include_bytes!("../../assets/test.jpeg");
+        let settings = resolve_settings(None, jpeg, "image/jpeg");
+        assert_eq!(settings.lossy, Some(LOSSY_SUPPORTED));
+
+        let png = resolve_settings(None, b"", "image/png");
+        assert_eq!(png.lossy, Some(false));
+    }
+
+    #[test]
+    #[cfg(not(feature = "wasm"))]
+    fn keeps_explicit_lossy_setting() {
+        let jpeg = include_bytes!("../../assets/test.jpeg");
+        let explicit = Settings::WebP(WebPSettings {
+            lossy: Some(false),
+            ..WebPSettings::default()
+        });
+        let settings = resolve_settings(Some(&explicit), jpeg, "image/jpeg");
+        assert_eq!(settings.lossy, Some(false));
+    }
+
+    #[test]
+    fn stops_on_oversized_chunks() {
+        let mut file = b"RIFF\0\0\0\0WEBPXMP \xff\xff\xff\xff".to_vec();
+        file.extend_from_slice(b"VP8 \0\0\0\0");
+        assert!(!is_lossy_webp(&file));
+    }
+
+    #[test]
+    fn encodes_lossless_with_metadata() {
+        let settings = WebPSettings::default();
+        let output = encode_webp(&noise(), &settings, None, Some(vec![1, 2, 3])).unwrap();
+        assert!(!is_lossy_webp(&output));
+        assert!(output.windows(4).any(|w| w == b"ICCP"));
+    }
+
+    #[cfg(feature = "webp-lossy")]
+    #[test]
+    fn encodes_lossy_with_metadata() {
+        let img = noise();
+        let lossless = encode_webp(&img, &WebPSettings::default(), None, None).unwrap();
+        let settings = WebPSettings {
+            lossy: Some(true),
+            quality: 50.0,
+            method: 6,
+        };
+        let lossy = encode_webp(&img, &settings, Some(b"II*\0".to_vec()), None).unwrap();
+        assert!(is_lossy_webp(&lossy));
+        assert!(lossy.len() < lossless.len());
+        assert!(lossy.windows(4).any(|w| w == b"EXIF"));
+
+        let decoded = image::load_from_memory_with_format(&lossy, ImageFormat::WebP).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (64, 64));
+        assert!(reencode_settings(&lossy).is_some());
+    }
+
+    #[cfg(not(feature = "webp-lossy"))]
+    #[test]
+    fn rejects_lossy_without_feature() {
+        let settings = WebPSettings {
+            lossy: Some(true),
+            ..WebPSettings::default()
+        };
+        assert!(encode_webp(&noise(), &settings, None, None).is_err());
+    }
+}
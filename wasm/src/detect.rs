@@ -0,0 +1,313 @@
+//! Format detection from the file content. The MIME type given by the caller usually comes from
+//! the file extension, so it is checked against the magic bytes before decoding.
+
+use image::ImageFormat;
+
+use crate::error::WasmImageError;
+use crate::load::GZIP_MAGIC;
+use crate::source_type::SourceType;
+use crate::tiff::TiffReader;
+
+#[cfg(feature = "wasm")]
+use {
+    crate::input::{ImageInput, Input},
+    wasm_bindgen::prelude::*,
+};
+
+/// How far into the file an `<svg` root element is looked for, past the XML prolog and comments.
+const SVG_SNIFF_LEN: usize = 4096;
+
+const TAG_DNG_VERSION: u16 = 0xc612;
+
+/// How much of the file is read to detect its format.
+#[cfg(feature = "wasm")]
+const DETECT_READ_LEN: usize = 64 * 1024;
+
+/// ISOBMFF brands of HEIC/HEIF files.
+const HEIF_BRANDS: [&[u8]; 8] = [
+    b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1", b"msf1",
+];
+
+/// The major and compatible brands of an ISOBMFF `ftyp` box.
+fn ftyp_brands(file: &[u8]) -> Option<impl Iterator<Item = &[u8]>> {
+    if file.get(4..8)? != b"ftyp" {
+        return None;
+    }
+    let size = u32::from_be_bytes(file.get(..4)?.try_into().ok()?) as usize;
+    let ftyp = file.get(8..size.min(file.len()))?;
+    // Major brand, minor version, then the compatible brands.
+    Some(
+        ftyp.chunks_exact(4)
+            .enumerate()
+            .filter(|(i, _)| *i != 1)
+            .map(|(_, brand)| brand),
+    )
+}
+
+fn sniff_ftyp(file: &[u8]) -> Option<&'static str> {
+    let brands: Vec<&[u8]> = ftyp_brands(file)?.collect();
+    // `mif1` is shared by AVIF and HEIF, the AV1 brands decide.
+    if brands
+        .iter()
+        .any(|brand| matches!(*brand, b"avif" | b"avis"))
+    {
+        return Some("image/avif");
+    }
+    if brands.iter().any(|brand| HEIF_BRANDS.contains(brand)) {
+        return Some("image/heic");
+    }
+    None
+}
+
+fn is_svg(file: &[u8]) -> bool {
+    let file = file.strip_prefix(b"\xef\xbb\xbf").unwrap_or(file);
+    let start = file.iter().position(|b| !b.is_ascii_whitespace());
+    let Some(head) = start.map(|start| &file[start..file.len().min(start + SVG_SNIFF_LEN)]) else {
+        return false;
+    };
+    head.starts_with(b"<") && head.windows(4).any(|w| w == b"<svg")
+}
+
+/// Whether a gzip stream is SVGZ. Only the beginning is decompressed.
+fn is_svgz(file: &[u8]) -> bool {
+    use std::io::Read;
+
+    let mut head = Vec::new();
+    let limit = SVG_SNIFF_LEN as u64 * 2;
+    // A truncated or corrupt stream still yields the bytes decoded so far.
+    let _ = flate2::read::GzDecoder::new(file)
+        .take(limit)
+        .read_to_end(&mut head);
+    is_svg(&head)
+}
+
+/// Tells plain TIFF apart from the TIFF-based camera RAW formats that can be recognized.
+fn sniff_tiff(file: &[u8]) -> &'static str {
+    if file.get(8..10) == Some(b"CR") {
+        return "image/x-canon-cr2";
+    }
+    let is_dng = TiffReader::new(file)
+        .and_then(|reader| reader.chain())
+        .and_then(|ifds| ifds.first().map(|ifd| ifd.entry(TAG_DNG_VERSION).is_some()))
+        .unwrap_or(false);
+    if is_dng {
+        "image/x-adobe-dng"
+    } else {
+        "image/tiff"
+    }
+}
+
+/// Detects the MIME type of a file from its content, whether or not this build can decode it.
+pub(crate) fn sniff_mime_type(file: &[u8]) -> Option<&'static str> {
+    if let Some(mime_type) = sniff_ftyp(file) {
+        return Some(mime_type);
+    }
+    if file.starts_with(&[0xff, 0x0a]) || file.starts_with(b"\0\0\0\x0cJXL \r\n\x87\n") {
+        return Some("image/jxl");
+    }
+    if file.starts_with(&GZIP_MAGIC) {
+        return is_svgz(file).then_some("image/svg+xml");
+    }
+    if file.starts_with(b"#?RGBE") {
+        return Some("image/vnd.radiance");
+    }
+    match image::guess_format(file) {
+        Ok(ImageFormat::Tiff) => Some(sniff_tiff(file)),
+        // `image` has no MIME type for Farbfeld.
+        Ok(ImageFormat::Farbfeld) => Some("image/farbfeld"),
+        Ok(format) => Some(format.to_mime_type()),
+        Err(_) => is_svg(file).then_some("image/svg+xml"),
+    }
+}
+
+/// Whether a file detected as `detected` can be decoded as `stated`.
+fn is_compatible(stated: &SourceType, detected: &SourceType) -> bool {
+    match (stated, detected) {
+        (SourceType::Raster(stated), SourceType::Raster(detected)) => stated == detected,
+        (SourceType::Svg, SourceType::Svg) => true,
+        #[cfg(feature = "heic")]
+        (SourceType::Heif, SourceType::Heif) => true,
+        #[cfg(feature = "jxl")]
+        (SourceType::Jxl, SourceType::Jxl) => true,
+        // Most RAW formats can't be told apart from TIFF by their header.
+        #[cfg(feature = "raw")]
+        (SourceType::Raw, SourceType::Raw | SourceType::Raster(ImageFormat::Tiff))
+        | (SourceType::Raster(ImageFormat::Tiff), SourceType::Raw) => true,
+        _ => false,
+    }
+}
+
+/// Checks the MIME type given for a file against its content. Returns the given type if it
+/// matches or the content isn't recognized, and the detected type if the file was mislabeled.
+/// # Errors
+/// Returns [`WasmImageError::FormatMismatch`] if the file is mislabeled and its actual format
+/// can't be decoded by this build.
+pub(crate) fn resolve_mime_type<'a>(
+    file: &[u8],
+    src_type: &'a str,
+) -> Result<&'a str, WasmImageError> {
+    let Some(detected_type) = sniff_mime_type(file) else {
+        return Ok(src_type);
+    };
+    let Some(stated) = SourceType::from_mime_type(src_type) else {
+        return Ok(detected_type);
+    };
+    match SourceType::from_mime_type(detected_type) {
+        Some(detected) if is_compatible(&stated, &detected) => Ok(src_type),
+        Some(_) => Ok(detected_type),
+        // Without the `raw` feature, RAW files are decoded as TIFF, which yields their first IFD.
+        None if matches!(stated, SourceType::Raster(ImageFormat::Tiff))
+            && image::guess_format(file).is_ok_and(|format| format == ImageFormat::Tiff) =>
+        {
+            Ok(src_type)
+        }
+        None => Err(WasmImageError::FormatMismatch(
+            src_type.to_string(),
+            detected_type.to_string(),
+        )),
+    }
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen(js_name = detectFormat)]
+/// Detect the format of an image file from its content.
+/// # Arguments
+/// * `file` - The image file bytes, or a `Blob` of which only the start is read.
+/// # Returns
+/// The MIME type of the file, or `undefined` if the format isn't recognized. Formats this build
+/// can't decode (e.g. HEIC without the `heic` feature) are still reported.
+/// # Errors
+/// Returns an error if the file can't be read.
+pub fn detect_format(file: &ImageInput) -> Result<Option<String>, JsValue> {
+    let head = Input::new(file)?.read_prefix(DETECT_READ_LEN)?;
+    Ok(sniff_mime_type(&head).map(str::to_string))
+}
+
+#[cfg(not(feature = "wasm"))]
+/// Detect the format of an image file from its content.
+/// # Arguments
+/// * `file` - The image file bytes.
+/// # Returns
+/// The MIME type of the file, or `None` if the format isn't recognized. Formats this build
+/// can't decode (e.g. HEIC without the `heic` feature) are still reported.
+#[must_use]
+pub fn detect_format(file: &[u8]) -> Option<String> {
+    sniff_mime_type(file).map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_signatures() {
+        let jpeg = include_bytes!("../assets/test.jpeg");
+        assert_eq!(sniff_mime_type(jpeg), Some("image/jpeg"));
+        assert_eq!(
+            sniff_mime_type(b"farbfeld\0\0\0\x01"),
+            Some("image/farbfeld")
+        );
+        assert_eq!(sniff_mime_type(b"qoif\0\0\0\x01"), Some("image/x-qoi"));
+        assert_eq!(sniff_mime_type(b"#?RADIANCE\n"), Some("image/vnd.radiance"));
+        assert_eq!(sniff_mime_type(b"#?RGBE\n"), Some("image/vnd.radiance"));
+        assert_eq!(
+            sniff_mime_type(b"\0\0\0\x18ftypheic\0\0\0\0mif1heic"),
+            Some("image/heic")
+        );
+        assert_eq!(
+            sniff_mime_type(b"\0\0\0\x1cftypmif1\0\0\0\0mif1avifmiaf"),
+            Some("image/avif")
+        );
+        assert_eq!(sniff_mime_type(&[0xff, 0x0a, 0x00]), Some("image/jxl"));
+        assert_eq!(sniff_mime_type(b"not an image"), None);
+    }
+
+    #[test]
+    fn reads_short_ftyp_boxes() {
+        assert_eq!(sniff_mime_type(b"\0\0\0\x0cftypheic"), Some("image/heic"));
+        assert_eq!(sniff_mime_type(b"\0\0\0\x04ftyp"), None);
+    }
+
+    #[test]
+    fn detects_svg() {
+        let svg = b"\xef\xbb\xbf\n<?xml version=\"1.0\"?>\n<!-- icon -->\n<svg xmlns=\"http://www.w3.org/2000/svg\"/>";
+        assert_eq!(sniff_mime_type(svg), Some("image/svg+xml"));
+        assert_eq!(sniff_mime_type(b"<html><body/></html>"), None);
+        assert_eq!(sniff_mime_type(b"text with <svg"), None);
+    }
+
+    #[test]
+    fn detects_svgz() {
+        use std::io::Write;
+
+        let gzip = |data: &[u8]| {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        };
+        let svgz = gzip(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>");
+        assert_eq!(sniff_mime_type(&svgz), Some("image/svg+xml"));
+        assert_eq!(
+            resolve_mime_type(&svgz, "image/svg+xml").unwrap(),
+            "image/svg+xml"
+        );
+        assert_eq!(sniff_mime_type(&gzip(b"plain text")), None);
+        assert_eq!(sniff_mime_type(&svgz[..12]), None);
+    }
+
+    #[test]
+    fn resolves_mislabeled_files() {
+        let jpeg = include_bytes!("../assets/test.jpeg");
+        assert_eq!(resolve_mime_type(jpeg, "image/jpeg").unwrap(), "image/jpeg");
+        assert_eq!(resolve_mime_type(jpeg, "image/png").unwrap(), "image/jpeg");
+        assert_eq!(
+            resolve_mime_type(jpeg, "application/octet-stream").unwrap(),
+            "image/jpeg"
+        );
+        // TGA has no signature, the stated type is kept.
+        assert_eq!(
+            resolve_mime_type(b"\0\0\x02", "image/x-tga").unwrap(),
+            "image/x-tga"
+        );
+
+        let svg = b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>";
+        assert_eq!(
+            resolve_mime_type(svg, "image/png").unwrap(),
+            "image/svg+xml"
+        );
+    }
+
+    #[cfg(not(feature = "heic"))]
+    #[test]
+    fn rejects_unsupported_mislabeled_files() {
+        let heic = b"\0\0\0\x18ftypheic\0\0\0\0mif1heic";
+        assert!(matches!(
+            resolve_mime_type(heic, "image/jpeg"),
+            Err(WasmImageError::FormatMismatch(..))
+        ));
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    #[test]
+    fn converts_mislabeled_files() {
+        let jpeg = include_bytes!("../assets/test.jpeg");
+        let png = crate::convert_image(jpeg, "image/png", "image/png", &None, None).unwrap();
+        assert_eq!(detect_format(&png).as_deref(), Some("image/png"));
+    }
+
+    #[test]
+    fn keeps_stated_tiff() {
+        let mut output = std::io::Cursor::new(Vec::new());
+        let mut encoder = tiff::encoder::TiffEncoder::new(&mut output).unwrap();
+        encoder
+            .write_image::<tiff::encoder::colortype::Gray8>(1, 1, &[0])
+            .unwrap();
+        let tiff = output.into_inner();
+        assert_eq!(sniff_mime_type(&tiff), Some("image/tiff"));
+        assert_eq!(
+            resolve_mime_type(&tiff, "image/tiff").unwrap(),
+            "image/tiff"
+        );
+    }
+}
@@ -0,0 +1,568 @@
+use std::io::Cursor;
+
+use image::{imageops::FilterType, DynamicImage, ImageFormat, Rgba, RgbaImage};
+use tiff::encoder::{
+    colortype::{Gray8, RGB8, RGBA8},
+    compression::DeflateLevel,
+    Compression, Rational, TiffEncoder,
+};
+use tiff::tags::ResolutionUnit;
+
+use crate::convert::pdf::{write_pdf, PdfPage};
+use crate::convert::vector::pdf_image;
+use crate::detect::resolve_mime_type;
+use crate::error::{ErrorStage, WasmImageError};
+use crate::limits::check_dimensions;
+use crate::load::{load_image, SourceImage};
+use crate::source_type::SourceType;
+use crate::target_type::TargetType;
+
+#[cfg(feature = "wasm")]
+use {
+    crate::input::{ImageInput, Input},
+    js_sys::{Array, Uint8Array},
+    wasm_bindgen::{prelude::*, JsCast},
+};
+
+const POINTS_PER_INCH: f32 = 72.0;
+/// Page sides PDF readers accept, in points.
+const PAGE_SIDE_RANGE: std::ops::RangeInclusive<f32> = 3.0..=14_400.0;
+
+/// Page size of an assembled document. Fixed sizes are in PDF points (1/72 inch).
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[cfg_attr(feature = "wasm", tsify(from_wasm_abi, into_wasm_abi))]
+#[derive(Clone, Copy, Default, PartialEq, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageSize {
+    /// Each page takes the size of its image at `dpi`, plus the margins.
+    #[default]
+    Image,
+    A3,
+    A4,
+    A5,
+    Letter,
+    Legal,
+    Custom {
+        width: f32,
+        height: f32,
+    },
+}
+
+impl PageSize {
+    fn points(self) -> Option<(f32, f32)> {
+        match self {
+            Self::Image => None,
+            Self::A3 => Some((841.89, 1190.55)),
+            Self::A4 => Some((595.28, 841.89)),
+            Self::A5 => Some((419.53, 595.28)),
+            Self::Letter => Some((612.0, 792.0)),
+            Self::Legal => Some((612.0, 1008.0)),
+            Self::Custom { width, height } => Some((width, height)),
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[cfg_attr(feature = "wasm", tsify(from_wasm_abi, into_wasm_abi))]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageOrientation {
+    /// Landscape for images wider than high, portrait otherwise.
+    #[default]
+    Auto,
+    Portrait,
+    Landscape,
+}
+
+/// How an image is placed inside the page margins.
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[cfg_attr(feature = "wasm", tsify(from_wasm_abi, into_wasm_abi))]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PageFit {
+    /// Scale to the largest size that fits, keeping the aspect ratio.
+    #[default]
+    Contain,
+    /// Scale to cover the whole area, keeping the aspect ratio and cropping the overflow.
+    Cover,
+    /// Stretch to the area, ignoring the aspect ratio.
+    Stretch,
+    /// Keep the size at `dpi`, only shrinking images that don't fit.
+    Original,
+}
+
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[cfg_attr(feature = "wasm", tsify(from_wasm_abi, into_wasm_abi))]
+#[derive(Clone, Copy, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct DocumentSettings {
+    pub page_size: PageSize,
+    pub orientation: PageOrientation,
+    /// Margin on every side, in points.
+    pub margin: f32,
+    pub fit: PageFit,
+    /// Pixel density of the images, used to size `image` pages and `original` placement,
+    /// and the resolution of TIFF pages.
+    pub dpi: f32,
+}
+
+impl Default for DocumentSettings {
+    fn default() -> Self {
+        Self {
+            page_size: PageSize::default(),
+            orientation: PageOrientation::default(),
+            margin: 0.0,
+            fit: PageFit::default(),
+            dpi: 96.0,
+        }
+    }
+}
+
+/// Page size and image placement, in points with the origin at the bottom left.
+#[derive(Debug, PartialEq)]
+struct PageLayout {
+    width: f32,
+    height: f32,
+    /// Where the image is drawn; may extend past `area` for `cover`.
+    rect: [f32; 4],
+    /// The page minus its margins.
+    area: [f32; 4],
+}
+
+fn layout_page(
+    image_width: u32,
+    image_height: u32,
+    settings: &DocumentSettings,
+) -> Result<PageLayout, WasmImageError> {
+    let DocumentSettings {
+        margin, fit, dpi, ..
+    } = *settings;
+    if !(dpi.is_finite() && dpi > 0.0 && margin.is_finite() && margin >= 0.0) {
+        return Err(WasmImageError::InvalidSettings(
+            "DPI must be greater than 0 and margins must not be negative".to_string(),
+        ));
+    }
+
+    if let PageSize::Custom { width, height } = settings.page_size {
+        if !(PAGE_SIDE_RANGE.contains(&width) && PAGE_SIDE_RANGE.contains(&height)) {
+            return Err(WasmImageError::InvalidSettings(format!(
+                "Custom page sides must be between {} and {} points",
+                PAGE_SIDE_RANGE.start(),
+                PAGE_SIDE_RANGE.end()
+            )));
+        }
+    }
+
+    let natural = (
+        image_width as f32 * POINTS_PER_INCH / dpi,
+        image_height as f32 * POINTS_PER_INCH / dpi,
+    );
+    let Some((short, long)) = settings.page_size.points() else {
+        let area = [margin, margin, natural.0, natural.1];
+        return Ok(PageLayout {
+            width: natural.0 + 2.0 * margin,
+            height: natural.1 + 2.0 * margin,
+            rect: area,
+            area,
+        });
+    };
+
+    let landscape = match settings.orientation {
+        PageOrientation::Auto => image_width > image_height,
+        PageOrientation::Portrait => false,
+        PageOrientation::Landscape => true,
+    };
+    let (short, long) = (short.min(long), short.max(long));
+    let (width, height) = if landscape {
+        (long, short)
+    } else {
+        (short, long)
+    };
+
+    let area = [margin, margin, width - 2.0 * margin, height - 2.0 * margin];
+    if area[2] <= 0.0 || area[3] <= 0.0 {
+        return Err(WasmImageError::InvalidSettings(
+            "Margins leave no space on the page".to_string(),
+        ));
+    }
+
+    let contain = (area[2] / natural.0).min(area[3] / natural.1);
+    let (draw_width, draw_height) = match fit {
+        PageFit::Contain => (natural.0 * contain, natural.1 * contain),
+        PageFit::Cover => {
+            let cover = (area[2] / natural.0).max(area[3] / natural.1);
+            (natural.0 * cover, natural.1 * cover)
+        }
+        PageFit::Stretch => (area[2], area[3]),
+        PageFit::Original => {
+            let shrink = contain.min(1.0);
+            (natural.0 * shrink, natural.1 * shrink)
+        }
+    };
+    let rect = [
+        area[0] + (area[2] - draw_width) / 2.0,
+        area[1] + (area[3] - draw_height) / 2.0,
+        draw_width,
+        draw_height,
+    ];
+
+    Ok(PageLayout {
+        width,
+        height,
+        rect,
+        area,
+    })
+}
+
+fn load_pages(
+    files: &[&[u8]],
+    src_types: &[&str],
+) -> Result<Vec<(DynamicImage, Option<ImageFormat>)>, WasmImageError> {
+    if files.is_empty() || files.len() != src_types.len() {
+        return Err(WasmImageError::InvalidArgument(
+            "Expected at least one file and one MIME type per file".to_string(),
+        ));
+    }
+
+    files
+        .iter()
+        .zip(src_types)
+        .map(|(file, src_type)| {
+            let src_type = resolve_mime_type(file, src_type)?;
+            let source_type = SourceType::from_mime_type(src_type);
+            let loaded = load_image(file, source_type.as_ref())?;
+            // SVG and camera RAW files are decoded with the default settings.
+            let img = if let SourceImage::Raster(img) = loaded {
+                img
+            } else {
+                loaded.rasterize(None)?
+            };
+            Ok((img, ImageFormat::from_mime_type(src_type)))
+        })
+        .collect()
+}
+
+fn write_pdf_document(
+    files: &[&[u8]],
+    pages: &[(DynamicImage, Option<ImageFormat>)],
+    settings: &DocumentSettings,
+) -> Result<Vec<u8>, WasmImageError> {
+    let pages = pages
+        .iter()
+        .zip(files)
+        .map(|((img, format), file)| {
+            let image = pdf_image(img, file, *format)?;
+            let layout = layout_page(image.width(), image.height(), settings)?;
+            Ok(PdfPage {
+                width: layout.width,
+                height: layout.height,
+                image,
+                rect: layout.rect,
+                clip: (settings.fit == PageFit::Cover).then_some(layout.area),
+            })
+        })
+        .collect::<Result<Vec<_>, WasmImageError>>()?;
+
+    Ok(write_pdf(&pages))
+}
+
+/// Draws the image onto a white page canvas at `settings.dpi`.
+fn render_page(
+    img: &DynamicImage,
+    settings: &DocumentSettings,
+) -> Result<DynamicImage, WasmImageError> {
+    let layout = layout_page(img.width(), img.height(), settings)?;
+    let scale = settings.dpi / POINTS_PER_INCH;
+    let px = |pt: f32| (pt * scale).round() as i64;
+    let size = |pt: f32| u32::try_from(px(pt).max(1)).unwrap_or(u32::MAX);
+
+    // A high `dpi` or a large custom page size makes the canvas huge even for small images.
+    let (canvas_width, canvas_height) = (size(layout.width), size(layout.height));
+    check_dimensions(canvas_width, canvas_height, ErrorStage::Resize)?;
+    let [x, y, width, height] = layout.rect;
+    let (resized_width, resized_height) = (size(width), size(height));
+    check_dimensions(resized_width, resized_height, ErrorStage::Resize)?;
+
+    let mut canvas = RgbaImage::from_pixel(canvas_width, canvas_height, Rgba([255, 255, 255, 255]));
+    let resized = img.resize_exact(resized_width, resized_height, FilterType::Lanczos3);
+
+    // Points have their origin at the bottom left, pixels at the top left.
+    let (left, top) = (px(x), px(layout.height - y - height));
+    let [area_x, area_y, area_width, area_height] = layout.area;
+    let (area_left, area_top) = (px(area_x), px(layout.height - area_y - area_height));
+    let (area_right, area_bottom) = (area_left + px(area_width), area_top + px(area_height));
+
+    // Crop the overflow of `cover` placements to the area inside the margins.
+    let visible_left = left.max(area_left);
+    let visible_top = top.max(area_top);
+    let visible_right = (left + i64::from(resized.width())).min(area_right);
+    let visible_bottom = (top + i64::from(resized.height())).min(area_bottom);
+    if visible_right > visible_left && visible_bottom > visible_top {
+        let visible = resized.crop_imm(
+            (visible_left - left) as u32,
+            (visible_top - top) as u32,
+            (visible_right - visible_left) as u32,
+            (visible_bottom - visible_top) as u32,
+        );
+        image::imageops::overlay(&mut canvas, &visible.to_rgba8(), visible_left, visible_top);
+    }
+
+    Ok(DynamicImage::ImageRgb8(
+        DynamicImage::ImageRgba8(canvas).to_rgb8(),
+    ))
+}
+
+fn write_tiff_document(
+    pages: &[(DynamicImage, Option<ImageFormat>)],
+    settings: &DocumentSettings,
+) -> Result<Vec<u8>, WasmImageError> {
+    let tiff_error = |e: tiff::TiffError| WasmImageError::EncodingError(e.to_string());
+    let resolution = Rational {
+        n: settings.dpi.round().max(1.0) as u32,
+        d: 1,
+    };
+    // Pages sized to their images are written untouched, keeping transparency.
+    let keep_pixels = settings.page_size == PageSize::Image && settings.margin == 0.0;
+
+    let mut output = Vec::new();
+    let mut encoder = TiffEncoder::new(Cursor::new(&mut output))
+        .map_err(tiff_error)?
+        .with_compression(Compression::Deflate(DeflateLevel::Balanced));
+
+    for (img, _) in pages {
+        let page = if keep_pixels {
+            img.clone()
+        } else {
+            render_page(img, settings)?
+        };
+        let (width, height) = (page.width(), page.height());
+
+        macro_rules! write_page {
+            ($color:ty, $data:expr) => {{
+                let mut image = encoder
+                    .new_image::<$color>(width, height)
+                    .map_err(tiff_error)?;
+                image.resolution(ResolutionUnit::Inch, resolution.clone());
+                image.write_data(&$data).map_err(tiff_error)?;
+            }};
+        }
+        if !page.color().has_color() && !page.color().has_alpha() {
+            write_page!(Gray8, page.to_luma8().into_raw());
+        } else if page.color().has_alpha() {
+            write_page!(RGBA8, page.to_rgba8().into_raw());
+        } else {
+            write_page!(RGB8, page.to_rgb8().into_raw());
+        }
+    }
+
+    Ok(output)
+}
+
+fn assemble(
+    files: &[&[u8]],
+    src_types: &[&str],
+    target_type: &str,
+    settings: &DocumentSettings,
+) -> Result<Vec<u8>, WasmImageError> {
+    let target = TargetType::from_mime_type(target_type);
+    if !matches!(
+        target,
+        Some(TargetType::Pdf | TargetType::Raster(ImageFormat::Tiff))
+    ) {
+        return Err(WasmImageError::UnsupportedTargetFormat(
+            target_type.to_string(),
+            format!("Multi-page output is only supported for PDF and TIFF, not {target_type}"),
+        ));
+    }
+
+    let pages = load_pages(files, src_types)?;
+    match target {
+        Some(TargetType::Pdf) => write_pdf_document(files, &pages, settings),
+        _ => write_tiff_document(&pages, settings),
+    }
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen(js_name = assembleDocument)]
+#[allow(clippy::needless_pass_by_value)]
+/// Combine several images into one multi-page PDF or TIFF, one image per page.
+/// # Arguments
+/// * `files` - Array of image files (`Uint8Array` or `Blob`), in page order.
+/// * `src_types` - Array with the MIME type of each file.
+/// * `target_type` - `application/pdf` or `image/tiff`.
+/// * `cb` - A callback function to report progress.
+/// * `settings` - Page size, orientation, margins and fit.
+/// # Errors
+/// Returns an error if a file cannot be loaded or the target type is not supported.
+pub fn assemble_document(
+    files: &Array,
+    src_types: &Array,
+    target_type: &str,
+    cb: &js_sys::Function,
+    settings: Option<DocumentSettings>,
+) -> Result<Uint8Array, JsValue> {
+    crate::progress::report(cb, 10.0, "Starting document assembly");
+
+    let files: Vec<Vec<u8>> = files
+        .iter()
+        .map(|file| Input::new(file.unchecked_ref::<ImageInput>())?.read())
+        .collect::<Result<_, _>>()?;
+    let src_types: Vec<String> = src_types.iter().filter_map(|t| t.as_string()).collect();
+
+    crate::progress::report(cb, 35.0, "Loading images");
+
+    let files: Vec<&[u8]> = files.iter().map(Vec::as_slice).collect();
+    let src_types: Vec<&str> = src_types.iter().map(String::as_str).collect();
+    let output = assemble(
+        &files,
+        &src_types,
+        target_type,
+        &settings.unwrap_or_default(),
+    )
+    .map_err(JsValue::from)?;
+
+    crate::progress::report(cb, 100.0, "Document assembly complete");
+
+    Ok(Uint8Array::from(output.as_slice()))
+}
+
+#[cfg(not(feature = "wasm"))]
+/// Combine several images into one multi-page PDF or TIFF, one image per page.
+/// # Arguments
+/// * `files` - Image file bytes, in page order.
+/// * `src_types` - The MIME type of each file.
+/// * `target_type` - `application/pdf` or `image/tiff`.
+/// * `settings` - Page size, orientation, margins and fit.
+/// # Errors
+/// Returns an error if a file cannot be loaded or the target type is not supported.
+pub fn assemble_document(
+    files: &[&[u8]],
+    src_types: &[&str],
+    target_type: &str,
+    settings: &DocumentSettings,
+) -> Result<Vec<u8>, WasmImageError> {
+    assemble(files, src_types, target_type, settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lays_out_pages() {
+        let a4 = DocumentSettings {
+            page_size: PageSize::A4,
+            margin: 10.0,
+            ..DocumentSettings::default()
+        };
+        let layout = layout_page(200, 100, &a4).unwrap();
+        // Wide images turn the page to landscape.
+        assert_eq!((layout.width, layout.height), (841.89, 595.28));
+        assert!((layout.rect[2] - 821.89).abs() < 0.01);
+        assert!((layout.rect[3] - 410.945).abs() < 0.01);
+
+        let cover = DocumentSettings {
+            fit: PageFit::Cover,
+            orientation: PageOrientation::Portrait,
+            ..a4
+        };
+        let layout = layout_page(200, 100, &cover).unwrap();
+        assert!((layout.rect[3] - 821.89).abs() < 0.01);
+        assert!(layout.rect[0] < 0.0);
+
+        let image = layout_page(96, 48, &DocumentSettings::default()).unwrap();
+        assert_eq!((image.width, image.height), (72.0, 36.0));
+
+        let margins = DocumentSettings {
+            margin: 400.0,
+            ..a4
+        };
+        assert!(layout_page(10, 10, &margins).is_err());
+
+        for (width, height) in [
+            (0.0, 100.0),
+            (-100.0, 100.0),
+            (f32::NAN, 100.0),
+            (100.0, 20_000.0),
+        ] {
+            let custom = DocumentSettings {
+                page_size: PageSize::Custom { width, height },
+                ..DocumentSettings::default()
+            };
+            assert!(matches!(
+                layout_page(10, 10, &custom),
+                Err(WasmImageError::InvalidSettings(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn assembles_multi_page_tiff() {
+        let jpeg = include_bytes!("../assets/test.jpeg");
+        let svg = include_bytes!("../assets/test.svg");
+        let settings = DocumentSettings {
+            page_size: PageSize::Custom {
+                width: 72.0,
+                height: 72.0,
+            },
+            ..DocumentSettings::default()
+        };
+
+        let tiff = assemble(
+            &[jpeg, svg],
+            &["image/jpeg", "image/svg+xml"],
+            "image/tiff",
+            &settings,
+        )
+        .unwrap();
+
+        let mut decoder = tiff::decoder::Decoder::new(Cursor::new(tiff)).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (96, 96));
+        assert!(decoder.more_images());
+        decoder.next_image().unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (96, 96));
+        assert!(!decoder.more_images());
+    }
+
+    #[test]
+    fn limits_page_canvas_size() {
+        let img = DynamicImage::new_rgb8(1, 1);
+        let settings = DocumentSettings {
+            page_size: PageSize::A4,
+            dpi: 100_000.0,
+            ..DocumentSettings::default()
+        };
+        assert!(matches!(
+            render_page(&img, &settings),
+            Err(WasmImageError::LimitExceeded(..))
+        ));
+    }
+
+    #[test]
+    fn assembles_multi_page_pdf() {
+        let jpeg = include_bytes!("../assets/test.jpeg");
+        let pdf = assemble(
+            &[jpeg, jpeg, jpeg],
+            &["image/jpeg"; 3],
+            "application/pdf",
+            &DocumentSettings {
+                page_size: PageSize::Letter,
+                fit: PageFit::Cover,
+                ..DocumentSettings::default()
+            },
+        )
+        .unwrap();
+
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.contains("/Count 3"));
+        assert_eq!(text.matches("re W n").count(), 3);
+
+        assert!(assemble(
+            &[jpeg],
+            &["image/jpeg"],
+            "image/png",
+            &DocumentSettings::default()
+        )
+        .is_err());
+    }
+}
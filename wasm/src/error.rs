@@ -1,10 +1,15 @@
+use image::error::{ImageFormatHint, UnsupportedErrorKind};
 use image::ImageError;
 use resvg::usvg::Error as SvgError;
 
+#[cfg(feature = "wasm")]
+use {tsify::Tsify, wasm_bindgen::prelude::*};
+
 #[derive(thiserror::Error, Debug)]
 pub enum WasmImageError {
-    #[error("Unknown file type: {0}")]
-    UnknownFileType(String),
+    /// The declared format, if known, and why the file can't be read.
+    #[error("Unknown file type: {1}")]
+    UnknownFileType(Option<String>, String),
     #[error("Image library error: {0}")]
     LibError(#[from] ImageError),
     #[error("Parsing error: {0}")]
@@ -15,6 +20,209 @@ pub enum WasmImageError {
     EncodingError(String),
     #[error("Decoder error: Could not create {0} decoder: {1}")]
     DecoderError(String, String),
+    #[error("Format mismatch: the file was declared as {0} but is {1}, which isn't supported")]
+    FormatMismatch(String, String),
+    #[error("Limit exceeded: {1}")]
+    LimitExceeded(ErrorStage, String),
     #[error("Exif error: {0}")]
     ExifError(#[from] exif::Error),
+    /// The requested format and why it can't be written.
+    #[error("Unsupported target format: {1}")]
+    UnsupportedTargetFormat(String, String),
+    #[error("Invalid settings: {0}")]
+    InvalidSettings(String),
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+}
+
+/// Stable identifier of an error, to branch on instead of the message.
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    UnknownFileType,
+    FormatMismatch,
+    DecodeFailed,
+    EncodeFailed,
+    UnsupportedFormat,
+    UnsupportedTargetFormat,
+    InvalidSettings,
+    InvalidArgument,
+    LimitExceeded,
+    ParseFailed,
+    SvgFailed,
+    ExifFailed,
+    IoFailed,
+}
+
+/// The step that failed.
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorStage {
+    Detect,
+    Decode,
+    /// Rendering an SVG to pixels.
+    Rasterize,
+    Resize,
+    Encode,
+    Settings,
+    Metadata,
+}
+
+/// An error as passed to JavaScript.
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[cfg_attr(feature = "wasm", tsify(into_wasm_abi))]
+#[derive(Clone, PartialEq, Eq, Debug, serde::Serialize)]
+pub struct ErrorInfo {
+    pub code: ErrorCode,
+    /// Human-readable description, not meant to be matched on.
+    pub message: String,
+    /// The format involved, as a MIME type when known or a name otherwise.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub stage: Option<ErrorStage>,
+}
+
+fn hint_format(hint: &ImageFormatHint) -> Option<String> {
+    match hint {
+        ImageFormatHint::Exact(format) => Some(format.to_mime_type().to_string()),
+        ImageFormatHint::Name(name) => Some(name.clone()),
+        ImageFormatHint::PathExtension(ext) => Some(ext.to_string_lossy().into_owned()),
+        _ => None,
+    }
+}
+
+impl WasmImageError {
+    #[must_use]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::UnknownFileType(..) => ErrorCode::UnknownFileType,
+            Self::LibError(e) => match e {
+                ImageError::Decoding(_) => ErrorCode::DecodeFailed,
+                ImageError::Encoding(_) => ErrorCode::EncodeFailed,
+                ImageError::Parameter(_) => ErrorCode::InvalidArgument,
+                ImageError::Limits(_) => ErrorCode::LimitExceeded,
+                ImageError::Unsupported(_) => ErrorCode::UnsupportedFormat,
+                ImageError::IoError(_) => ErrorCode::IoFailed,
+            },
+            Self::ParseError(_) => ErrorCode::ParseFailed,
+            Self::SvgError(_) => ErrorCode::SvgFailed,
+            Self::EncodingError(_) => ErrorCode::EncodeFailed,
+            Self::DecoderError(..) => ErrorCode::DecodeFailed,
+            Self::FormatMismatch(..) => ErrorCode::FormatMismatch,
+            Self::LimitExceeded(..) => ErrorCode::LimitExceeded,
+            Self::ExifError(_) => ErrorCode::ExifFailed,
+            Self::UnsupportedTargetFormat(..) => ErrorCode::UnsupportedTargetFormat,
+            Self::InvalidSettings(_) => ErrorCode::InvalidSettings,
+            Self::InvalidArgument(_) => ErrorCode::InvalidArgument,
+        }
+    }
+
+    /// The code, message and details of the error.
+    #[must_use]
+    pub fn info(&self) -> ErrorInfo {
+        let (format, stage) = match self {
+            Self::UnknownFileType(format, _) => (format.clone(), Some(ErrorStage::Detect)),
+            Self::LibError(e) => match e {
+                ImageError::Decoding(e) => {
+                    (hint_format(&e.format_hint()), Some(ErrorStage::Decode))
+                }
+                ImageError::Encoding(e) => {
+                    (hint_format(&e.format_hint()), Some(ErrorStage::Encode))
+                }
+                ImageError::Unsupported(e) => {
+                    let format = match e.kind() {
+                        UnsupportedErrorKind::Format(hint) => hint_format(&hint),
+                        _ => hint_format(&e.format_hint()),
+                    };
+                    (format, None)
+                }
+                _ => (None, None),
+            },
+            Self::ParseError(_) | Self::InvalidSettings(_) => (None, Some(ErrorStage::Settings)),
+            Self::SvgError(_) => (Some("image/svg+xml".to_string()), Some(ErrorStage::Decode)),
+            Self::EncodingError(_) => (None, Some(ErrorStage::Encode)),
+            Self::DecoderError(format, _) => (Some(format.clone()), Some(ErrorStage::Decode)),
+            Self::FormatMismatch(_, detected) => (Some(detected.clone()), Some(ErrorStage::Detect)),
+            Self::LimitExceeded(stage, _) => (None, Some(*stage)),
+            Self::ExifError(_) => (None, Some(ErrorStage::Metadata)),
+            Self::UnsupportedTargetFormat(format, _) => {
+                (Some(format.clone()), Some(ErrorStage::Encode))
+            }
+            Self::InvalidArgument(_) => (None, None),
+        };
+        ErrorInfo {
+            code: self.code(),
+            message: self.to_string(),
+            format,
+            stage,
+        }
+    }
+}
+
+#[cfg(feature = "wasm")]
+impl From<WasmImageError> for JsValue {
+    /// Errors reach JavaScript as an [`ErrorInfo`] object rather than a string.
+    fn from(error: WasmImageError) -> Self {
+        error.info().into_js().unwrap_throw().into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_errors() {
+        let info = WasmImageError::DecoderError("HEIF".to_string(), "bad box".to_string()).info();
+        assert_eq!(info.code, ErrorCode::DecodeFailed);
+        assert_eq!(info.format.as_deref(), Some("HEIF"));
+        assert_eq!(info.stage, Some(ErrorStage::Decode));
+        assert_eq!(
+            serde_json::to_value(&info).unwrap(),
+            serde_json::json!({
+                "code": "decode_failed",
+                "message": "Decoder error: Could not create HEIF decoder: bad box",
+                "format": "HEIF",
+                "stage": "decode",
+            })
+        );
+
+        let info = image::load_from_memory_with_format(
+            b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0",
+            image::ImageFormat::Png,
+        )
+        .map_err(WasmImageError::from)
+        .unwrap_err()
+        .info();
+        assert_eq!(info.code, ErrorCode::DecodeFailed);
+        assert_eq!(info.format.as_deref(), Some("image/png"));
+
+        let json =
+            serde_json::to_value(WasmImageError::InvalidArgument("no files".to_string()).info())
+                .unwrap();
+        assert_eq!(json["code"], "invalid_argument");
+        assert!(json.get("stage").is_none());
+
+        let info = WasmImageError::UnsupportedTargetFormat(
+            "image/webp".to_string(),
+            "Lossy WebP encoding requires the `webp-lossy` feature".to_string(),
+        )
+        .info();
+        assert_eq!(info.format.as_deref(), Some("image/webp"));
+        assert_eq!(
+            info.message,
+            "Unsupported target format: Lossy WebP encoding requires the `webp-lossy` feature"
+        );
+
+        let info = crate::limits::check_dimensions(50_000, 50_000, ErrorStage::Resize)
+            .unwrap_err()
+            .info();
+        assert_eq!(info.code, ErrorCode::LimitExceeded);
+        assert_eq!(info.stage, Some(ErrorStage::Resize));
+    }
 }
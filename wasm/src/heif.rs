@@ -0,0 +1,66 @@
+//! HEIC/HEIF decoding with the pure Rust `heic-rs` decoder, enabled by the `heic` feature.
+
+use heic_rs::{context::Context, DecodeOptions, PixelLayout};
+use image::{DynamicImage, GrayImage, RgbImage, RgbaImage};
+
+use crate::error::WasmImageError;
+
+fn heif_error(e: &heic_rs::Error) -> WasmImageError {
+    WasmImageError::DecoderError("HEIF".to_string(), e.to_string())
+}
+
+/// Decodes the primary image, with rotation, mirroring and cropping applied.
+pub(crate) fn decode_heif(file: &[u8]) -> Result<DynamicImage, WasmImageError> {
+    let info = heic_rs::probe(file).map_err(|e| heif_error(&e))?;
+    let layout = if info.has_alpha {
+        PixelLayout::Rgba8
+    } else {
+        PixelLayout::Rgb8
+    };
+    let decoded = heic_rs::decode(file, &DecodeOptions::default().with_layout(layout))
+        .map_err(|e| heif_error(&e))?;
+
+    let (width, height) = (decoded.width, decoded.height);
+    let img = match decoded.layout {
+        PixelLayout::Rgba8 => RgbaImage::from_raw(width, height, decoded.data).map(Into::into),
+        PixelLayout::Gray8 => GrayImage::from_raw(width, height, decoded.data).map(Into::into),
+        _ => RgbImage::from_raw(width, height, decoded.data).map(Into::into),
+    };
+    img.ok_or_else(|| {
+        WasmImageError::DecoderError(
+            "HEIF".to_string(),
+            "Decoded pixels do not match the image size".to_string(),
+        )
+    })
+}
+
+/// Displayed size of the primary image, read from the container without decoding it.
+pub(crate) fn heif_dimensions(file: &[u8]) -> Result<(u32, u32), WasmImageError> {
+    let info = heic_rs::probe(file).map_err(|e| heif_error(&e))?;
+    Ok((info.width, info.height))
+}
+
+/// The TIFF block of the EXIF item attached to the primary image, from the `meta` box.
+pub(crate) fn heif_exif(file: &[u8]) -> Result<Option<Vec<u8>>, WasmImageError> {
+    let info = heic_rs::probe(file).map_err(|e| heif_error(&e))?;
+    let context = Context::open(file).map_err(|e| heif_error(&e))?;
+    context
+        .exif(info.primary_item)
+        .map(|exif| exif.map(<[u8]>::to_vec))
+        .map_err(|e| heif_error(&e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_other_formats() {
+        let jpeg = include_bytes!("../assets/test.jpeg");
+        assert!(matches!(
+            decode_heif(jpeg),
+            Err(WasmImageError::DecoderError(format, _)) if format == "HEIF"
+        ));
+        assert!(heif_dimensions(jpeg).is_err());
+    }
+}
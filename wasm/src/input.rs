@@ -0,0 +1,90 @@
+//! File input of the wasm functions: a `Uint8Array`, or a `Blob` such as a `File`. Partial reads
+//! only help the metadata prefix reads: format detection and JPEG metadata read the start of the
+//! file. Everything else decodes from a copy of the whole file in wasm memory.
+//!
+//! Blobs are read synchronously with `FileReaderSync`, which only exists in web workers. Each read
+//! goes through a temporary `ArrayBuffer` before it is copied into wasm memory.
+
+use js_sys::Uint8Array;
+use wasm_bindgen::{prelude::*, JsCast};
+use web_sys::{Blob, FileReaderSync};
+
+use crate::error::WasmImageError;
+
+#[wasm_bindgen]
+extern "C" {
+    /// Image file bytes, or a `Blob` to read them from.
+    #[wasm_bindgen(typescript_type = "Uint8Array | Blob")]
+    pub type ImageInput;
+}
+
+fn read_error(message: &str) -> WasmImageError {
+    image::ImageError::IoError(std::io::Error::other(message.to_string())).into()
+}
+
+pub(crate) enum Input {
+    Bytes(Uint8Array),
+    Blob(Blob),
+}
+
+impl Input {
+    /// # Errors
+    /// Returns [`WasmImageError::InvalidArgument`] if the value is neither a `Uint8Array` nor a
+    /// `Blob`.
+    pub(crate) fn new(value: &ImageInput) -> Result<Self, WasmImageError> {
+        if let Some(bytes) = value.dyn_ref::<Uint8Array>() {
+            Ok(Self::Bytes(bytes.clone()))
+        } else if let Some(blob) = value.dyn_ref::<Blob>() {
+            Ok(Self::Blob(blob.clone()))
+        } else {
+            Err(WasmImageError::InvalidArgument(
+                "Expected a Uint8Array or a Blob".to_string(),
+            ))
+        }
+    }
+
+    /// Size of the file in bytes.
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Self::Bytes(bytes) => bytes.length() as usize,
+            Self::Blob(blob) => blob.size() as usize,
+        }
+    }
+
+    /// Copies the bytes from `start` to `end` into wasm memory. The range is clamped to the file.
+    /// # Errors
+    /// Returns an error if the blob can't be read, e.g. outside of a web worker.
+    pub(crate) fn read_range(&self, start: usize, end: usize) -> Result<Vec<u8>, WasmImageError> {
+        let end = end.min(self.len());
+        let start = start.min(end);
+        match self {
+            Self::Bytes(bytes) => Ok(bytes.subarray(start as u32, end as u32).to_vec()),
+            Self::Blob(blob) => {
+                let slice = blob
+                    .slice_with_f64_and_f64(start as f64, end as f64)
+                    .map_err(|_| read_error("Could not slice the blob"))?;
+                let reader = FileReaderSync::new()
+                    .map_err(|_| read_error("Blobs can only be read in a web worker"))?;
+                let buffer = reader
+                    .read_as_array_buffer(&slice)
+                    .map_err(|_| read_error("Could not read the blob"))?;
+                Ok(Uint8Array::new(&buffer).to_vec())
+            }
+        }
+    }
+
+    /// Copies the first `len` bytes into wasm memory.
+    /// # Errors
+    /// Returns an error if the blob can't be read.
+    pub(crate) fn read_prefix(&self, len: usize) -> Result<Vec<u8>, WasmImageError> {
+        self.read_range(0, len)
+    }
+
+    /// Copies the whole file into wasm memory. For a `Blob`, this briefly holds the file twice,
+    /// once in the `ArrayBuffer` read from it and once in wasm memory.
+    /// # Errors
+    /// Returns an error if the blob can't be read.
+    pub(crate) fn read(&self) -> Result<Vec<u8>, WasmImageError> {
+        self.read_range(0, self.len())
+    }
+}
@@ -0,0 +1,155 @@
+//! JPEG XL support. Decoding uses the pure Rust `jxl-oxide` decoder (`jxl` feature), encoding
+//! uses the lossless modular encoder of `zune-jpegxl` (`jxl-encode` feature).
+//!
+//! Lossless JPEG to JPEG XL recompression, which keeps the JPEG's DCT coefficients, is not
+//! supported: `zune-jpegxl` only has a modular encoder. JPEG input is stored as lossless pixels
+//! instead, which is usually larger than the JPEG. Only the reverse is supported: rebuilding
+//! the original JPEG of a file recompressed by another encoder, see [`reconstruct_jpeg`].
+
+#[cfg(feature = "jxl")]
+use std::io::Cursor;
+
+use image::DynamicImage;
+#[cfg(feature = "jxl")]
+use jxl_oxide::{integration::JxlDecoder, JpegReconstructionStatus, JxlImage};
+
+use crate::error::WasmImageError;
+
+#[cfg(feature = "jxl")]
+fn jxl_error(e: &impl ToString) -> WasmImageError {
+    WasmImageError::DecoderError("JPEG XL".to_string(), e.to_string())
+}
+
+#[cfg(feature = "jxl")]
+fn read_jxl(file: &[u8]) -> Result<JxlImage, WasmImageError> {
+    JxlImage::builder()
+        .read(Cursor::new(file))
+        .map_err(|e| jxl_error(&e))
+}
+
+/// Decodes the first frame of a JPEG XL file.
+#[cfg(feature = "jxl")]
+pub(crate) fn decode_jxl(file: &[u8]) -> Result<DynamicImage, WasmImageError> {
+    let decoder = JxlDecoder::new(Cursor::new(file))?;
+    Ok(DynamicImage::from_decoder(decoder)?)
+}
+
+/// Image size from the codestream header.
+#[cfg(feature = "jxl")]
+pub(crate) fn jxl_dimensions(file: &[u8]) -> Result<(u32, u32), WasmImageError> {
+    let image = read_jxl(file)?;
+    Ok((image.width(), image.height()))
+}
+
+/// The TIFF block of the `Exif` box, if any.
+#[cfg(feature = "jxl")]
+pub(crate) fn jxl_exif(file: &[u8]) -> Result<Option<Vec<u8>>, WasmImageError> {
+    let image = read_jxl(file)?;
+    let exif = image.aux_boxes().first_exif().map_err(|e| jxl_error(&e))?;
+    if !exif.has_data() {
+        return Ok(None);
+    }
+    let exif = exif.unwrap();
+    let offset = exif.tiff_header_offset() as usize;
+    Ok(exif.payload().get(offset..).map(<[u8]>::to_vec))
+}
+
+/// The original JPEG of a file that was losslessly recompressed from JPEG, rebuilt bit for bit.
+/// Returns `None` if the file has no reconstruction data.
+#[cfg(feature = "jxl")]
+pub(crate) fn reconstruct_jpeg(file: &[u8]) -> Result<Option<Vec<u8>>, WasmImageError> {
+    let image = read_jxl(file)?;
+    if image.jpeg_reconstruction_status() != JpegReconstructionStatus::Available {
+        return Ok(None);
+    }
+    let mut jpeg = Vec::new();
+    image
+        .reconstruct_jpeg(&mut jpeg)
+        .map_err(|e| jxl_error(&e))?;
+    Ok(Some(jpeg))
+}
+
+/// Encodes an image as lossless JPEG XL. 16-bit and float images are stored with 16 bits per
+/// channel, everything else with 8. JPEG sources are stored as their decoded pixels, not
+/// recompressed.
+#[cfg(feature = "jxl-encode")]
+pub(crate) fn encode_jxl(img: &DynamicImage) -> Result<Vec<u8>, WasmImageError> {
+    use image::ColorType;
+    use zune_core::{bit_depth::BitDepth, colorspace::ColorSpace, options::EncoderOptions};
+
+    let (img, colorspace, depth) = match img.color() {
+        ColorType::L8 => (img.clone(), ColorSpace::Luma, BitDepth::Eight),
+        ColorType::La8 => (img.clone(), ColorSpace::LumaA, BitDepth::Eight),
+        ColorType::Rgb8 => (img.clone(), ColorSpace::RGB, BitDepth::Eight),
+        ColorType::L16 => (img.clone(), ColorSpace::Luma, BitDepth::Sixteen),
+        ColorType::La16 => (img.clone(), ColorSpace::LumaA, BitDepth::Sixteen),
+        ColorType::Rgb16 => (img.clone(), ColorSpace::RGB, BitDepth::Sixteen),
+        ColorType::Rgba16 => (img.clone(), ColorSpace::RGBA, BitDepth::Sixteen),
+        ColorType::Rgb32F => (
+            DynamicImage::ImageRgb16(img.to_rgb16()),
+            ColorSpace::RGB,
+            BitDepth::Sixteen,
+        ),
+        ColorType::Rgba32F => (
+            DynamicImage::ImageRgba16(img.to_rgba16()),
+            ColorSpace::RGBA,
+            BitDepth::Sixteen,
+        ),
+        _ => (
+            DynamicImage::ImageRgba8(img.to_rgba8()),
+            ColorSpace::RGBA,
+            BitDepth::Eight,
+        ),
+    };
+
+    let options = EncoderOptions::new(
+        img.width() as usize,
+        img.height() as usize,
+        colorspace,
+        depth,
+    );
+    let mut output = Vec::new();
+    zune_jpegxl::JxlSimpleEncoder::new(img.as_bytes(), options)
+        .encode(&mut output)
+        .map_err(|e| WasmImageError::EncodingError(format!("{e:?}")))?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "jxl")]
+    #[test]
+    fn rejects_other_formats() {
+        let jpeg = include_bytes!("../assets/test.jpeg");
+        assert!(decode_jxl(jpeg).is_err());
+        assert!(jxl_dimensions(jpeg).is_err());
+    }
+
+    #[cfg(all(feature = "jxl", feature = "jxl-encode"))]
+    #[test]
+    fn round_trips_lossless() {
+        use image::{GenericImageView, Rgba, RgbaImage};
+
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(17, 9, |x, y| {
+            Rgba([(x * 15) as u8, (y * 28) as u8, 90, 255 - (x * y) as u8])
+        }));
+        let jxl = encode_jxl(&img).unwrap();
+        assert_eq!(&jxl[..2], [0xff, 0x0a]);
+        assert_eq!(jxl_dimensions(&jxl).unwrap(), (17, 9));
+        assert_eq!(jxl_exif(&jxl).unwrap(), None);
+        assert_eq!(reconstruct_jpeg(&jxl).unwrap(), None);
+
+        let decoded = decode_jxl(&jxl).unwrap();
+        assert_eq!(decoded.dimensions(), (17, 9));
+        assert_eq!(decoded.to_rgba8(), img.to_rgba8());
+    }
+
+    #[cfg(feature = "jxl-encode")]
+    #[test]
+    fn encodes_gray() {
+        let img = DynamicImage::ImageLuma8(image::GrayImage::from_pixel(8, 8, image::Luma([7])));
+        assert!(encode_jxl(&img).unwrap().starts_with(&[0xff, 0x0a]));
+    }
+}
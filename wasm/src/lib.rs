@@ -7,17 +7,41 @@
 )]
 #![forbid(unsafe_code)]
 
+#[cfg(all(feature = "webp-lossy", target_arch = "wasm32"))]
+compile_error!(
+    "The `webp-lossy` feature compiles libwebp from C, which doesn't build for wasm32 targets."
+);
+
 pub mod convert;
+pub mod detect;
+pub mod document;
 pub mod error;
+#[cfg(feature = "heic")]
+pub(crate) mod heif;
+#[cfg(feature = "wasm")]
+pub mod input;
+#[cfg(any(feature = "jxl", feature = "jxl-encode"))]
+pub(crate) mod jxl;
+pub mod limits;
 pub(crate) mod load;
 pub mod metadata;
+pub mod pages;
+pub(crate) mod preview;
 #[cfg(feature = "wasm")]
 pub(crate) mod progress;
+#[cfg(feature = "raw")]
+pub(crate) mod raw;
+pub(crate) mod recover;
 pub mod resize;
 pub(crate) mod source_type;
+pub(crate) mod target_type;
+pub mod texture;
+pub(crate) mod tiff;
 pub mod view;
 
-pub use {convert::convert_image, metadata::load_metadata, resize::resize_image, view::get_pixels};
-
-#[cfg(feature = "wasm")]
-pub use metadata::save_metadata;
+pub use {
+    convert::convert_image, detect::detect_format, document::assemble_document,
+    limits::set_decode_limits, metadata::load_metadata, metadata::save_metadata,
+    metadata::shift_timestamps, metadata::transplant_metadata, metadata::validate_image,
+    pages::split_pages, resize::resize_image, view::get_pixel_buffer, view::get_pixels,
+};
@@ -0,0 +1,226 @@
+//! Resource limits for decoding, so crafted files (huge dimensions, decompression bombs, SVGs
+//! with millions of nodes) fail with [`WasmImageError::LimitExceeded`] instead of exhausting
+//! memory. The limits are process-wide and apply to every function of the crate.
+
+use std::io::Cursor;
+use std::sync::{PoisonError, RwLock};
+
+use image::{DynamicImage, ImageDecoder, ImageError, ImageFormat, ImageReader};
+
+use crate::error::{ErrorStage, WasmImageError};
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+const MIB: u64 = 1024 * 1024;
+
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[cfg_attr(feature = "wasm", tsify(into_wasm_abi, from_wasm_abi))]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, serde::Deserialize, serde::Serialize)]
+pub struct DecodeLimits {
+    /// Largest image, in pixels, that is decoded, rasterized or resized to.
+    #[serde(default = "default_max_pixels")]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub max_pixels: u32,
+    /// Largest buffer a decoder may allocate, in MiB.
+    #[serde(default = "default_max_alloc_mib")]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub max_alloc_mib: u32,
+    /// Largest SVG source, in bytes, after decompressing SVGZ.
+    #[serde(default = "default_max_svg_bytes")]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub max_svg_bytes: u32,
+    /// Most elements an SVG may have once `<use>` references are expanded.
+    #[serde(default = "default_max_svg_nodes")]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub max_svg_nodes: u32,
+}
+
+fn default_max_pixels() -> u32 {
+    DEFAULT_LIMITS.max_pixels
+}
+
+fn default_max_alloc_mib() -> u32 {
+    DEFAULT_LIMITS.max_alloc_mib
+}
+
+fn default_max_svg_bytes() -> u32 {
+    DEFAULT_LIMITS.max_svg_bytes
+}
+
+fn default_max_svg_nodes() -> u32 {
+    DEFAULT_LIMITS.max_svg_nodes
+}
+
+const DEFAULT_LIMITS: DecodeLimits = DecodeLimits {
+    max_pixels: 100_000_000,
+    max_alloc_mib: 1024,
+    max_svg_bytes: 64 * 1024 * 1024,
+    max_svg_nodes: 500_000,
+};
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DEFAULT_LIMITS
+    }
+}
+
+static LIMITS: RwLock<DecodeLimits> = RwLock::new(DEFAULT_LIMITS);
+
+fn current() -> DecodeLimits {
+    *LIMITS.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn exceeded(stage: ErrorStage, message: String) -> WasmImageError {
+    WasmImageError::LimitExceeded(stage, message)
+}
+
+/// Checks that an allocation of `bytes` in the given stage is within the limits.
+pub(crate) fn check_alloc(bytes: u64, stage: ErrorStage) -> Result<(), WasmImageError> {
+    let max_alloc_mib = current().max_alloc_mib;
+    if bytes > u64::from(max_alloc_mib) * MIB {
+        return Err(exceeded(
+            stage,
+            format!(
+                "{} MiB needed, the limit is {max_alloc_mib} MiB",
+                bytes.div_ceil(MIB)
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that an image of the given size, stored as 8-bit RGBA, is within the limits.
+pub(crate) fn check_dimensions(
+    width: u32,
+    height: u32,
+    stage: ErrorStage,
+) -> Result<(), WasmImageError> {
+    let max_pixels = current().max_pixels;
+    let pixels = u64::from(width) * u64::from(height);
+    if pixels > u64::from(max_pixels) {
+        return Err(exceeded(
+            stage,
+            format!("{width}x{height} image, the limit is {max_pixels} pixels"),
+        ));
+    }
+    check_alloc(pixels * 4, stage)
+}
+
+/// Checks the size of an SVG source before it is parsed.
+pub(crate) fn check_svg_bytes(len: usize) -> Result<(), WasmImageError> {
+    let max_svg_bytes = current().max_svg_bytes;
+    if len as u64 > u64::from(max_svg_bytes) {
+        return Err(exceeded(
+            ErrorStage::Decode,
+            format!("SVG of {len} bytes, the limit is {max_svg_bytes} bytes"),
+        ));
+    }
+    Ok(())
+}
+
+/// Checks the number of nodes of a parsed SVG.
+pub(crate) fn check_svg_nodes(nodes: usize) -> Result<(), WasmImageError> {
+    let max_svg_nodes = current().max_svg_nodes;
+    if nodes as u64 > u64::from(max_svg_nodes) {
+        return Err(exceeded(
+            ErrorStage::Decode,
+            format!("SVG with {nodes} nodes, the limit is {max_svg_nodes}"),
+        ));
+    }
+    Ok(())
+}
+
+/// Maps the limit errors of `image` to [`WasmImageError::LimitExceeded`].
+pub(crate) fn limit_error(e: ImageError) -> WasmImageError {
+    match e {
+        ImageError::Limits(e) => exceeded(ErrorStage::Decode, e.to_string()),
+        e => e.into(),
+    }
+}
+
+/// Creates an `image` decoder, checking the image size from its header.
+pub(crate) fn decoder(
+    file: &[u8],
+    format: ImageFormat,
+) -> Result<impl ImageDecoder + '_, WasmImageError> {
+    let mut decoder = ImageReader::with_format(Cursor::new(file), format).into_decoder()?;
+    let (width, height) = decoder.dimensions();
+    check_dimensions(width, height, ErrorStage::Decode)?;
+    check_alloc(decoder.total_bytes(), ErrorStage::Decode)?;
+
+    let mut limits = image::Limits::no_limits();
+    limits.max_alloc = Some(u64::from(current().max_alloc_mib) * MIB);
+    decoder.set_limits(limits).map_err(limit_error)?;
+    Ok(decoder)
+}
+
+/// Decodes an image with `image`, checking its size before any pixel data is read.
+pub(crate) fn decode(file: &[u8], format: ImageFormat) -> Result<DynamicImage, WasmImageError> {
+    DynamicImage::from_decoder(decoder(file, format)?).map_err(limit_error)
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen(js_name = setDecodeLimits)]
+/// Set the resource limits for all subsequent calls.
+/// # Arguments
+/// * `limits` - The new limits. Omitted fields are reset to their defaults.
+pub fn set_decode_limits(limits: DecodeLimits) {
+    *LIMITS.write().unwrap_or_else(PoisonError::into_inner) = limits;
+}
+
+#[cfg(not(feature = "wasm"))]
+/// Set the resource limits for all subsequent calls, in every thread.
+/// # Arguments
+/// * `limits` - The new limits.
+pub fn set_decode_limits(limits: DecodeLimits) {
+    *LIMITS.write().unwrap_or_else(PoisonError::into_inner) = limits;
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen(js_name = getDecodeLimits)]
+/// Get the resource limits currently applied.
+#[must_use]
+pub fn decode_limits() -> DecodeLimits {
+    current()
+}
+
+#[cfg(not(feature = "wasm"))]
+/// Get the resource limits currently applied.
+#[must_use]
+pub fn decode_limits() -> DecodeLimits {
+    current()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_dimensions() {
+        assert!(check_dimensions(10_000, 10_000, ErrorStage::Decode).is_ok());
+        assert!(matches!(
+            check_dimensions(50_000, 50_000, ErrorStage::Decode),
+            Err(WasmImageError::LimitExceeded(ErrorStage::Decode, _))
+        ));
+        assert!(check_svg_bytes(1024).is_ok());
+        assert!(check_svg_nodes(DEFAULT_LIMITS.max_svg_nodes as usize + 1).is_err());
+    }
+
+    #[test]
+    fn rejects_huge_png_before_decoding() {
+        // A valid PNG header claiming 50000x50000 pixels, without any image data.
+        let mut png = Vec::new();
+        let encoder = image::codecs::png::PngEncoder::new(&mut png);
+        image::ImageEncoder::write_image(encoder, &[0], 1, 1, image::ExtendedColorType::L8)
+            .unwrap();
+        png[16..24].copy_from_slice(&[0, 0, 0xc3, 0x50, 0, 0, 0xc3, 0x50]);
+        let crc = crc32fast::hash(&png[12..29]);
+        png[29..33].copy_from_slice(&crc.to_be_bytes());
+
+        assert!(matches!(
+            decode(&png, ImageFormat::Png),
+            Err(WasmImageError::LimitExceeded(ErrorStage::Decode, _))
+        ));
+    }
+}
@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+
 use image::ImageFormat;
 
 use crate::{
@@ -5,13 +7,35 @@ use crate::{
         settings::{Settings, SvgSettings},
         svg::svg_to_png,
     },
-    error::WasmImageError,
+    error::{ErrorStage, WasmImageError},
+    limits::{check_dimensions, check_svg_bytes, decode},
     source_type::SourceType,
 };
 
+/// Signature of gzip streams, such as SVGZ files.
+pub(crate) const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Decompresses an SVGZ file, stopping once the SVG size limit is exceeded.
+pub(crate) fn decompress_svgz(file: &[u8]) -> Result<Vec<u8>, WasmImageError> {
+    use std::io::Read;
+
+    let limit = crate::limits::decode_limits().max_svg_bytes;
+    let mut svg = Vec::new();
+    flate2::read::GzDecoder::new(file)
+        .take(u64::from(limit) + 1)
+        .read_to_end(&mut svg)
+        .map_err(|_| WasmImageError::SvgError(resvg::usvg::Error::MalformedGZip))?;
+    check_svg_bytes(svg.len())?;
+    Ok(svg)
+}
+
 pub(crate) enum SourceImage<'s> {
     Raster(image::DynamicImage),
-    Svg(&'s [u8]),
+    /// SVG source text, decompressed if the file was SVGZ.
+    Svg(Cow<'s, [u8]>),
+    /// Camera RAW, decoded once the settings are known.
+    #[cfg(feature = "raw")]
+    Raw(&'s [u8]),
 }
 
 impl SourceImage<'_> {
@@ -23,12 +47,20 @@ impl SourceImage<'_> {
             Self::Raster(img) => Ok(img.clone()),
             Self::Svg(svg) => {
                 let svg_settings = match settings {
-                    Some(Settings::Svg(settings)) => *settings,
+                    Some(Settings::Svg(settings)) => settings.clone(),
                     _ => SvgSettings::default(),
                 };
-                let img = svg_to_png(svg, svg_settings)?;
+                let img = svg_to_png(svg, &svg_settings)?;
                 image::load_from_memory_with_format(&img, ImageFormat::Png).map_err(Into::into)
             }
+            #[cfg(feature = "raw")]
+            Self::Raw(file) => {
+                let raw_settings = match settings {
+                    Some(Settings::Raw(settings)) => settings.clone(),
+                    _ => crate::convert::settings::RawSettings::default(),
+                };
+                crate::raw::decode_raw(file, &raw_settings)
+            }
         }
     }
 }
@@ -38,23 +70,78 @@ pub(crate) fn load_image<'a>(
     source_type: Option<&'a SourceType>,
 ) -> Result<SourceImage<'a>, WasmImageError> {
     let loaded_image = match source_type {
-        Some(SourceType::Raster(file_type)) => {
-            SourceImage::Raster(image::load_from_memory_with_format(file, *file_type)?)
+        Some(SourceType::Raster(ImageFormat::Dds)) => {
+            let (width, height) = crate::texture::dds_dimensions(file)?;
+            check_dimensions(width, height, ErrorStage::Decode)?;
+            SourceImage::Raster(crate::texture::decode_dds(file)?)
+        }
+        Some(SourceType::Raster(file_type)) => SourceImage::Raster(decode(file, *file_type)?),
+        Some(SourceType::Svg) if file.starts_with(&GZIP_MAGIC) => {
+            SourceImage::Svg(Cow::Owned(decompress_svgz(file)?))
+        }
+        Some(SourceType::Svg) => SourceImage::Svg(Cow::Borrowed(file)),
+        #[cfg(feature = "heic")]
+        Some(SourceType::Heif) => {
+            let (width, height) = crate::heif::heif_dimensions(file)?;
+            check_dimensions(width, height, ErrorStage::Decode)?;
+            SourceImage::Raster(crate::heif::decode_heif(file)?)
+        }
+        #[cfg(feature = "jxl")]
+        Some(SourceType::Jxl) => {
+            let (width, height) = crate::jxl::jxl_dimensions(file)?;
+            check_dimensions(width, height, ErrorStage::Decode)?;
+            SourceImage::Raster(crate::jxl::decode_jxl(file)?)
+        }
+        #[cfg(feature = "raw")]
+        Some(SourceType::Raw) => {
+            // Developing the sensor data takes more memory than the output, which is checked
+            // against the allocation limit in `raw::develop` once the mode is known.
+            let (width, height) = crate::raw::raw_dimensions(file)?;
+            check_dimensions(width, height, ErrorStage::Decode)?;
+            SourceImage::Raw(file)
         }
-        Some(SourceType::Svg) => SourceImage::Svg(file),
         None => {
-            let img = image::load_from_memory(file)
-                .map_err(|e| WasmImageError::UnknownFileType(e.to_string()))?;
-            SourceImage::Raster(img)
+            let format = image::guess_format(file)
+                .map_err(|e| WasmImageError::UnknownFileType(None, e.to_string()))?;
+            SourceImage::Raster(decode(file, format)?)
         }
     };
 
     Ok(loaded_image)
 }
 
+/// Like [`load_image`], but truncated or corrupt raster images yield the pixels that could be
+/// decoded instead of an error, along with warnings describing what is missing.
+pub(crate) fn load_image_lenient<'a>(
+    file: &'a [u8],
+    source_type: Option<&'a SourceType>,
+) -> Result<(SourceImage<'a>, Vec<String>), WasmImageError> {
+    let format = match source_type {
+        Some(SourceType::Raster(format)) => Some(*format),
+        None => image::guess_format(file).ok(),
+        _ => None,
+    };
+    match format {
+        // Not decoded by `image` (AVIF) or decoded outside of it, without partial results.
+        Some(ImageFormat::Avif | ImageFormat::Dds) | None => {
+            load_image(file, source_type).map(|img| (img, Vec::new()))
+        }
+        Some(format) => {
+            let (img, warnings) = crate::recover::decode_partial(file, format)?;
+            Ok((SourceImage::Raster(img), warnings))
+        }
+    }
+}
+
 pub(crate) enum RawSourceImage<'s> {
     Raster(&'s [u8], ImageFormat),
     Svg(&'s [u8]),
+    #[cfg(feature = "heic")]
+    Heif(&'s [u8]),
+    #[cfg(feature = "jxl")]
+    Jxl(&'s [u8]),
+    #[cfg(feature = "raw")]
+    Raw(&'s [u8]),
 }
 
 pub(crate) fn load_raw_image<'a>(
@@ -64,9 +151,15 @@ pub(crate) fn load_raw_image<'a>(
     match source_type {
         Some(SourceType::Raster(file_type)) => Ok(RawSourceImage::Raster(file, *file_type)),
         Some(SourceType::Svg) => Ok(RawSourceImage::Svg(file)),
+        #[cfg(feature = "heic")]
+        Some(SourceType::Heif) => Ok(RawSourceImage::Heif(file)),
+        #[cfg(feature = "jxl")]
+        Some(SourceType::Jxl) => Ok(RawSourceImage::Jxl(file)),
+        #[cfg(feature = "raw")]
+        Some(SourceType::Raw) => Ok(RawSourceImage::Raw(file)),
         None => {
             let img = image::guess_format(file)
-                .map_err(|e| WasmImageError::UnknownFileType(e.to_string()))?;
+                .map_err(|e| WasmImageError::UnknownFileType(None, e.to_string()))?;
 
             Ok(RawSourceImage::Raster(file, img))
         }
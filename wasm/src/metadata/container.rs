@@ -0,0 +1,394 @@
+//! Byte-level metadata injection for the cases the `image` encoders don't cover:
+//! ICC and XMP segments (including extended XMP) in JPEG files that are edited without
+//! re-encoding, and XMP packets in PNG and WebP files.
+
+use crate::error::WasmImageError;
+
+const JPEG_ICC_SIGNATURE: &[u8] = b"ICC_PROFILE\0";
+const JPEG_XMP_SIGNATURE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const JPEG_XMP_EXTENSION_SIGNATURE: &[u8] = b"http://ns.adobe.com/xmp/extension/\0";
+pub(super) const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const PNG_XMP_KEYWORD: &[u8] = b"XML:com.adobe.xmp";
+
+const JPEG_APP1: u8 = 0xE1;
+const JPEG_APP2: u8 = 0xE2;
+/// Largest payload of a JPEG marker segment (the length field counts itself).
+const JPEG_MAX_PAYLOAD: usize = u16::MAX as usize - 2;
+
+fn malformed(format: &str) -> WasmImageError {
+    WasmImageError::EncodingError(format!("Malformed {format} file"))
+}
+
+/// A JPEG marker segment, from its first `0xFF` byte to the end of its payload.
+pub(crate) struct JpegSegment {
+    pub(crate) marker: u8,
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+}
+
+impl JpegSegment {
+    /// The bytes after the marker and length field.
+    pub(crate) fn payload<'a>(&self, jpeg: &'a [u8]) -> &'a [u8] {
+        let marker = self.start
+            + jpeg[self.start..]
+                .iter()
+                .take_while(|&&b| b == 0xFF)
+                .count();
+        &jpeg[(marker + 3).min(self.end)..self.end]
+    }
+}
+
+/// Why a JPEG couldn't be split into marker segments. Each case carries the offset of the
+/// problem.
+#[derive(thiserror::Error, Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum JpegSegmentError {
+    #[error("Missing start-of-image marker")]
+    MissingStartOfImage,
+    #[error("Expected a marker, found 0x{found:02X}")]
+    ExpectedMarker { pos: usize, found: u8 },
+    #[error("The file ends before the end-of-image marker")]
+    UnexpectedEnd { pos: usize },
+    #[error("The file ends inside a marker segment")]
+    TruncatedLength { start: usize },
+    #[error("The 0x{marker:02X} segment extends past the end of the file")]
+    InvalidLength { start: usize, marker: u8 },
+}
+
+impl JpegSegmentError {
+    pub(crate) fn pos(self) -> usize {
+        match self {
+            Self::MissingStartOfImage => 0,
+            Self::ExpectedMarker { pos, .. } | Self::UnexpectedEnd { pos } => pos,
+            Self::TruncatedLength { start } | Self::InvalidLength { start, .. } => start,
+        }
+    }
+}
+
+impl From<JpegSegmentError> for WasmImageError {
+    fn from(_: JpegSegmentError) -> Self {
+        malformed("JPEG")
+    }
+}
+
+/// Reads the marker segment at `pos`: fill bytes, the marker and, unless it stands alone, the
+/// length-prefixed payload. The entropy-coded data following a start of scan is not included.
+pub(crate) fn read_jpeg_segment(jpeg: &[u8], pos: usize) -> Result<JpegSegment, JpegSegmentError> {
+    match jpeg.get(pos) {
+        Some(0xFF) => {}
+        Some(&found) => return Err(JpegSegmentError::ExpectedMarker { pos, found }),
+        None => return Err(JpegSegmentError::UnexpectedEnd { pos }),
+    }
+    let start = pos;
+    let mut pos = pos;
+    while jpeg.get(pos) == Some(&0xFF) {
+        pos += 1;
+    }
+    let marker = *jpeg
+        .get(pos)
+        .ok_or(JpegSegmentError::UnexpectedEnd { pos: start })?;
+    pos += 1;
+
+    // Standalone markers have no length field. 0x00 is not a marker but a stuffed 0xFF byte,
+    // which has no length either.
+    if !matches!(marker, 0x00 | 0x01 | 0xD0..=0xD9) {
+        let len = jpeg
+            .get(pos..pos + 2)
+            .map(|b| usize::from(u16::from_be_bytes([b[0], b[1]])))
+            .ok_or(JpegSegmentError::TruncatedLength { start })?;
+        if len < 2 || pos + len > jpeg.len() {
+            return Err(JpegSegmentError::InvalidLength { start, marker });
+        }
+        pos += len;
+    }
+    Ok(JpegSegment {
+        marker,
+        start,
+        end: pos,
+    })
+}
+
+/// Splits the JPEG header (everything between SOI and SOS) into marker segments.
+/// Returns the segments and the offset at which the start-of-scan (or end-of-image) segment
+/// starts.
+pub(crate) fn jpeg_header_segments(
+    jpeg: &[u8],
+) -> Result<(Vec<JpegSegment>, usize), JpegSegmentError> {
+    if !jpeg.starts_with(&[0xFF, 0xD8]) {
+        return Err(JpegSegmentError::MissingStartOfImage);
+    }
+
+    let mut segments = Vec::new();
+    let mut pos = 2;
+    loop {
+        let segment = read_jpeg_segment(jpeg, pos)?;
+        // Start of scan / end of image: the rest is entropy-coded data.
+        if matches!(segment.marker, 0xDA | 0xD9) {
+            return Ok((segments, segment.start));
+        }
+        pos = segment.end;
+        segments.push(segment);
+    }
+}
+
+/// Length of the JPEG header, up to and including the first start-of-scan segment. Decoders need
+/// nothing more to read the image size, and all metadata segments come before it.
+#[cfg(any(feature = "wasm", test))]
+pub(super) fn jpeg_header_len(jpeg: &[u8]) -> Option<usize> {
+    let (_, scan_start) = jpeg_header_segments(jpeg).ok()?;
+    let scan = read_jpeg_segment(jpeg, scan_start).ok()?;
+    (scan.marker == 0xDA).then_some(scan.end)
+}
+
+/// Replaces all `marker` segments whose payload starts with one of `signatures` by new
+/// segments holding `payloads`. New segments are placed after the leading APP0/APP1 segments
+/// (JFIF and EXIF), where readers expect ICC and XMP data.
+fn replace_jpeg_segments(
+    jpeg: &[u8],
+    marker: u8,
+    signatures: &[&[u8]],
+    payloads: &[Vec<u8>],
+) -> Result<Vec<u8>, WasmImageError> {
+    let (segments, scan_start) = jpeg_header_segments(jpeg)?;
+    let kept: Vec<&JpegSegment> = segments
+        .iter()
+        .filter(|s| {
+            !(s.marker == marker
+                && signatures
+                    .iter()
+                    .any(|signature| s.payload(jpeg).starts_with(signature)))
+        })
+        .collect();
+    let insert_at = kept
+        .iter()
+        .position(|s| !matches!(s.marker, 0xE0 | JPEG_APP1))
+        .unwrap_or(kept.len());
+
+    let mut output = Vec::with_capacity(jpeg.len() + payloads.iter().map(Vec::len).sum::<usize>());
+    output.extend_from_slice(&jpeg[..2]);
+    for segment in &kept[..insert_at] {
+        output.extend_from_slice(&jpeg[segment.start..segment.end]);
+    }
+    for payload in payloads {
+        if payload.len() > JPEG_MAX_PAYLOAD {
+            return Err(WasmImageError::EncodingError(
+                "Metadata segment too large for JPEG".to_string(),
+            ));
+        }
+        output.extend_from_slice(&[0xFF, marker]);
+        output.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+        output.extend_from_slice(payload);
+    }
+    for segment in &kept[insert_at..] {
+        output.extend_from_slice(&jpeg[segment.start..segment.end]);
+    }
+    output.extend_from_slice(&jpeg[scan_start..]);
+    Ok(output)
+}
+
+/// Embeds an ICC profile as APP2 `ICC_PROFILE` segments, replacing any existing profile.
+pub(super) fn set_jpeg_icc_profile(jpeg: &[u8], icc: &[u8]) -> Result<Vec<u8>, WasmImageError> {
+    let chunk_size = JPEG_MAX_PAYLOAD - JPEG_ICC_SIGNATURE.len() - 2;
+    let chunks: Vec<&[u8]> = icc.chunks(chunk_size).collect();
+    let count = u8::try_from(chunks.len())
+        .map_err(|_| WasmImageError::EncodingError("ICC profile too large for JPEG".to_string()))?;
+
+    let payloads: Vec<Vec<u8>> = chunks
+        .iter()
+        .zip(1..=count)
+        .map(|(chunk, seq)| [JPEG_ICC_SIGNATURE, &[seq, count], chunk].concat())
+        .collect();
+
+    replace_jpeg_segments(jpeg, JPEG_APP2, &[JPEG_ICC_SIGNATURE], &payloads)
+}
+
+/// Embeds an XMP packet as an APP1 segment, replacing any existing one.
+///
+/// Packets that don't fit in one segment are written as extended XMP: the whole packet is
+/// split across `xmp/extension` segments, and the standard segment only points to it by the
+/// MD5 digest of the packet (`xmpNote:HasExtendedXMP`).
+pub(super) fn set_jpeg_xmp(jpeg: &[u8], xmp: &[u8]) -> Result<Vec<u8>, WasmImageError> {
+    let signatures = [JPEG_XMP_SIGNATURE, JPEG_XMP_EXTENSION_SIGNATURE];
+    let payload = [JPEG_XMP_SIGNATURE, xmp].concat();
+    if payload.len() <= JPEG_MAX_PAYLOAD {
+        return replace_jpeg_segments(jpeg, JPEG_APP1, &signatures, &[payload]);
+    }
+
+    let full_len = u32::try_from(xmp.len())
+        .map_err(|_| WasmImageError::EncodingError("XMP packet too large for JPEG".to_string()))?;
+    let guid = format!("{:X}", md5::compute(xmp));
+    let standard = format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\
+         <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\
+         <rdf:Description rdf:about=\"\" xmlns:xmpNote=\"http://ns.adobe.com/xmp/note/\" \
+         xmpNote:HasExtendedXMP=\"{guid}\"/>\
+         </rdf:RDF></x:xmpmeta><?xpacket end=\"w\"?>"
+    );
+
+    // Signature, GUID, full length and offset of the chunk, then the chunk itself.
+    let header_len = JPEG_XMP_EXTENSION_SIGNATURE.len() + guid.len() + 8;
+    let mut payloads = vec![[JPEG_XMP_SIGNATURE, standard.as_bytes()].concat()];
+    let mut offset = 0u32;
+    for chunk in xmp.chunks(JPEG_MAX_PAYLOAD - header_len) {
+        payloads.push(
+            [
+                JPEG_XMP_EXTENSION_SIGNATURE,
+                guid.as_bytes(),
+                &full_len.to_be_bytes(),
+                &offset.to_be_bytes(),
+                chunk,
+            ]
+            .concat(),
+        );
+        // Can't overflow, the chunks add up to `full_len`.
+        offset += chunk.len() as u32;
+    }
+    replace_jpeg_segments(jpeg, JPEG_APP1, &signatures, &payloads)
+}
+
+fn png_chunk(chunk_type: [u8; 4], data: &[u8]) -> Result<Vec<u8>, WasmImageError> {
+    let len = u32::try_from(data.len())
+        .map_err(|_| WasmImageError::EncodingError("PNG chunk too large".to_string()))?;
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(&chunk_type);
+    crc.update(data);
+
+    let mut chunk = Vec::with_capacity(data.len() + 12);
+    chunk.extend_from_slice(&len.to_be_bytes());
+    chunk.extend_from_slice(&chunk_type);
+    chunk.extend_from_slice(data);
+    chunk.extend_from_slice(&crc.finalize().to_be_bytes());
+    Ok(chunk)
+}
+
+/// Embeds an XMP packet as an uncompressed `iTXt` chunk before the image data,
+/// replacing any existing XMP chunk.
+pub(super) fn set_png_xmp(png: &[u8], xmp: &[u8]) -> Result<Vec<u8>, WasmImageError> {
+    if !png.starts_with(PNG_SIGNATURE) {
+        return Err(malformed("PNG"));
+    }
+
+    // keyword, null separator, compression flag + method, empty language tag and
+    // translated keyword (each null-terminated), text.
+    let data = [PNG_XMP_KEYWORD, &[0, 0, 0, 0, 0], xmp].concat();
+    let mut xmp_chunk = Some(png_chunk(*b"iTXt", &data)?);
+
+    let mut output = Vec::with_capacity(png.len() + data.len() + 12);
+    output.extend_from_slice(PNG_SIGNATURE);
+    let mut pos = PNG_SIGNATURE.len();
+    while pos < png.len() {
+        let header = png.get(pos..pos + 8).ok_or_else(|| malformed("PNG"))?;
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let chunk_type = &header[4..8];
+        let end = len
+            .checked_add(12)
+            .and_then(|chunk_len| pos.checked_add(chunk_len))
+            .filter(|&end| end <= png.len())
+            .ok_or_else(|| malformed("PNG"))?;
+
+        let is_xmp = chunk_type == b"iTXt" && png[pos + 8..end - 4].starts_with(PNG_XMP_KEYWORD);
+        if chunk_type == b"IDAT" {
+            if let Some(chunk) = xmp_chunk.take() {
+                output.extend_from_slice(&chunk);
+            }
+        }
+        if !is_xmp {
+            output.extend_from_slice(&png[pos..end]);
+        }
+        pos = end;
+    }
+
+    Ok(output)
+}
+
+/// Embeds an XMP packet as a WebP `XMP ` chunk, replacing any existing one. Files in the
+/// simple (lossy/lossless only) layout are upgraded to the extended layout with a `VP8X`
+/// header, which requires the canvas size and whether the image has alpha.
+pub(super) fn set_webp_xmp(
+    webp: &[u8],
+    xmp: &[u8],
+    width: u32,
+    height: u32,
+    has_alpha: bool,
+) -> Result<Vec<u8>, WasmImageError> {
+    set_webp_chunk(webp, *b"XMP ", xmp, width, height, has_alpha)
+}
+
+/// Embeds a metadata chunk (`ICCP`, `EXIF` or `XMP `) in a WebP file, replacing any existing
+/// one and setting its flag in the `VP8X` header, like [`set_webp_xmp`].
+pub(crate) fn set_webp_chunk(
+    webp: &[u8],
+    chunk: [u8; 4],
+    payload: &[u8],
+    width: u32,
+    height: u32,
+    has_alpha: bool,
+) -> Result<Vec<u8>, WasmImageError> {
+    const ALPHA_FLAG: u8 = 0x10;
+
+    let flag = match &chunk {
+        b"ICCP" => 0x20,
+        b"EXIF" => 0x08,
+        b"XMP " => 0x04,
+        _ => {
+            return Err(WasmImageError::EncodingError(
+                "Unsupported WebP metadata chunk".to_string(),
+            ))
+        }
+    };
+
+    if webp.len() < 12 || &webp[..4] != b"RIFF" || &webp[8..12] != b"WEBP" {
+        return Err(malformed("WebP"));
+    }
+
+    let mut chunks: Vec<(&[u8], Vec<u8>)> = Vec::new();
+    let mut pos = 12;
+    while pos + 8 <= webp.len() {
+        let fourcc = &webp[pos..pos + 4];
+        let len = u32::from_le_bytes([webp[pos + 4], webp[pos + 5], webp[pos + 6], webp[pos + 7]])
+            as usize;
+        let end = (pos + 8)
+            .checked_add(len)
+            .filter(|&end| end <= webp.len())
+            .ok_or_else(|| malformed("WebP"))?;
+        if fourcc != chunk {
+            chunks.push((fourcc, webp[pos + 8..end].to_vec()));
+        }
+        // Odd-sized chunks are padded, the padding may be missing at the end of the file.
+        pos = end.saturating_add(len & 1);
+    }
+
+    if let Some((_, data)) = chunks.iter_mut().find(|(fourcc, _)| *fourcc == b"VP8X") {
+        data[0] |= flag;
+    } else {
+        let mut data = vec![flag | if has_alpha { ALPHA_FLAG } else { 0 }, 0, 0, 0];
+        data.extend_from_slice(&(width.saturating_sub(1)).to_le_bytes()[..3]);
+        data.extend_from_slice(&(height.saturating_sub(1)).to_le_bytes()[..3]);
+        chunks.insert(0, (b"VP8X", data));
+    }
+    // The ICC profile must directly follow the header, EXIF and XMP come after the image.
+    if &chunk == b"ICCP" {
+        chunks.insert(1, (&b"ICCP"[..], payload.to_vec()));
+    } else {
+        chunks.push((&chunk[..], payload.to_vec()));
+    }
+
+    let mut body = b"WEBP".to_vec();
+    for (fourcc, data) in chunks {
+        let len = u32::try_from(data.len())
+            .map_err(|_| WasmImageError::EncodingError("WebP chunk too large".to_string()))?;
+        body.extend_from_slice(fourcc);
+        body.extend_from_slice(&len.to_le_bytes());
+        body.extend_from_slice(&data);
+        if data.len() % 2 == 1 {
+            body.push(0);
+        }
+    }
+
+    let riff_len = u32::try_from(body.len())
+        .map_err(|_| WasmImageError::EncodingError("WebP file too large".to_string()))?;
+    let mut output = b"RIFF".to_vec();
+    output.extend_from_slice(&riff_len.to_le_bytes());
+    output.extend_from_slice(&body);
+    Ok(output)
+}
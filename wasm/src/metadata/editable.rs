@@ -38,15 +38,71 @@ pub(super) static EDITABLE_TAGS: &[EditableTag] = &[
     EditableTag { tag: exif::Tag::LensSerialNumber,    constructor: ExifTag::LensSerialNumber    },
 ];
 
-pub(super) fn description_to_exif_tag(desc: &str, value: String) -> Option<ExifTag> {
+/// Parses the IFD name accepted in `MetadataChange::ifd` and returned in `MetadataField::ifd`.
+pub(super) fn parse_ifd(ifd: &str) -> Option<exif::Context> {
+    match ifd.to_ascii_lowercase().as_str() {
+        "tiff" | "ifd0" => Some(exif::Context::Tiff),
+        "exif" => Some(exif::Context::Exif),
+        "gps" => Some(exif::Context::Gps),
+        "interop" => Some(exif::Context::Interop),
+        _ => None,
+    }
+}
+
+pub(super) fn ifd_name(context: exif::Context) -> &'static str {
+    match context {
+        exif::Context::Tiff => "tiff",
+        exif::Context::Exif => "exif",
+        exif::Context::Gps => "gps",
+        exif::Context::Interop => "interop",
+        _ => "unknown",
+    }
+}
+
+/// Stable identifier for a tag: the EXIF tag name (`DateTimeOriginal`) for tags known to
+/// kamadak-exif, the hex tag number (`0x9003`) otherwise.
+pub(super) fn tag_id(tag: exif::Tag) -> String {
+    if tag.description().is_some() {
+        tag.to_string()
+    } else {
+        format!("{:#06x}", tag.number())
+    }
+}
+
+fn parse_tag_number(id: &str) -> Option<u16> {
+    match id.strip_prefix("0x").or_else(|| id.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => id.parse().ok(),
+    }
+}
+
+/// Resolves a `MetadataChange::tag` to a known tag. Accepts the tag name (`DateTimeOriginal`),
+/// the tag number in hex or decimal (`0x9003`, `36867`), and — as a fallback for older
+/// callers — the `tag.description()` string. `ifd` narrows the lookup when given.
+pub(super) fn resolve_tag(id: &str, ifd: Option<exif::Context>) -> Option<exif::Tag> {
+    let known = || {
+        EDITABLE_TAGS
+            .iter()
+            .map(|e| e.tag)
+            .chain(DELETABLE_TAGS.iter().map(|e| e.tag))
+            .filter(|tag| ifd.is_none_or(|ctx| tag.context() == ctx))
+    };
+
+    known()
+        .find(|tag| tag.to_string() == id)
+        .or_else(|| parse_tag_number(id).and_then(|n| known().find(|tag| tag.number() == n)))
+        .or_else(|| known().find(|tag| tag.description() == Some(id)))
+}
+
+pub(super) fn editable_exif_tag(tag: exif::Tag, value: String) -> Option<ExifTag> {
     EDITABLE_TAGS
         .iter()
-        .find(|e| e.tag.description() == Some(desc))
+        .find(|e| e.tag == tag)
         .map(|e| (e.constructor)(value))
 }
 
 /// Tags that cannot be edited as strings but can still be deleted.
-/// little_exif only needs a correctly-typed dummy value for remove_tag —
+/// `little_exif` only needs a correctly-typed dummy value for `remove_tag` —
 /// the actual content doesn't matter, just that the variant matches.
 pub(super) struct DeletableTag {
     pub tag:     exif::Tag,
@@ -82,10 +138,14 @@ pub(super) static DELETABLE_TAGS: &[DeletableTag] = &[
     DeletableTag { tag: exif::Tag::Sharpness,         deleter: || ExifTag::Sharpness(vec![])         },
 ];
 
-/// Returns an ExifTag suitable for remove_tag, checking both editable and deletable-only tags.
-pub(super) fn tag_for_remove(desc: &str) -> Option<ExifTag> {
-    description_to_exif_tag(desc, String::new())
-        .or_else(|| DELETABLE_TAGS.iter().find(|e| e.tag.description() == Some(desc)).map(|e| (e.deleter)()))
+/// Returns an `ExifTag` suitable for `remove_tag`, checking both editable and deletable-only tags.
+pub(super) fn tag_for_remove(tag: exif::Tag) -> Option<ExifTag> {
+    editable_exif_tag(tag, String::new()).or_else(|| {
+        DELETABLE_TAGS
+            .iter()
+            .find(|e| e.tag == tag)
+            .map(|e| (e.deleter)())
+    })
 }
 
 #[cfg(feature = "wasm")]
@@ -122,7 +182,7 @@ mod tests {
                 e.tag
             );
         }
-        // Descriptions must be unique so the lookup in description_to_exif_tag is unambiguous.
+        // Descriptions must be unique so the fallback lookup in resolve_tag is unambiguous.
         let descs: Vec<&str> = EDITABLE_TAGS.iter().filter_map(|e| e.tag.description()).collect();
         let unique: std::collections::HashSet<&str> = descs.iter().copied().collect();
         assert_eq!(descs.len(), unique.len(), "EDITABLE_TAGS contains duplicate descriptions");
@@ -147,4 +207,25 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn resolve_tag_by_name_number_and_description() {
+        let expected = Some(exif::Tag::DateTimeOriginal);
+        assert_eq!(resolve_tag("DateTimeOriginal", None), expected);
+        assert_eq!(resolve_tag("0x9003", None), expected);
+        assert_eq!(resolve_tag("36867", None), expected);
+        assert_eq!(resolve_tag("0x9003", Some(exif::Context::Exif)), expected);
+        assert_eq!(resolve_tag("0x9003", Some(exif::Context::Tiff)), None);
+        let desc = exif::Tag::DateTimeOriginal.description().unwrap();
+        assert_eq!(resolve_tag(desc, None), expected);
+        assert_eq!(resolve_tag("NotATag", None), None);
+    }
+
+    #[test]
+    fn tag_ids_round_trip() {
+        for tag in EDITABLE_TAGS.iter().map(|e| e.tag).chain(DELETABLE_TAGS.iter().map(|e| e.tag)) {
+            let ifd = parse_ifd(ifd_name(tag.context()));
+            assert_eq!(resolve_tag(&tag_id(tag), ifd), Some(tag), "{tag:?} does not round-trip");
+        }
+    }
 }
@@ -1,4 +1,5 @@
 use std::{
+    borrow::Cow,
     collections::HashMap,
     io::{BufReader, Cursor},
 };
@@ -7,26 +8,69 @@ use exif::Exif;
 use image::{codecs, GenericImageView, ImageDecoder, ImageFormat};
 use resvg::usvg::Options;
 
+#[cfg(any(feature = "wasm", test))]
+use super::container::jpeg_header_len;
+use super::editable::{ifd_name, tag_id};
+use super::thumbnail::exif_thumbnail;
+#[cfg(feature = "heic")]
+use crate::heif::{heif_dimensions, heif_exif};
+#[cfg(feature = "jxl")]
+use crate::jxl::{jxl_dimensions, jxl_exif};
+#[cfg(feature = "raw")]
+use crate::raw::raw_dimensions;
 use crate::{
+    detect::resolve_mime_type,
     error::WasmImageError,
-    load::{load_raw_image, RawSourceImage},
+    limits::decode,
+    load::{decompress_svgz, load_raw_image, RawSourceImage, GZIP_MAGIC},
+    pages::{tiff_pages, PageInfo},
     source_type::SourceType,
+    texture::{dds_dimensions, dds_info, TextureInfo},
 };
 
 #[cfg(feature = "wasm")]
-use {js_sys::Uint8Array, wasm_bindgen::prelude::*};
+use {
+    crate::input::{ImageInput, Input},
+    serde::Serialize,
+    wasm_bindgen::prelude::*,
+};
 
 #[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
-#[cfg_attr(feature = "wasm", tsify(from_wasm_abi, into_wasm_abi))]
 #[derive(serde::Deserialize, serde::Serialize, Default)]
 pub struct Metadata {
     pub width: u32,
     pub height: u32,
     pub other: Option<HashMap<String, String>>,
     pub gps: Option<HashMap<String, String>>,
+    /// Every EXIF field with its stable identifier, usable as `MetadataChange::tag`.
+    pub fields: Option<Vec<MetadataField>>,
+    /// JPEG bytes of the EXIF thumbnail (IFD1), if the file embeds one.
+    #[serde(default, with = "serde_bytes")]
+    #[cfg_attr(feature = "wasm", tsify(type = "Uint8Array | null"))]
+    pub thumbnail: Option<Vec<u8>>,
+    /// Size of every page of a multi-page TIFF, in file order.
+    pub pages: Option<Vec<PageInfo>>,
+    /// Mip count and pixel format of a DDS texture.
+    pub texture: Option<TextureInfo>,
     pub errors: Option<Vec<String>>,
 }
 
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[cfg_attr(feature = "wasm", tsify(from_wasm_abi, into_wasm_abi))]
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct MetadataField {
+    /// Tag name (`DateTimeOriginal`), or the hex tag number (`0x9003`) for unknown tags.
+    pub id: String,
+    pub number: u16,
+    /// IFD the tag was read from: `tiff`, `exif`, `gps` or `interop`.
+    pub ifd: String,
+    /// Human-readable description; the key used in `other` and `gps`.
+    pub description: Option<String>,
+    pub value: String,
+    /// Raw text of ASCII fields, without the quoting and escaping applied to `value`.
+    pub text: Option<String>,
+}
+
 pub(super) fn exif_to_hashmaps(
     exif: &Exif,
 ) -> (HashMap<String, String>, Option<HashMap<String, String>>) {
@@ -46,7 +90,37 @@ pub(super) fn exif_to_hashmaps(
     (other, if gps.is_empty() { None } else { Some(gps) })
 }
 
-fn get_decoder<'a>(
+pub(super) fn exif_to_fields(exif: &Exif) -> Vec<MetadataField> {
+    exif.fields()
+        .map(|field| MetadataField {
+            id: tag_id(field.tag),
+            number: field.tag.number(),
+            ifd: ifd_name(field.tag.context()).to_string(),
+            description: field.tag.description().map(str::to_string),
+            value: field.value.display_as(field.tag).to_string(),
+            text: ascii_text(&field.value),
+        })
+        .collect()
+}
+
+fn ascii_text(value: &exif::Value) -> Option<String> {
+    match value {
+        exif::Value::Ascii(strings) => Some(
+            strings
+                .iter()
+                .map(|s| {
+                    String::from_utf8_lossy(s)
+                        .trim_end_matches('\0')
+                        .to_string()
+                })
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        _ => None,
+    }
+}
+
+pub(super) fn get_decoder<'a>(
     format: ImageFormat,
     img: &'a [u8],
 ) -> Result<Box<dyn ImageDecoder + 'a>, WasmImageError> {
@@ -114,7 +188,7 @@ fn get_decoder<'a>(
     Ok(decoder)
 }
 
-fn get_exif_errors(error: exif::Error) -> Result<Vec<String>, WasmImageError> {
+pub(super) fn get_exif_errors(error: exif::Error) -> Result<Vec<String>, WasmImageError> {
     let mut errors = Vec::new();
     error
         .distill_partial_result(|exif_errors| {
@@ -127,11 +201,79 @@ fn get_exif_errors(error: exif::Error) -> Result<Vec<String>, WasmImageError> {
     Ok(errors)
 }
 
+/// Parses a plain TIFF-structured EXIF block, as stored outside of `image`'s formats.
+#[cfg(any(feature = "heic", feature = "jxl", feature = "raw"))]
+fn read_raw_exif(exif: Vec<u8>) -> Result<Exif, Vec<String>> {
+    let mut reader = exif::Reader::new();
+    reader.continue_on_error(true);
+    reader
+        .read_raw(exif)
+        .map_err(|e| get_exif_errors(e).unwrap_or_else(|e| vec![e.to_string()]))
+}
+
+impl Metadata {
+    /// Metadata of an image with the given size and parsed EXIF, or the EXIF parse errors.
+    fn from_exif(width: u32, height: u32, exif: Result<Exif, Vec<String>>) -> Self {
+        match exif {
+            Ok(exif) => {
+                let (other, gps) = exif_to_hashmaps(&exif);
+                Self {
+                    width,
+                    height,
+                    other: Some(other),
+                    gps,
+                    fields: Some(exif_to_fields(&exif)),
+                    thumbnail: exif_thumbnail(&exif),
+                    ..Default::default()
+                }
+            }
+            Err(errors) => Self {
+                width,
+                height,
+                errors: Some(errors),
+                ..Default::default()
+            },
+        }
+    }
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+extern "C" {
+    /// Metadata as returned by `loadMetadata`. Converted with `serde-wasm-bindgen` rather than
+    /// through JSON, so the thumbnail is a `Uint8Array`.
+    #[wasm_bindgen(typescript_type = "Metadata")]
+    pub type MetadataValue;
+}
+
+#[cfg(feature = "wasm")]
+impl Metadata {
+    pub(crate) fn to_value(&self) -> Result<MetadataValue, JsValue> {
+        self.serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+            .map(JsCast::unchecked_into)
+            .map_err(JsValue::from)
+    }
+
+    pub(crate) fn from_value(value: &MetadataValue) -> Result<Self, WasmImageError> {
+        serde_wasm_bindgen::from_value(value.into())
+            .map_err(|e| WasmImageError::InvalidArgument(e.to_string()))
+    }
+}
+
 impl TryFrom<RawSourceImage<'_>> for Metadata {
     type Error = WasmImageError;
 
     fn try_from(img: RawSourceImage) -> Result<Self, Self::Error> {
         match img {
+            RawSourceImage::Raster(img, ImageFormat::Dds) => {
+                let (width, height) = dds_dimensions(img)?;
+                Ok(Self {
+                    width,
+                    height,
+                    texture: Some(dds_info(img)?),
+                    ..Default::default()
+                })
+            }
             RawSourceImage::Raster(img, format) => {
                 let decoder = get_decoder(format, img).ok();
 
@@ -142,7 +284,6 @@ impl TryFrom<RawSourceImage<'_>> for Metadata {
                     reader.continue_on_error(true);
 
                     let exif = match format {
-                        // Also HEIF, HEIC, not supported by image-rs though
                         ImageFormat::Tiff
                         | ImageFormat::Jpeg
                         | ImageFormat::Avif
@@ -165,24 +306,17 @@ impl TryFrom<RawSourceImage<'_>> for Metadata {
                         }
                     };
 
-                    let (other, gps, errors) = match exif {
-                        Ok(exif) => {
-                            let (other, gps) = exif_to_hashmaps(&exif);
-                            (Some(other), gps, None)
-                        }
-                        Err(errors) => (None, None, Some(errors)),
+                    let pages = match format {
+                        ImageFormat::Tiff => tiff_pages(img).ok(),
+                        _ => None,
                     };
 
                     Self {
-                        width,
-                        height,
-                        other,
-                        gps,
-                        errors,
+                        pages,
+                        ..Self::from_exif(width, height, exif)
                     }
                 } else {
-                    let img = image::load_from_memory_with_format(img, format)
-                        .map_err(WasmImageError::LibError)?;
+                    let img = decode(img, format)?;
                     let (width, height) = img.dimensions();
                     Self {
                         width,
@@ -194,7 +328,12 @@ impl TryFrom<RawSourceImage<'_>> for Metadata {
                 Ok(metadata)
             }
             RawSourceImage::Svg(svg) => {
-                let tree = resvg::usvg::Tree::from_data(svg, &Options::default())?;
+                let svg = if svg.starts_with(&GZIP_MAGIC) {
+                    Cow::Owned(decompress_svgz(svg)?)
+                } else {
+                    Cow::Borrowed(svg)
+                };
+                let tree = crate::convert::svg::parse_svg(&svg, &Options::default())?;
                 let size = tree.size();
                 let (width, height) = (size.width() as u32, size.height() as u32);
                 Ok(Self {
@@ -203,39 +342,118 @@ impl TryFrom<RawSourceImage<'_>> for Metadata {
                     ..Default::default()
                 })
             }
+            #[cfg(feature = "heic")]
+            RawSourceImage::Heif(img) => {
+                let (width, height) = heif_dimensions(img)?;
+
+                // The EXIF item in the `meta` box holds a plain TIFF block.
+                let exif = match heif_exif(img) {
+                    Ok(Some(exif)) => read_raw_exif(exif),
+                    Ok(None) => Err(Vec::new()),
+                    Err(e) => Err(vec![e.to_string()]),
+                };
+
+                Ok(Self::from_exif(width, height, exif))
+            }
+            #[cfg(feature = "jxl")]
+            RawSourceImage::Jxl(img) => {
+                let (width, height) = jxl_dimensions(img)?;
+
+                let exif = match jxl_exif(img) {
+                    Ok(Some(exif)) => read_raw_exif(exif),
+                    Ok(None) => Err(Vec::new()),
+                    Err(e) => Err(vec![e.to_string()]),
+                };
+
+                Ok(Self::from_exif(width, height, exif))
+            }
+            #[cfg(feature = "raw")]
+            RawSourceImage::Raw(img) => {
+                let (width, height) = raw_dimensions(img)?;
+
+                // TIFF-based RAW files are one big EXIF block, including the maker note.
+                Ok(Self::from_exif(width, height, read_raw_exif(img.to_vec())))
+            }
         }
     }
 }
 
+/// Size of the first read of the file, enough for the header of most JPEG files.
+#[cfg(feature = "wasm")]
+const HEADER_READ_LEN: usize = 256 * 1024;
+
+/// The header of a JPEG file, terminated so it reads as a complete image without pixel data.
+/// `None` if the file isn't a JPEG or the header doesn't end within `prefix`.
+#[cfg(any(feature = "wasm", test))]
+fn jpeg_header(prefix: &[u8]) -> Option<Vec<u8>> {
+    if !prefix.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut header = prefix[..jpeg_header_len(prefix)?].to_vec();
+    // End of image, so parsers looking for more segments stop here.
+    header.extend_from_slice(&[0xFF, 0xD9]);
+    Some(header)
+}
+
+/// Reads what metadata extraction needs: only the header of JPEG files, the whole file otherwise.
+#[cfg(feature = "wasm")]
+fn read_metadata_bytes(input: &Input) -> Result<Vec<u8>, WasmImageError> {
+    let mut len = HEADER_READ_LEN;
+    loop {
+        let prefix = input.read_prefix(len)?;
+        if prefix.len() == input.len() {
+            return Ok(prefix);
+        }
+        if !prefix.starts_with(&[0xFF, 0xD8]) {
+            return input.read();
+        }
+        if let Some(header) = jpeg_header(&prefix) {
+            return Ok(header);
+        }
+        len *= 4;
+    }
+}
+
 #[cfg(feature = "wasm")]
 #[wasm_bindgen(js_name = loadMetadata)]
+/// Extract dimensions and EXIF metadata from an image file.
+/// # Arguments
+/// * `file` - The image file bytes, or a `Blob`. Only the header of JPEG files is read.
+/// * `src_type` - The MIME type of the source image.
+/// * `cb` - A callback function to report progress.
+/// # Errors
+/// Returns an error if the image could not be loaded.
 pub fn load_metadata(
-    file: &Uint8Array,
+    file: &ImageInput,
     src_type: &str,
     cb: &js_sys::Function,
-) -> Result<Metadata, JsValue> {
-    let src_type = SourceType::from_mime_type(src_type);
-
+) -> Result<MetadataValue, JsValue> {
     crate::progress::report(cb, 10.0, "Starting metadata extraction");
-    let file = file.to_vec();
+    let file = read_metadata_bytes(&Input::new(file)?)?;
+    let src_type = resolve_mime_type(&file, src_type).map_err(JsValue::from)?;
+    let src_type = SourceType::from_mime_type(src_type);
     crate::progress::report(cb, 35.0, "Loading image");
 
-    let img = load_raw_image(&file, src_type.as_ref())
-        .map_err(|e| JsValue::from_str(e.to_string().as_str()))?;
+    let img = load_raw_image(&file, src_type.as_ref()).map_err(JsValue::from)?;
 
     crate::progress::report(cb, 65.0, "Extracting metadata");
 
-    let metadata =
-        Metadata::try_from(img).map_err(|e| JsValue::from_str(e.to_string().as_str()))?;
+    let metadata = Metadata::try_from(img).map_err(JsValue::from)?;
 
     crate::progress::report(cb, 100.0, "Metadata extraction complete");
 
-    Ok(metadata)
+    metadata.to_value()
 }
 
 #[cfg(not(feature = "wasm"))]
+/// Extract dimensions and EXIF metadata from an image file.
+/// # Arguments
+/// * `file` - The image file bytes.
+/// * `src_type` - The MIME type of the source image.
+/// # Errors
+/// Returns an error if the image could not be loaded.
 pub fn load_metadata(file: &[u8], src_type: &str) -> Result<Metadata, WasmImageError> {
-    let src_type = SourceType::from_mime_type(src_type);
+    let src_type = SourceType::from_mime_type(resolve_mime_type(file, src_type)?);
     let img = load_raw_image(file, src_type.as_ref())?;
     Metadata::try_from(img)
 }
@@ -272,6 +490,21 @@ mod tests {
         println!("{hashmap:?}");
     }
 
+    #[cfg(not(feature = "wasm"))]
+    #[test]
+    fn reads_metadata_from_jpeg_header() {
+        use super::{jpeg_header, load_metadata};
+
+        let file = include_bytes!("../../assets/test.jpeg");
+        let header = jpeg_header(file).expect("No JPEG header");
+        assert!(header.len() < file.len());
+        assert!(jpeg_header(include_bytes!("../../assets/exif.webp")).is_none());
+
+        let full = serde_json::to_value(load_metadata(file, "image/jpeg").unwrap()).unwrap();
+        let partial = serde_json::to_value(load_metadata(&header, "image/jpeg").unwrap()).unwrap();
+        assert_eq!(full, partial);
+    }
+
     #[test]
     fn test_load_webp_metadata() {
         let file = include_bytes!("../../assets/exif.webp");
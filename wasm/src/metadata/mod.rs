@@ -1,11 +1,27 @@
+pub(crate) mod container;
 mod editable;
 mod extract;
 mod presets;
 mod save;
+mod sidecar;
+mod thumbnail;
+mod timestamps;
+mod transplant;
+mod validate;
 
-pub use extract::{load_metadata, Metadata};
+pub use extract::{load_metadata, Metadata, MetadataField};
 pub use presets::MetadataPresets;
 pub use save::MetadataChange;
+pub use thumbnail::ThumbnailAction;
+
+pub use save::save_metadata;
+pub use sidecar::{xmp_sidecar_to_changes, SIDECAR_SCHEMA_VERSION};
+pub use timestamps::shift_timestamps;
+pub use transplant::{transplant_metadata, TransplantOptions};
+pub use validate::{validate_image, Severity, ValidationIssue, ValidationReport};
 
 #[cfg(feature = "wasm")]
-pub use save::{save_metadata, MetadataChanges};
+pub use {extract::MetadataValue, save::MetadataChanges};
+
+#[cfg(not(feature = "wasm"))]
+pub use presets::metadata_presets;
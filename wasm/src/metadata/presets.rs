@@ -87,6 +87,13 @@ pub fn metadata_presets() -> MetadataPresets {
     build_metadata_presets()
 }
 
+#[cfg(not(feature = "wasm"))]
+#[must_use]
+/// Tag descriptions grouped into the presets offered by the metadata editor.
+pub fn metadata_presets() -> MetadataPresets {
+    build_metadata_presets()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
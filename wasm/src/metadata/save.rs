@@ -5,26 +5,42 @@ use little_exif::{
     exif_tag::ExifTag, filetype::FileExtension, metadata::Metadata as LittleExifMetadata,
 };
 
-use super::editable::{description_to_exif_tag, tag_for_remove};
+use super::editable::{editable_exif_tag, parse_ifd, resolve_tag, tag_for_remove};
+use super::thumbnail::{apply_thumbnail_action, ThumbnailAction};
+use crate::convert::settings::WebPSettings;
+use crate::convert::webp::{encode_webp, reencode_settings};
+use crate::detect::resolve_mime_type;
 use crate::error::WasmImageError;
+use crate::limits::decode;
 
 #[cfg(feature = "wasm")]
-use {js_sys::Uint8Array, wasm_bindgen::prelude::*};
+use {
+    crate::input::{ImageInput, Input},
+    js_sys::Uint8Array,
+    wasm_bindgen::prelude::*,
+};
 
-#[cfg_attr(feature = "wasm", derive(tsify::Tsify, serde::Deserialize))]
-#[cfg_attr(feature = "wasm", tsify(from_wasm_abi))]
-#[cfg_attr(not(feature = "wasm"), derive(serde::Deserialize))]
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[cfg_attr(feature = "wasm", tsify(from_wasm_abi, into_wasm_abi))]
+#[derive(serde::Deserialize, serde::Serialize, Debug, PartialEq, Eq)]
 pub struct MetadataChange {
+    /// Tag name (`DateTimeOriginal`), tag number (`0x9003`) or, for backwards
+    /// compatibility, the tag description shown by `load_metadata`.
     pub tag: String,
+    /// IFD the tag lives in (`tiff`, `exif`, `gps`, `interop`). Only needed to
+    /// disambiguate numeric tags.
+    #[serde(default)]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub ifd: Option<String>,
     pub value: Option<String>,
 }
 
 #[cfg(feature = "wasm")]
-#[derive(tsify::Tsify, serde::Deserialize)]
-#[tsify(from_wasm_abi)]
+#[derive(tsify::Tsify, serde::Deserialize, serde::Serialize)]
+#[tsify(from_wasm_abi, into_wasm_abi)]
 pub struct MetadataChanges(pub Vec<MetadataChange>);
 
-fn file_ext_for_format(format: ImageFormat) -> Option<FileExtension> {
+pub(super) fn file_ext_for_format(format: ImageFormat) -> Option<FileExtension> {
     match format {
         ImageFormat::Jpeg => Some(FileExtension::JPEG),
         ImageFormat::Png => Some(FileExtension::PNG {
@@ -36,7 +52,7 @@ fn file_ext_for_format(format: ImageFormat) -> Option<FileExtension> {
     }
 }
 
-fn strip_gps_tags(exif_meta: &mut LittleExifMetadata) {
+pub(super) fn strip_gps_tags(exif_meta: &mut LittleExifMetadata) {
     for tag in [
         ExifTag::GPSVersionID(vec![]),
         ExifTag::GPSLatitudeRef(String::new()),
@@ -65,14 +81,22 @@ fn strip_gps_tags(exif_meta: &mut LittleExifMetadata) {
 
 fn apply_tag_changes(exif_meta: &mut LittleExifMetadata, changes: &[MetadataChange]) {
     for change in changes {
+        let ifd = change.ifd.as_deref().map(parse_ifd);
+        if ifd == Some(None) {
+            // Unknown IFD name: skip the change rather than guess where it belongs.
+            continue;
+        }
+        let Some(tag) = resolve_tag(&change.tag, ifd.flatten()) else {
+            continue;
+        };
         match &change.value {
             Some(value) => {
-                if let Some(tag) = description_to_exif_tag(&change.tag, value.clone()) {
+                if let Some(tag) = editable_exif_tag(tag, value.clone()) {
                     exif_meta.set_tag(tag);
                 }
             }
             None => {
-                if let Some(tag) = tag_for_remove(&change.tag) {
+                if let Some(tag) = tag_for_remove(tag) {
                     exif_meta.remove_tag(tag);
                 }
             }
@@ -84,9 +108,13 @@ fn apply_tag_changes(exif_meta: &mut LittleExifMetadata, changes: &[MetadataChan
 fn encode_image_to_vec(
     img: &image::DynamicImage,
     format: ImageFormat,
+    webp: Option<WebPSettings>,
 ) -> Result<Vec<u8>, WasmImageError> {
     let mut output: Vec<u8> = Vec::new();
     match format {
+        ImageFormat::WebP => {
+            output = encode_webp(img, &webp.unwrap_or_default(), None, None)?;
+        }
         ImageFormat::Jpeg => {
             let mut encoder = codecs::jpeg::JpegEncoder::new_with_quality(&mut output, 90);
             encoder
@@ -114,46 +142,58 @@ fn encode_image_to_vec(
     Ok(output)
 }
 
-/// Re-encodes the image and embeds EXIF via `ImageEncoder::set_exif_metadata`.
+/// Re-encodes the image and embeds EXIF via `ImageEncoder::set_exif_metadata` and an
+/// ICC profile via `ImageEncoder::set_icc_profile`.
 /// `exif` must be raw TIFF-structured bytes (the output of `LittleExifMetadata::encode()`).
 /// The encoder is responsible for wrapping them in the format-specific container
 /// (APP1 for JPEG, eXIf chunk for PNG, EXIF chunk for WebP).
-/// PNG and WebP are supported. TIFF falls back to re-encode without EXIF.
-fn encode_image_with_exif_to_vec(
+/// PNG and WebP support both. TIFF only embeds the ICC profile.
+/// WebP is encoded with `webp`, or losslessly if it is `None`.
+pub(super) fn encode_image_with_metadata_to_vec(
     img: &image::DynamicImage,
     format: ImageFormat,
-    exif: Vec<u8>,
+    webp: Option<WebPSettings>,
+    exif: Option<Vec<u8>>,
+    icc: Option<Vec<u8>>,
 ) -> Result<Vec<u8>, WasmImageError> {
+    fn write_with<E: ImageEncoder>(
+        mut encoder: E,
+        img: &image::DynamicImage,
+        exif: Option<Vec<u8>>,
+        icc: Option<Vec<u8>>,
+    ) -> Result<(), WasmImageError> {
+        if let Some(exif) = exif {
+            let _ = encoder.set_exif_metadata(exif);
+        }
+        if let Some(icc) = icc {
+            let _ = encoder.set_icc_profile(icc);
+        }
+        encoder
+            .write_image(
+                img.as_bytes(),
+                img.width(),
+                img.height(),
+                img.color().into(),
+            )
+            .map_err(WasmImageError::LibError)
+    }
+
     let mut output: Vec<u8> = Vec::new();
     match format {
         ImageFormat::Png => {
-            let mut encoder = codecs::png::PngEncoder::new(&mut output);
-            let _ = encoder.set_exif_metadata(exif);
-            encoder
-                .write_image(
-                    img.as_bytes(),
-                    img.width(),
-                    img.height(),
-                    img.color().into(),
-                )
-                .map_err(WasmImageError::LibError)?;
+            write_with(codecs::png::PngEncoder::new(&mut output), img, exif, icc)?;
         }
         ImageFormat::WebP => {
-            let mut encoder = codecs::webp::WebPEncoder::new_lossless(&mut output);
-            let _ = encoder.set_exif_metadata(exif);
-            encoder
-                .write_image(
-                    img.as_bytes(),
-                    img.width(),
-                    img.height(),
-                    img.color().into(),
-                )
-                .map_err(WasmImageError::LibError)?;
+            output = encode_webp(img, &webp.unwrap_or_default(), exif, icc)?;
         }
-        _ => {
-            // TIFF: set_exif_metadata is unsupported (returns UnsupportedError).
-            // Re-encode without EXIF. The frontend restricts editing to formats
+        ImageFormat::Tiff => {
+            // TIFF: set_exif_metadata is unsupported (returns UnsupportedError), so only
+            // the ICC profile survives. The frontend restricts EXIF editing to formats
             // that support set_exif_metadata, so this is a defensive fallback.
+            let mut cursor = Cursor::new(&mut output);
+            write_with(codecs::tiff::TiffEncoder::new(&mut cursor), img, None, icc)?;
+        }
+        _ => {
             img.write_to(&mut Cursor::new(&mut output), format)
                 .map_err(WasmImageError::LibError)?;
         }
@@ -161,74 +201,241 @@ fn encode_image_with_exif_to_vec(
     Ok(output)
 }
 
+/// Re-encodes the image to strip all metadata. The image crate never preserves EXIF
+/// when encoding, and re-encoding also clears XMP, IPTC, and other non-EXIF segments
+/// embedded in APP markers.
+fn strip_all_metadata(
+    file: &[u8],
+    format: ImageFormat,
+    progress: impl Fn(f64, &str),
+) -> Result<Vec<u8>, WasmImageError> {
+    progress(30.0, "Loading image");
+    let img = decode(file, format)?;
+    progress(70.0, "Encoding image");
+    encode_image_to_vec(&img, format, reencode_settings(file))
+}
+
+/// The EXIF edits requested from `save_metadata`.
+#[derive(Clone, Copy)]
+pub(super) struct ExifEdits<'a> {
+    pub changes: &'a [MetadataChange],
+    pub strip_gps: bool,
+    pub thumbnail: ThumbnailAction,
+}
+
+/// Reads the EXIF block of `file` and applies `edits` to it. `img` is only decoded
+/// when the thumbnail is regenerated.
+fn edited_exif(
+    file: &[u8],
+    file_ext: FileExtension,
+    edits: ExifEdits,
+    img: impl FnOnce() -> Result<image::DynamicImage, WasmImageError>,
+) -> Result<LittleExifMetadata, WasmImageError> {
+    let mut exif_meta = LittleExifMetadata::new_from_vec(&file.to_vec(), file_ext)
+        .unwrap_or_else(|_| LittleExifMetadata::new());
+    apply_tag_changes(&mut exif_meta, edits.changes);
+    if edits.strip_gps {
+        strip_gps_tags(&mut exif_meta);
+    }
+    apply_thumbnail_action(&mut exif_meta, edits.thumbnail, img)?;
+    Ok(exif_meta)
+}
+
+/// JPEG metadata edit: write EXIF directly into a copy of the original bytes.
+/// `little_exif`'s `write_to_vec` supports JPEG, and skipping re-encode preserves the
+/// original quantization tables exactly.
+fn edit_jpeg_metadata(
+    file: &[u8],
+    file_ext: FileExtension,
+    edits: ExifEdits,
+) -> Result<Vec<u8>, WasmImageError> {
+    let exif_meta = edited_exif(file, file_ext, edits, || decode(file, ImageFormat::Jpeg))?;
+    let mut output = file.to_vec();
+    exif_meta
+        .write_to_vec(&mut output, file_ext)
+        .map_err(|e| WasmImageError::EncodingError(e.to_string()))?;
+    Ok(output)
+}
+
+/// Builds the edited EXIF block for formats that are re-encoded on save.
+fn edited_exif_bytes(
+    file: &[u8],
+    file_ext: FileExtension,
+    edits: ExifEdits,
+    img: &image::DynamicImage,
+) -> Result<Vec<u8>, WasmImageError> {
+    edited_exif(file, file_ext, edits, || Ok(img.clone()))?
+        .encode()
+        .map_err(|e| WasmImageError::EncodingError(e.to_string()))
+}
+
+/// The format of a file whose metadata is edited, checked against its content.
+pub(super) fn editable_format(
+    file: &[u8],
+    src_type: &str,
+) -> Result<(ImageFormat, FileExtension), WasmImageError> {
+    ImageFormat::from_mime_type(resolve_mime_type(file, src_type)?)
+        .and_then(|format| file_ext_for_format(format).map(|ext| (format, ext)))
+        .ok_or_else(|| {
+            WasmImageError::EncodingError(
+                "Metadata editing is not supported for this format".to_string(),
+            )
+        })
+}
+
 #[cfg(feature = "wasm")]
 #[wasm_bindgen(js_name = saveMetadata)]
 #[allow(clippy::needless_pass_by_value)]
+/// Apply metadata changes to an image, preserving its format.
+/// # Arguments
+/// * `file` - The image file bytes, or a `Blob`.
+/// * `src_type` - The MIME type of the source image (JPEG, PNG, WebP or TIFF).
+/// * `changes` - Tags to set (`value` present) or remove (`value` null).
+/// * `strip_all` - Remove all metadata, ignoring `changes`.
+/// * `strip_gps` - Remove all GPS tags.
+/// * `thumbnail` - Keep (default), remove or regenerate the EXIF thumbnail.
+/// * `cb` - A callback function to report progress.
+/// # Errors
+/// Returns an error if the format does not support metadata editing or encoding fails.
 pub fn save_metadata(
-    file: &Uint8Array,
+    file: &ImageInput,
     src_type: &str,
     changes: MetadataChanges,
     strip_all: bool,
     strip_gps: bool,
+    thumbnail: Option<ThumbnailAction>,
     cb: &js_sys::Function,
 ) -> Result<Uint8Array, JsValue> {
-    let format = ImageFormat::from_mime_type(src_type)
-        .ok_or_else(|| JsValue::from_str("Metadata editing is not supported for this format"))?;
+    let edits = ExifEdits {
+        changes: &changes.0,
+        strip_gps,
+        thumbnail: thumbnail.unwrap_or_default(),
+    };
+    crate::progress::report(cb, 10.0, "Starting metadata save");
 
-    let file_ext = file_ext_for_format(format)
-        .ok_or_else(|| JsValue::from_str("Metadata editing is not supported for this format"))?;
+    let file_bytes = Input::new(file)?.read()?;
+    let output = rewrite_metadata(
+        &file_bytes,
+        src_type,
+        strip_all,
+        edits,
+        |progress, message| {
+            crate::progress::report(cb, progress, message);
+        },
+    )?;
 
-    crate::progress::report(cb, 10.0, "Starting metadata save");
+    crate::progress::report(cb, 100.0, "Metadata save complete");
+
+    Ok(Uint8Array::from(output.as_slice()))
+}
+
+/// Applies `edits` to a JPEG, PNG, WebP or TIFF file and returns the re-written file.
+pub(super) fn write_metadata_changes(
+    file: &[u8],
+    src_type: &str,
+    edits: ExifEdits,
+) -> Result<Vec<u8>, WasmImageError> {
+    rewrite_metadata(file, src_type, false, edits, |_, _| ())
+}
 
-    let file_bytes = file.to_vec();
-
-    let output = if strip_all {
-        // Re-encode to strip all metadata. The image crate never preserves EXIF
-        // when encoding, and re-encoding also clears XMP, IPTC, and other
-        // non-EXIF segments embedded in APP markers.
-        crate::progress::report(cb, 30.0, "Loading image");
-        let img = image::load_from_memory_with_format(&file_bytes, format)
-            .map_err(|e| JsValue::from_str(&e.to_string()))?;
-        crate::progress::report(cb, 70.0, "Encoding image");
-        encode_image_to_vec(&img, format).map_err(|e| JsValue::from_str(&e.to_string()))?
+/// Strips all metadata from `file`, or applies `edits` to it. `progress` is called with the
+/// percentage and description of each step.
+fn rewrite_metadata(
+    file: &[u8],
+    src_type: &str,
+    strip_all: bool,
+    edits: ExifEdits,
+    progress: impl Fn(f64, &str),
+) -> Result<Vec<u8>, WasmImageError> {
+    let (format, file_ext) = editable_format(file, src_type)?;
+
+    if strip_all {
+        strip_all_metadata(file, format, progress)
     } else if format == ImageFormat::Jpeg {
-        // JPEG metadata edit: write EXIF directly into a copy of the original
-        // bytes. little_exif's write_to_vec supports JPEG, and skipping
-        // re-encode preserves the original quantization tables exactly.
-        crate::progress::report(cb, 40.0, "Applying metadata changes");
-        let mut output = file_bytes.clone();
-        let mut exif_meta = LittleExifMetadata::new_from_vec(&file_bytes, file_ext.clone())
-            .unwrap_or_else(|_| LittleExifMetadata::new());
-        apply_tag_changes(&mut exif_meta, &changes.0);
-        if strip_gps {
-            strip_gps_tags(&mut exif_meta);
-        }
-        exif_meta
-            .write_to_vec(&mut output, file_ext)
-            .map_err(|e| JsValue::from_str(&e.to_string()))?;
-        output
+        progress(40.0, "Applying metadata changes");
+        edit_jpeg_metadata(file, file_ext, edits)
     } else {
         // Non-JPEG metadata edit: re-encode and embed EXIF via set_exif_metadata.
         // PNG and WebP both implement this. TIFF falls back to re-encode only.
-        crate::progress::report(cb, 30.0, "Loading image");
-        let img = image::load_from_memory_with_format(&file_bytes, format)
-            .map_err(|e| JsValue::from_str(&e.to_string()))?;
-        crate::progress::report(cb, 55.0, "Applying metadata changes");
-        let mut exif_meta = LittleExifMetadata::new_from_vec(&file_bytes, file_ext)
-            .unwrap_or_else(|_| LittleExifMetadata::new());
-        apply_tag_changes(&mut exif_meta, &changes.0);
-        if strip_gps {
-            strip_gps_tags(&mut exif_meta);
-        }
-        let exif_bytes = exif_meta
-            .encode()
-            .map_err(|e| JsValue::from_str(&e.to_string()))?;
-        crate::progress::report(cb, 80.0, "Encoding image");
-        encode_image_with_exif_to_vec(&img, format, exif_bytes)
-            .map_err(|e| JsValue::from_str(&e.to_string()))?
+        progress(30.0, "Loading image");
+        let img = decode(file, format)?;
+        progress(55.0, "Applying metadata changes");
+        let exif_bytes = edited_exif_bytes(file, file_ext, edits, &img)?;
+        progress(80.0, "Encoding image");
+        encode_image_with_metadata_to_vec(
+            &img,
+            format,
+            reencode_settings(file),
+            Some(exif_bytes),
+            None,
+        )
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+/// Apply metadata changes to an image, preserving its format.
+/// # Arguments
+/// * `file` - The image file bytes.
+/// * `src_type` - The MIME type of the source image (JPEG, PNG, WebP or TIFF).
+/// * `changes` - Tags to set (`value` present) or remove (`value` `None`).
+/// * `strip_all` - Remove all metadata, ignoring `changes`.
+/// * `strip_gps` - Remove all GPS tags.
+/// * `thumbnail` - Keep, remove or regenerate the EXIF thumbnail.
+/// # Errors
+/// Returns an error if the format does not support metadata editing or encoding fails.
+pub fn save_metadata(
+    file: &[u8],
+    src_type: &str,
+    changes: &[MetadataChange],
+    strip_all: bool,
+    strip_gps: bool,
+    thumbnail: ThumbnailAction,
+) -> Result<Vec<u8>, WasmImageError> {
+    let edits = ExifEdits {
+        changes,
+        strip_gps,
+        thumbnail,
     };
+    rewrite_metadata(file, src_type, strip_all, edits, |_, _| ())
+}
 
-    crate::progress::report(cb, 100.0, "Metadata save complete");
+#[cfg(all(test, not(feature = "wasm")))]
+mod tests {
+    use super::*;
+    use crate::metadata::load_metadata;
 
-    Ok(Uint8Array::from(output.as_slice()))
+    #[test]
+    fn save_metadata_accepts_tag_ids() {
+        let file = include_bytes!("../../assets/test.jpeg");
+        let changes = [
+            MetadataChange {
+                tag: "Artist".to_string(),
+                ifd: None,
+                value: Some("re;file labs".to_string()),
+            },
+            MetadataChange {
+                tag: "0x8298".to_string(),
+                ifd: Some("tiff".to_string()),
+                value: Some("MIT".to_string()),
+            },
+        ];
+
+        let output = save_metadata(
+            file,
+            "image/jpeg",
+            &changes,
+            false,
+            false,
+            ThumbnailAction::Keep,
+        )
+        .unwrap();
+        let fields = load_metadata(&output, "image/jpeg")
+            .unwrap()
+            .fields
+            .unwrap();
+
+        let value_of = |id: &str| fields.iter().find(|f| f.id == id).map(|f| f.value.clone());
+        assert_eq!(value_of("Artist").as_deref(), Some("\"re;file labs\""));
+        assert_eq!(value_of("Copyright").as_deref(), Some("\"MIT\""));
+    }
 }
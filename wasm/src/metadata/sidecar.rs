@@ -0,0 +1,640 @@
+//! Sidecar export of [`Metadata`] (versioned JSON, CSV rows and `.xmp` files) and import of
+//! `.xmp` sidecars into [`MetadataChange`]s for `save_metadata`.
+
+use std::fmt::Write;
+
+use quick_xml::{
+    events::{BytesStart, Event},
+    name::ResolveResult,
+    NsReader,
+};
+
+use super::editable::{ifd_name, tag_id, DELETABLE_TAGS, EDITABLE_TAGS};
+use super::extract::{Metadata, MetadataField};
+use super::presets::PRESET_GPS;
+use super::save::MetadataChange;
+use super::timestamps::{change_for, format_exif_datetime, parse_exif_datetime, parse_timezone};
+use crate::error::WasmImageError;
+
+#[cfg(feature = "wasm")]
+use {
+    super::{extract::MetadataValue, save::MetadataChanges},
+    wasm_bindgen::prelude::*,
+};
+
+/// Version of the JSON sidecar schema. Bumped on incompatible changes.
+pub const SIDECAR_SCHEMA_VERSION: u32 = 1;
+
+const NS_DC: &str = "http://purl.org/dc/elements/1.1/";
+const NS_XMP: &str = "http://ns.adobe.com/xap/1.0/";
+const NS_TIFF: &str = "http://ns.adobe.com/tiff/1.0/";
+const NS_EXIF: &str = "http://ns.adobe.com/exif/1.0/";
+const NS_EXIF_EX: &str = "http://cipa.jp/exif/1.0/";
+
+const NAMESPACES: &[(&str, &str)] = &[
+    ("dc", NS_DC),
+    ("xmp", NS_XMP),
+    ("tiff", NS_TIFF),
+    ("exif", NS_EXIF),
+    ("exifEX", NS_EXIF_EX),
+];
+
+#[derive(Clone, Copy)]
+enum XmpValue {
+    Text,
+    /// `dc:creator`: an ordered `rdf:Seq`.
+    Seq,
+    /// `dc:description`, `dc:rights`: a language alternative (`rdf:Alt`).
+    LangAlt,
+    /// An ISO 8601 date that also carries the sub-second and UTC offset tags.
+    Date {
+        subsec: exif::Tag,
+        offset: exif::Tag,
+    },
+}
+
+struct XmpProperty {
+    tag: exif::Tag,
+    ns: &'static str,
+    name: &'static str,
+    value: XmpValue,
+}
+
+/// XMP properties for the editable EXIF tags, following the mapping of the Metadata Working
+/// Group guidelines. The `SubSecTime*` and `OffsetTime*` tags are folded into the dates.
+static XMP_PROPERTIES: &[XmpProperty] = &[
+    XmpProperty {
+        tag: exif::Tag::ImageDescription,
+        ns: NS_DC,
+        name: "description",
+        value: XmpValue::LangAlt,
+    },
+    XmpProperty {
+        tag: exif::Tag::Make,
+        ns: NS_TIFF,
+        name: "Make",
+        value: XmpValue::Text,
+    },
+    XmpProperty {
+        tag: exif::Tag::Model,
+        ns: NS_TIFF,
+        name: "Model",
+        value: XmpValue::Text,
+    },
+    XmpProperty {
+        tag: exif::Tag::Software,
+        ns: NS_XMP,
+        name: "CreatorTool",
+        value: XmpValue::Text,
+    },
+    XmpProperty {
+        tag: exif::Tag::DateTime,
+        ns: NS_XMP,
+        name: "ModifyDate",
+        value: XmpValue::Date {
+            subsec: exif::Tag::SubSecTime,
+            offset: exif::Tag::OffsetTime,
+        },
+    },
+    XmpProperty {
+        tag: exif::Tag::Artist,
+        ns: NS_DC,
+        name: "creator",
+        value: XmpValue::Seq,
+    },
+    XmpProperty {
+        tag: exif::Tag::Copyright,
+        ns: NS_DC,
+        name: "rights",
+        value: XmpValue::LangAlt,
+    },
+    XmpProperty {
+        tag: exif::Tag::DateTimeOriginal,
+        ns: NS_EXIF,
+        name: "DateTimeOriginal",
+        value: XmpValue::Date {
+            subsec: exif::Tag::SubSecTimeOriginal,
+            offset: exif::Tag::OffsetTimeOriginal,
+        },
+    },
+    XmpProperty {
+        tag: exif::Tag::DateTimeDigitized,
+        ns: NS_XMP,
+        name: "CreateDate",
+        value: XmpValue::Date {
+            subsec: exif::Tag::SubSecTimeDigitized,
+            offset: exif::Tag::OffsetTimeDigitized,
+        },
+    },
+    XmpProperty {
+        tag: exif::Tag::SpectralSensitivity,
+        ns: NS_EXIF,
+        name: "SpectralSensitivity",
+        value: XmpValue::Text,
+    },
+    XmpProperty {
+        tag: exif::Tag::RelatedSoundFile,
+        ns: NS_EXIF,
+        name: "RelatedSoundFile",
+        value: XmpValue::Text,
+    },
+    XmpProperty {
+        tag: exif::Tag::ImageUniqueID,
+        ns: NS_EXIF,
+        name: "ImageUniqueID",
+        value: XmpValue::Text,
+    },
+    XmpProperty {
+        tag: exif::Tag::CameraOwnerName,
+        ns: NS_EXIF_EX,
+        name: "CameraOwnerName",
+        value: XmpValue::Text,
+    },
+    XmpProperty {
+        tag: exif::Tag::BodySerialNumber,
+        ns: NS_EXIF_EX,
+        name: "BodySerialNumber",
+        value: XmpValue::Text,
+    },
+    XmpProperty {
+        tag: exif::Tag::LensMake,
+        ns: NS_EXIF_EX,
+        name: "LensMake",
+        value: XmpValue::Text,
+    },
+    XmpProperty {
+        tag: exif::Tag::LensModel,
+        ns: NS_EXIF_EX,
+        name: "LensModel",
+        value: XmpValue::Text,
+    },
+    XmpProperty {
+        tag: exif::Tag::LensSerialNumber,
+        ns: NS_EXIF_EX,
+        name: "LensSerialNumber",
+        value: XmpValue::Text,
+    },
+];
+
+#[derive(serde::Serialize)]
+struct JsonSidecar<'a> {
+    schema_version: u32,
+    width: u32,
+    height: u32,
+    fields: &'a [MetadataField],
+    has_thumbnail: bool,
+    errors: &'a [String],
+}
+
+fn xml_escape(value: &str) -> String {
+    quick_xml::escape::escape(value).into_owned()
+}
+
+fn csv_escape(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Tags that get a column in the CSV report, in column order.
+fn csv_tags() -> impl Iterator<Item = exif::Tag> {
+    EDITABLE_TAGS
+        .iter()
+        .map(|e| e.tag)
+        .chain(DELETABLE_TAGS.iter().map(|e| e.tag))
+        .chain(PRESET_GPS.iter().copied())
+}
+
+/// `YYYY:MM:DD HH:MM:SS` plus optional sub-seconds and offset to ISO 8601. `None` for dates
+/// that don't exist, such as `2024:02:31`, so they are left out of the sidecar.
+fn exif_date_to_iso(date: &str, subsec: Option<&str>, offset: Option<&str>) -> Option<String> {
+    // Re-formatted so unpadded values like `2024:3:1 1:2:3` come out zero-padded.
+    let date = format_exif_datetime(parse_exif_datetime(date)?)?;
+    let mut iso = format!(
+        "{}-{}-{}T{}",
+        &date[0..4],
+        &date[5..7],
+        &date[8..10],
+        &date[11..19]
+    );
+    if let Some(subsec) = subsec
+        .map(str::trim)
+        .filter(|s| s.bytes().all(|b| b.is_ascii_digit()))
+    {
+        if !subsec.is_empty() {
+            let _ = write!(iso, ".{subsec}");
+        }
+    }
+    if let Some(offset) = offset.and_then(|o| parse_timezone(o.trim())) {
+        iso.push_str(&offset);
+    }
+    Some(iso)
+}
+
+/// Splits an ISO 8601 date into the EXIF date, sub-seconds and offset. Missing time
+/// components default to zero, as XMP allows dates without a time.
+fn iso_date_to_exif(iso: &str) -> Option<(String, Option<String>, Option<String>)> {
+    let iso = iso.trim();
+    let (date, time) = iso.split_once('T').unwrap_or((iso, ""));
+
+    let (time, offset) = if let Some(time) = time.strip_suffix('Z') {
+        (time, Some("+00:00".to_string()))
+    } else if let Some(pos) = time.find(['+', '-']) {
+        (&time[..pos], Some(parse_timezone(&time[pos..])?))
+    } else {
+        (time, None)
+    };
+    let (time, subsec) = match time.split_once('.') {
+        Some((time, subsec)) if subsec.bytes().all(|b| b.is_ascii_digit()) => {
+            (time, Some(subsec.to_string()))
+        }
+        Some(_) => return None,
+        None => (time, None),
+    };
+
+    let mut date_parts = date.split('-');
+    let year = date_parts.next()?;
+    let month = date_parts.next().unwrap_or("01");
+    let day = date_parts.next().unwrap_or("01");
+    let mut time_parts = time.split(':').filter(|s| !s.is_empty());
+    let hour = time_parts.next().unwrap_or("00");
+    let minute = time_parts.next().unwrap_or("00");
+    let second = time_parts.next().unwrap_or("00");
+
+    let exif = format!("{year}:{month}:{day} {hour}:{minute}:{second}");
+    let well_formed = year.len() == 4
+        && [month, day, hour, minute, second]
+            .iter()
+            .all(|s| s.len() == 2)
+        && parse_exif_datetime(&exif).is_some();
+    well_formed.then_some((exif, subsec, offset))
+}
+
+impl Metadata {
+    fn field(&self, tag: exif::Tag) -> Option<&MetadataField> {
+        let (id, ifd) = (tag_id(tag), ifd_name(tag.context()));
+        self.fields
+            .as_deref()?
+            .iter()
+            .find(|f| f.id == id && f.ifd == ifd)
+    }
+
+    fn field_text(&self, tag: exif::Tag) -> Option<&str> {
+        self.field(tag)
+            .map(|f| f.text.as_deref().unwrap_or(&f.value))
+    }
+
+    /// Serializes the metadata as a versioned JSON sidecar. The schema holds
+    /// `schema_version`, `width`, `height`, `fields` (as in [`Metadata::fields`]),
+    /// `has_thumbnail` and `errors`.
+    /// # Errors
+    /// Returns an error if serialization fails.
+    pub fn to_json_sidecar(&self) -> Result<String, WasmImageError> {
+        Ok(serde_json::to_string_pretty(&JsonSidecar {
+            schema_version: SIDECAR_SCHEMA_VERSION,
+            width: self.width,
+            height: self.height,
+            fields: self.fields.as_deref().unwrap_or_default(),
+            has_thumbnail: self.thumbnail.is_some(),
+            errors: self.errors.as_deref().unwrap_or_default(),
+        })?)
+    }
+
+    /// Header row matching [`Metadata::to_csv_row`]: `file`, `width`, `height` and one
+    /// column per editable, deletable and GPS tag, named by its tag id.
+    #[must_use]
+    pub fn csv_header() -> String {
+        [
+            "file".to_string(),
+            "width".to_string(),
+            "height".to_string(),
+        ]
+        .into_iter()
+        .chain(csv_tags().map(tag_id))
+        .collect::<Vec<_>>()
+        .join(",")
+    }
+
+    /// Flattens the metadata into one CSV row for batch reports. Missing tags are left empty.
+    #[must_use]
+    pub fn to_csv_row(&self, file_name: &str) -> String {
+        [
+            csv_escape(file_name),
+            self.width.to_string(),
+            self.height.to_string(),
+        ]
+        .into_iter()
+        .chain(csv_tags().map(|tag| csv_escape(self.field_text(tag).unwrap_or_default())))
+        .collect::<Vec<_>>()
+        .join(",")
+    }
+
+    /// Writes the editable EXIF tags as a standard `.xmp` sidecar.
+    #[must_use]
+    pub fn to_xmp_sidecar(&self) -> String {
+        let mut xmp = String::from(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+             <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n \
+             <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n  \
+             <rdf:Description rdf:about=\"\"",
+        );
+        for (prefix, ns) in NAMESPACES {
+            let _ = write!(xmp, "\n    xmlns:{prefix}=\"{ns}\"");
+        }
+        xmp.push('>');
+
+        for property in XMP_PROPERTIES {
+            let Some(text) = self.field_text(property.tag) else {
+                continue;
+            };
+            let prefix = NAMESPACES
+                .iter()
+                .find(|(_, ns)| *ns == property.ns)
+                .map_or("", |(prefix, _)| prefix);
+            let name = format!("{prefix}:{}", property.name);
+            let value = match property.value {
+                XmpValue::Text => xml_escape(text),
+                XmpValue::Seq => {
+                    format!("<rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq>", xml_escape(text))
+                }
+                XmpValue::LangAlt => format!(
+                    "<rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt>",
+                    xml_escape(text)
+                ),
+                XmpValue::Date { subsec, offset } => {
+                    let Some(date) =
+                        exif_date_to_iso(text, self.field_text(subsec), self.field_text(offset))
+                    else {
+                        continue;
+                    };
+                    date
+                }
+            };
+            let _ = write!(xmp, "\n   <{name}>{value}</{name}>");
+        }
+
+        xmp.push_str("\n  </rdf:Description>\n </rdf:RDF>\n</x:xmpmeta>\n<?xpacket end=\"w\"?>\n");
+        xmp
+    }
+}
+
+fn xmp_error(message: &impl ToString) -> WasmImageError {
+    WasmImageError::DecoderError("XMP".to_string(), message.to_string())
+}
+
+fn find_property(ns: &ResolveResult, local: &[u8]) -> Option<usize> {
+    let ResolveResult::Bound(ns) = ns else {
+        return None;
+    };
+    XMP_PROPERTIES
+        .iter()
+        .position(|p| p.ns.as_bytes() == ns.as_ref() && p.name.as_bytes() == local)
+}
+
+/// Collects properties given in the short form, as attributes of `rdf:Description`.
+fn attribute_properties(
+    reader: &NsReader<&[u8]>,
+    element: &BytesStart,
+    values: &mut [Vec<String>],
+) -> Result<(), WasmImageError> {
+    for attr in element.attributes() {
+        let attr = attr.map_err(|e| xmp_error(&e))?;
+        let (ns, local) = reader.resolve_attribute(attr.key);
+        if let Some(index) = find_property(&ns, local.as_ref()) {
+            let value = attr.unescape_value().map_err(|e| xmp_error(&e))?;
+            values[index].push(value.into_owned());
+        }
+    }
+    Ok(())
+}
+
+/// Parses an `.xmp` sidecar into changes for `save_metadata`. Properties without an EXIF
+/// counterpart are ignored; for language alternatives the first entry is used.
+/// # Errors
+/// Returns an error if the XMP is not well-formed or holds an invalid date.
+pub fn xmp_sidecar_to_changes(xmp: &str) -> Result<Vec<MetadataChange>, WasmImageError> {
+    let mut reader = NsReader::from_str(xmp);
+    let mut values: Vec<Vec<String>> = vec![Vec::new(); XMP_PROPERTIES.len()];
+    // Property currently being read, with the element depth it was opened at.
+    let mut current: Option<(usize, usize)> = None;
+    let mut depth = 0;
+
+    loop {
+        let (ns, event) = reader.read_resolved_event().map_err(|e| xmp_error(&e))?;
+        match event {
+            Event::Start(element) => {
+                depth += 1;
+                if current.is_none() {
+                    current = find_property(&ns, element.local_name().as_ref())
+                        .map(|index| (index, depth));
+                }
+                attribute_properties(&reader, &element, &mut values)?;
+            }
+            Event::Empty(element) => attribute_properties(&reader, &element, &mut values)?,
+            Event::Text(text) => {
+                if let Some((index, _)) = current {
+                    let text = text.unescape().map_err(|e| xmp_error(&e))?;
+                    if !text.trim().is_empty() {
+                        values[index].push(text.trim().to_string());
+                    }
+                }
+            }
+            Event::End(_) => {
+                if current.is_some_and(|(_, start)| start == depth) {
+                    current = None;
+                }
+                depth -= 1;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    let mut changes = Vec::new();
+    for (property, values) in XMP_PROPERTIES.iter().zip(values) {
+        let Some(first) = values.first() else {
+            continue;
+        };
+        match property.value {
+            XmpValue::Text | XmpValue::LangAlt => {
+                changes.push(change_for(property.tag, first.clone()));
+            }
+            XmpValue::Seq => changes.push(change_for(property.tag, values.join("; "))),
+            XmpValue::Date { subsec, offset } => {
+                let (date, subsec_value, offset_value) = iso_date_to_exif(first)
+                    .ok_or_else(|| xmp_error(&format!("Invalid date: {first}")))?;
+                changes.push(change_for(property.tag, date));
+                if let Some(value) = subsec_value {
+                    changes.push(change_for(subsec, value));
+                }
+                if let Some(value) = offset_value {
+                    changes.push(change_for(offset, value));
+                }
+            }
+        }
+    }
+    Ok(changes)
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen(js_name = metadataToJson)]
+/// Serialize metadata returned by `loadMetadata` as a versioned JSON sidecar.
+/// # Errors
+/// Returns an error if the metadata is malformed or serialization fails.
+pub fn metadata_to_json(metadata: &MetadataValue) -> Result<String, JsValue> {
+    Metadata::from_value(metadata)?
+        .to_json_sidecar()
+        .map_err(JsValue::from)
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen(js_name = metadataCsvHeader)]
+/// Header row for `metadataToCsvRow`.
+#[must_use]
+pub fn metadata_csv_header() -> String {
+    Metadata::csv_header()
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen(js_name = metadataToCsvRow)]
+/// Flatten metadata returned by `loadMetadata` into one CSV row.
+/// # Arguments
+/// * `metadata` - The metadata to export.
+/// * `file_name` - The value of the `file` column.
+/// # Errors
+/// Returns an error if the metadata is malformed.
+pub fn metadata_to_csv_row(metadata: &MetadataValue, file_name: &str) -> Result<String, JsValue> {
+    Ok(Metadata::from_value(metadata)?.to_csv_row(file_name))
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen(js_name = metadataToXmp)]
+/// Write the editable EXIF tags of metadata returned by `loadMetadata` as an `.xmp` sidecar.
+/// # Errors
+/// Returns an error if the metadata is malformed.
+pub fn metadata_to_xmp(metadata: &MetadataValue) -> Result<String, JsValue> {
+    Ok(Metadata::from_value(metadata)?.to_xmp_sidecar())
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen(js_name = importXmpSidecar)]
+/// Parse an `.xmp` sidecar into changes that can be passed to `saveMetadata`.
+/// # Errors
+/// Returns an error if the XMP is not well-formed or holds an invalid date.
+pub fn import_xmp_sidecar(xmp: &str) -> Result<MetadataChanges, JsValue> {
+    xmp_sidecar_to_changes(xmp)
+        .map(MetadataChanges)
+        .map_err(JsValue::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata() -> Metadata {
+        let field = |tag: exif::Tag, text: &str| MetadataField {
+            id: tag_id(tag),
+            number: tag.number(),
+            ifd: ifd_name(tag.context()).to_string(),
+            description: tag.description().map(str::to_string),
+            value: format!("\"{text}\""),
+            text: Some(text.to_string()),
+        };
+        Metadata {
+            width: 4,
+            height: 3,
+            fields: Some(vec![
+                field(exif::Tag::Make, "ACME"),
+                field(exif::Tag::Artist, "Jane Doe"),
+                field(exif::Tag::Copyright, "© 2024 <Jane>"),
+                field(exif::Tag::DateTimeOriginal, "2024:03:01 12:30:45"),
+                field(exif::Tag::SubSecTimeOriginal, "25"),
+                field(exif::Tag::OffsetTimeOriginal, "+02:00"),
+                field(exif::Tag::ImageDescription, "Beach, \"sunset\""),
+            ]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn xmp_sidecar_round_trips() {
+        let xmp = metadata().to_xmp_sidecar();
+        assert!(xmp.contains("<exif:DateTimeOriginal>2024-03-01T12:30:45.25+02:00<"));
+
+        let changes = xmp_sidecar_to_changes(&xmp).unwrap();
+        let value = |tag: exif::Tag| {
+            changes
+                .iter()
+                .find(|c| c.tag == tag_id(tag))
+                .and_then(|c| c.value.as_deref())
+        };
+        assert_eq!(changes.len(), 7);
+        assert_eq!(value(exif::Tag::Make), Some("ACME"));
+        assert_eq!(value(exif::Tag::Artist), Some("Jane Doe"));
+        assert_eq!(value(exif::Tag::Copyright), Some("© 2024 <Jane>"));
+        assert_eq!(
+            value(exif::Tag::ImageDescription),
+            Some("Beach, \"sunset\"")
+        );
+        assert_eq!(
+            value(exif::Tag::DateTimeOriginal),
+            Some("2024:03:01 12:30:45")
+        );
+        assert_eq!(value(exif::Tag::SubSecTimeOriginal), Some("25"));
+        assert_eq!(value(exif::Tag::OffsetTimeOriginal), Some("+02:00"));
+    }
+
+    #[test]
+    fn imports_attribute_form_and_partial_dates() {
+        let xmp = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+            <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+              <rdf:Description rdf:about="" xmlns:tiff="http://ns.adobe.com/tiff/1.0/"
+                  xmlns:xmp="http://ns.adobe.com/xap/1.0/" tiff:Model="X100" xmp:CreateDate="2023-07-04T10:00Z"/>
+            </rdf:RDF>
+          </x:xmpmeta>"#;
+        let changes = xmp_sidecar_to_changes(xmp).unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                change_for(exif::Tag::Model, "X100".to_string()),
+                change_for(
+                    exif::Tag::DateTimeDigitized,
+                    "2023:07:04 10:00:00".to_string()
+                ),
+                change_for(exif::Tag::OffsetTimeDigitized, "+00:00".to_string()),
+            ]
+        );
+
+        assert!(xmp_sidecar_to_changes(
+            r#"<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#" xmlns:xmp="http://ns.adobe.com/xap/1.0/">
+                <rdf:Description xmp:ModifyDate="yesterday"/></rdf:RDF>"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn pads_unpadded_exif_dates() {
+        assert_eq!(
+            exif_date_to_iso("2024:3:1 1:2:3", None, None).as_deref(),
+            Some("2024-03-01T01:02:03")
+        );
+        assert_eq!(exif_date_to_iso("2024:3:1", None, None), None);
+        assert_eq!(exif_date_to_iso("2024:02:31 10:00:00", None, None), None);
+        assert!(iso_date_to_exif("2024-02-31T10:00:00").is_none());
+    }
+
+    #[test]
+    fn csv_row_matches_header() {
+        let header = Metadata::csv_header();
+        let row = metadata().to_csv_row("a,b.jpg");
+        assert!(header.starts_with("file,width,height,ImageDescription,Make"));
+        assert!(row.starts_with("\"a,b.jpg\",4,3,\"Beach, \"\"sunset\"\"\",ACME,"));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&metadata().to_json_sidecar().unwrap()).unwrap();
+        assert_eq!(json["schema_version"], SIDECAR_SCHEMA_VERSION);
+        assert_eq!(json["fields"][0]["id"], "Make");
+    }
+}
@@ -0,0 +1,144 @@
+use exif::{Exif, In};
+use image::{codecs, DynamicImage};
+use little_exif::{exif_tag::ExifTag, ifd::ExifTagGroup, metadata::Metadata as LittleExifMetadata};
+
+use crate::error::WasmImageError;
+
+/// Longest edge of a regenerated thumbnail. EXIF recommends 160x120.
+const THUMBNAIL_MAX_SIZE: u32 = 160;
+const THUMBNAIL_QUALITY: u8 = 75;
+/// The thumbnail lives in the second generic IFD (IFD1).
+const THUMBNAIL_IFD: u32 = 1;
+
+/// What `save_metadata` does with the EXIF thumbnail (IFD1). A stale thumbnail can still
+/// show the original picture after a crop or redaction.
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[cfg_attr(feature = "wasm", tsify(from_wasm_abi, into_wasm_abi))]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThumbnailAction {
+    /// Leave the thumbnail untouched.
+    #[default]
+    Keep,
+    /// Drop the thumbnail.
+    Remove,
+    /// Replace the thumbnail with a downscaled copy of the current pixels.
+    Regenerate,
+}
+
+/// Returns the JPEG thumbnail embedded in IFD1, if any.
+pub(super) fn exif_thumbnail(exif: &Exif) -> Option<Vec<u8>> {
+    let uint = |tag| {
+        exif.get_field(tag, In::THUMBNAIL)
+            .and_then(|f| f.value.get_uint(0))
+            .map(|v| v as usize)
+    };
+    let offset = uint(exif::Tag::JPEGInterchangeFormat)?;
+    let length = uint(exif::Tag::JPEGInterchangeFormatLength)?;
+    exif.buf()
+        .get(offset..offset.checked_add(length)?)
+        .filter(|data| !data.is_empty())
+        .map(<[u8]>::to_vec)
+}
+
+fn remove_thumbnail(exif_meta: &mut LittleExifMetadata) {
+    if exif_meta
+        .get_ifd(ExifTagGroup::GENERIC, THUMBNAIL_IFD)
+        .is_none()
+    {
+        return;
+    }
+    let ifd1 = exif_meta.get_ifd_mut(ExifTagGroup::GENERIC, THUMBNAIL_IFD);
+    let tags: Vec<u16> = ifd1.get_tags().iter().map(ExifTag::as_u16).collect();
+    for tag in tags {
+        ifd1.remove_tag(tag);
+    }
+}
+
+fn encode_thumbnail(img: &DynamicImage) -> Result<Vec<u8>, WasmImageError> {
+    let thumbnail = DynamicImage::ImageRgb8(
+        img.thumbnail(THUMBNAIL_MAX_SIZE, THUMBNAIL_MAX_SIZE)
+            .to_rgb8(),
+    );
+    let mut output = Vec::new();
+    codecs::jpeg::JpegEncoder::new_with_quality(&mut output, THUMBNAIL_QUALITY)
+        .encode_image(&thumbnail)?;
+    Ok(output)
+}
+
+/// Applies `action` to the EXIF thumbnail. `img` is only decoded for `Regenerate`.
+pub(super) fn apply_thumbnail_action(
+    exif_meta: &mut LittleExifMetadata,
+    action: ThumbnailAction,
+    img: impl FnOnce() -> Result<DynamicImage, WasmImageError>,
+) -> Result<(), WasmImageError> {
+    match action {
+        ThumbnailAction::Keep => {}
+        ThumbnailAction::Remove => remove_thumbnail(exif_meta),
+        ThumbnailAction::Regenerate => {
+            let data = encode_thumbnail(&img()?)?;
+            let length = u32::try_from(data.len())
+                .map_err(|_| WasmImageError::EncodingError("Thumbnail too large".to_string()))?;
+
+            remove_thumbnail(exif_meta);
+            let ifd1 = exif_meta.get_ifd_mut(ExifTagGroup::GENERIC, THUMBNAIL_IFD);
+            // 6 = JPEG compression. The offset is filled in by little_exif on encode.
+            ifd1.set_tag(ExifTag::Compression(vec![6]));
+            ifd1.set_tag(ExifTag::ThumbnailOffset(vec![0], data));
+            ifd1.set_tag(ExifTag::ThumbnailLength(vec![length]));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, Cursor};
+
+    use image::ImageFormat;
+    use little_exif::filetype::FileExtension;
+
+    use super::*;
+
+    fn read_exif(file: &[u8]) -> Exif {
+        exif::Reader::new()
+            .read_from_container(&mut BufReader::new(Cursor::new(file)))
+            .unwrap()
+    }
+
+    fn edited(file: &[u8], action: ThumbnailAction) -> Vec<u8> {
+        let mut output = file.to_vec();
+        let mut exif_meta = LittleExifMetadata::new_from_vec(&output, FileExtension::JPEG).unwrap();
+        apply_thumbnail_action(&mut exif_meta, action, || {
+            Ok(image::load_from_memory_with_format(
+                file,
+                ImageFormat::Jpeg,
+            )?)
+        })
+        .unwrap();
+        exif_meta
+            .write_to_vec(&mut output, FileExtension::JPEG)
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn regenerates_and_removes_thumbnail() {
+        let file = include_bytes!("../../assets/test.jpeg");
+
+        let regenerated = edited(file, ThumbnailAction::Regenerate);
+        let thumbnail = exif_thumbnail(&read_exif(&regenerated)).expect("no thumbnail written");
+        let thumbnail = image::load_from_memory_with_format(&thumbnail, ImageFormat::Jpeg).unwrap();
+        assert_eq!(
+            thumbnail.width().max(thumbnail.height()),
+            THUMBNAIL_MAX_SIZE
+        );
+
+        let removed = edited(&regenerated, ThumbnailAction::Remove);
+        let exif = read_exif(&removed);
+        assert!(exif_thumbnail(&exif).is_none());
+        assert!(exif
+            .get_field(exif::Tag::DateTimeOriginal, In::PRIMARY)
+            .is_some());
+    }
+}
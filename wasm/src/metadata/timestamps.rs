@@ -0,0 +1,360 @@
+use std::io::{BufReader, Cursor};
+
+use exif::{In, Value};
+
+use super::editable::{ifd_name, tag_id};
+use super::save::{write_metadata_changes, ExifEdits, MetadataChange};
+use super::thumbnail::ThumbnailAction;
+use crate::error::WasmImageError;
+
+#[cfg(feature = "wasm")]
+use {
+    crate::input::{ImageInput, Input},
+    js_sys::Uint8Array,
+    wasm_bindgen::prelude::*,
+};
+
+/// Date tags rewritten by `shift_timestamps`, each paired with the tag holding its UTC offset.
+pub(super) static SHIFTED_TIMESTAMPS: &[(exif::Tag, exif::Tag)] = &[
+    (exif::Tag::DateTime, exif::Tag::OffsetTime),
+    (exif::Tag::DateTimeOriginal, exif::Tag::OffsetTimeOriginal),
+    (exif::Tag::DateTimeDigitized, exif::Tag::OffsetTimeDigitized),
+];
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Parses an offset such as `+3h`, `-1 day` or `+1d 2h 30m` into seconds.
+/// Units: `d`/`day`/`days`, `h`/`hour`/`hours`, `m`/`min`/`minutes`, `s`/`sec`/`seconds`.
+/// A bare number is read as seconds, and may only be the last component.
+fn parse_offset(offset: &str) -> Option<i64> {
+    let offset = offset.trim();
+    let (sign, rest) = match offset.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, offset.strip_prefix('+').unwrap_or(offset)),
+    };
+
+    let mut total: i64 = 0;
+    let mut chars = rest.trim_start().chars().peekable();
+    let skip_whitespace = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    };
+    let mut seen_component = false;
+
+    while chars.peek().is_some() {
+        let digits: String = std::iter::from_fn(|| chars.next_if(char::is_ascii_digit)).collect();
+        skip_whitespace(&mut chars);
+        let unit: String =
+            std::iter::from_fn(|| chars.next_if(char::is_ascii_alphabetic)).collect();
+        skip_whitespace(&mut chars);
+        // Numbers separated by whitespace, as in `1 2h`, are not read as one number.
+        if unit.is_empty() && chars.peek().is_some() {
+            return None;
+        }
+        let value: i64 = digits.parse().ok()?;
+        let multiplier = match unit.to_ascii_lowercase().as_str() {
+            "d" | "day" | "days" => SECONDS_PER_DAY,
+            "h" | "hour" | "hours" => 3_600,
+            "m" | "min" | "mins" | "minute" | "minutes" => 60,
+            "" | "s" | "sec" | "secs" | "second" | "seconds" => 1,
+            _ => return None,
+        };
+        total = total.checked_add(value.checked_mul(multiplier)?)?;
+        seen_component = true;
+    }
+
+    seen_component.then_some(sign * total)
+}
+
+/// Validates a UTC offset in the EXIF `OffsetTime` format (`+HH:MM`).
+pub(super) fn parse_timezone(timezone: &str) -> Option<String> {
+    let bytes = timezone.as_bytes();
+    let valid = bytes.len() == 6
+        && matches!(bytes[0], b'+' | b'-')
+        && bytes[3] == b':'
+        && [1, 2, 4, 5].iter().all(|&i| bytes[i].is_ascii_digit())
+        && timezone[1..3].parse::<u8>().is_ok_and(|h| h <= 14)
+        && timezone[4..6].parse::<u8>().is_ok_and(|m| m < 60);
+    valid.then(|| timezone.to_string())
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Number of days in a month of the proleptic Gregorian calendar.
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Parses an EXIF `YYYY:MM:DD HH:MM:SS` timestamp into seconds since the Unix epoch.
+/// Dates that don't exist in the calendar, such as `2024:02:31`, are rejected.
+pub(super) fn parse_exif_datetime(value: &str) -> Option<i64> {
+    let value = value.trim_end_matches('\0').trim();
+    let (date, time) = value.split_once(' ')?;
+    let mut date = date.split(':').map(str::parse::<i64>);
+    let mut time = time.split(':').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let (hour, minute, second) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+
+    let in_range = (1..=12).contains(&month)
+        && (1..=days_in_month(year, month)).contains(&day)
+        && (0..24).contains(&hour)
+        && (0..60).contains(&minute)
+        && (0..60).contains(&second);
+    in_range.then(|| {
+        days_from_civil(year, month, day) * SECONDS_PER_DAY + hour * 3_600 + minute * 60 + second
+    })
+}
+
+pub(super) fn format_exif_datetime(timestamp: i64) -> Option<String> {
+    let (year, month, day) = civil_from_days(timestamp.div_euclid(SECONDS_PER_DAY));
+    let seconds = timestamp.rem_euclid(SECONDS_PER_DAY);
+    (0..=9999).contains(&year).then(|| {
+        format!(
+            "{year:04}:{month:02}:{day:02} {:02}:{:02}:{:02}",
+            seconds / 3_600,
+            seconds % 3_600 / 60,
+            seconds % 60
+        )
+    })
+}
+
+pub(super) fn change_for(tag: exif::Tag, value: String) -> MetadataChange {
+    MetadataChange {
+        tag: tag_id(tag),
+        ifd: Some(ifd_name(tag.context()).to_string()),
+        value: Some(value),
+    }
+}
+
+/// Builds the changes that shift every parsable timestamp in `file` by `offset` seconds.
+fn timestamp_changes(
+    file: &[u8],
+    offset: i64,
+    timezone: Option<&str>,
+) -> Result<Vec<MetadataChange>, WasmImageError> {
+    let exif = exif::Reader::new().read_from_container(&mut BufReader::new(Cursor::new(file)))?;
+
+    let mut changes = Vec::new();
+    for &(date_tag, offset_tag) in SHIFTED_TIMESTAMPS {
+        let Some(field) = exif.get_field(date_tag, In::PRIMARY) else {
+            continue;
+        };
+        let Value::Ascii(ref values) = field.value else {
+            continue;
+        };
+        let shifted = values
+            .first()
+            .and_then(|v| std::str::from_utf8(v).ok())
+            .and_then(parse_exif_datetime)
+            .and_then(|ts| ts.checked_add(offset))
+            .and_then(format_exif_datetime);
+
+        if let Some(shifted) = shifted {
+            changes.push(change_for(date_tag, shifted));
+            if let Some(timezone) = timezone {
+                changes.push(change_for(offset_tag, timezone.to_string()));
+            }
+        }
+    }
+
+    Ok(changes)
+}
+
+fn shift_file_timestamps(
+    file: &[u8],
+    src_type: &str,
+    offset: &str,
+    timezone: Option<&str>,
+) -> Result<Vec<u8>, WasmImageError> {
+    let offset = parse_offset(offset)
+        .ok_or_else(|| WasmImageError::InvalidSettings(format!("Invalid time offset: {offset}")))?;
+    let timezone = timezone
+        .map(|tz| {
+            parse_timezone(tz).ok_or_else(|| {
+                WasmImageError::InvalidSettings(format!("Invalid timezone offset: {tz}"))
+            })
+        })
+        .transpose()?;
+
+    let changes = timestamp_changes(file, offset, timezone.as_deref())?;
+
+    let edits = ExifEdits {
+        changes: &changes,
+        strip_gps: false,
+        thumbnail: ThumbnailAction::Keep,
+    };
+    write_metadata_changes(file, src_type, edits)
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen(js_name = shiftTimestamps)]
+#[allow(clippy::needless_pass_by_value)]
+/// Shift `DateTime`, `DateTimeOriginal` and `DateTimeDigitized` by a fixed offset.
+/// # Arguments
+/// * `file` - The image file bytes, or a `Blob`.
+/// * `src_type` - The MIME type of the source image (JPEG, PNG, WebP or TIFF).
+/// * `offset` - The offset to add, e.g. `+3h`, `-1 day` or `+1d 2h 30m`.
+/// * `timezone` - If given, written to the matching `OffsetTime*` tags (`+HH:MM`).
+/// * `cb` - A callback function to report progress.
+/// # Errors
+/// Returns an error if the offset or timezone is malformed, the file has no readable EXIF,
+/// or the format does not support metadata editing.
+pub fn shift_timestamps(
+    file: &ImageInput,
+    src_type: &str,
+    offset: &str,
+    timezone: Option<String>,
+    cb: &js_sys::Function,
+) -> Result<Uint8Array, JsValue> {
+    crate::progress::report(cb, 10.0, "Starting timestamp shift");
+
+    let file = Input::new(file)?.read()?;
+
+    crate::progress::report(cb, 40.0, "Shifting timestamps");
+
+    let output = shift_file_timestamps(&file, src_type, offset, timezone.as_deref())
+        .map_err(JsValue::from)?;
+
+    crate::progress::report(cb, 100.0, "Timestamp shift complete");
+
+    Ok(Uint8Array::from(output.as_slice()))
+}
+
+#[cfg(not(feature = "wasm"))]
+/// Shift `DateTime`, `DateTimeOriginal` and `DateTimeDigitized` by a fixed offset.
+/// # Arguments
+/// * `file` - The image file bytes.
+/// * `src_type` - The MIME type of the source image (JPEG, PNG, WebP or TIFF).
+/// * `offset` - The offset to add, e.g. `+3h`, `-1 day` or `+1d 2h 30m`.
+/// * `timezone` - If given, written to the matching `OffsetTime*` tags (`+HH:MM`).
+/// # Errors
+/// Returns an error if the offset or timezone is malformed, the file has no readable EXIF,
+/// or the format does not support metadata editing.
+pub fn shift_timestamps(
+    file: &[u8],
+    src_type: &str,
+    offset: &str,
+    timezone: Option<&str>,
+) -> Result<Vec<u8>, WasmImageError> {
+    shift_file_timestamps(file, src_type, offset, timezone)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::presets::PRESET_TIMESTAMPS;
+
+    #[test]
+    fn shifted_tags_are_timestamp_presets() {
+        for (date_tag, offset_tag) in SHIFTED_TIMESTAMPS {
+            assert!(
+                PRESET_TIMESTAMPS.contains(date_tag),
+                "{date_tag:?} missing from PRESET_TIMESTAMPS"
+            );
+            assert!(
+                PRESET_TIMESTAMPS.contains(offset_tag),
+                "{offset_tag:?} missing from PRESET_TIMESTAMPS"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_offsets() {
+        assert_eq!(parse_offset("+3h"), Some(3 * 3_600));
+        assert_eq!(parse_offset("-1 day"), Some(-SECONDS_PER_DAY));
+        assert_eq!(
+            parse_offset("+1d 2h 30m"),
+            Some(SECONDS_PER_DAY + 2 * 3_600 + 30 * 60)
+        );
+        assert_eq!(parse_offset("90"), Some(90));
+        assert_eq!(parse_offset(""), None);
+        assert_eq!(parse_offset("3 weeks"), None);
+        assert_eq!(parse_offset("1 2h"), None);
+        assert_eq!(parse_offset("1d 30"), Some(SECONDS_PER_DAY + 30));
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        assert!(parse_exif_datetime("2024:02:29 12:00:00").is_some());
+        assert_eq!(parse_exif_datetime("2023:02:29 12:00:00"), None);
+        assert_eq!(parse_exif_datetime("2024:02:31 12:00:00"), None);
+        assert_eq!(parse_exif_datetime("2024:04:31 12:00:00"), None);
+        assert_eq!(parse_exif_datetime("2024:01:01 12:00:60"), None);
+    }
+
+    #[test]
+    fn shifts_across_month_and_year_boundaries() {
+        let shift = |value: &str, offset: i64| {
+            parse_exif_datetime(value).and_then(|ts| format_exif_datetime(ts + offset))
+        };
+        assert_eq!(
+            shift("2008:05:30 15:56:01", 3 * 3_600).as_deref(),
+            Some("2008:05:30 18:56:01")
+        );
+        assert_eq!(
+            shift("2023:12:31 23:30:00", 3_600).as_deref(),
+            Some("2024:01:01 00:30:00")
+        );
+        assert_eq!(
+            shift("2024:03:01 00:00:00", -SECONDS_PER_DAY).as_deref(),
+            Some("2024:02:29 00:00:00")
+        );
+        assert_eq!(parse_exif_datetime("    :  :     :  :  "), None);
+    }
+
+    #[test]
+    fn validates_timezones() {
+        assert_eq!(parse_timezone("+02:00").as_deref(), Some("+02:00"));
+        assert_eq!(parse_timezone("-09:30").as_deref(), Some("-09:30"));
+        assert_eq!(parse_timezone("02:00"), None);
+        assert_eq!(parse_timezone("+25:00"), None);
+    }
+
+    #[test]
+    fn shifts_jpeg_timestamps() {
+        let file = include_bytes!("../../assets/test.jpeg");
+        let output = shift_file_timestamps(file, "image/jpeg", "-1 day", Some("+02:00")).unwrap();
+
+        let exif = exif::Reader::new()
+            .read_from_container(&mut BufReader::new(Cursor::new(&output)))
+            .unwrap();
+        let value = |tag| {
+            exif.get_field(tag, In::PRIMARY)
+                .map(|f| f.display_value().to_string())
+        };
+        assert_eq!(
+            value(exif::Tag::DateTimeOriginal).as_deref(),
+            Some("2008-05-29 15:56:01")
+        );
+        assert_eq!(
+            value(exif::Tag::OffsetTimeOriginal).as_deref(),
+            Some("\"+02:00\"")
+        );
+    }
+}
@@ -0,0 +1,340 @@
+use image::ImageFormat;
+use little_exif::{
+    exif_tag::ExifTag, filetype::FileExtension, metadata::Metadata as LittleExifMetadata,
+};
+
+use super::container::{set_jpeg_icc_profile, set_jpeg_xmp, set_png_xmp, set_webp_xmp};
+use super::extract::get_decoder;
+use super::save::{
+    editable_format, encode_image_with_metadata_to_vec, file_ext_for_format, strip_gps_tags,
+};
+use crate::convert::webp::reencode_settings;
+use crate::detect::resolve_mime_type;
+use crate::error::WasmImageError;
+use crate::limits::decode;
+
+#[cfg(feature = "wasm")]
+use {
+    crate::input::{ImageInput, Input},
+    js_sys::Uint8Array,
+    wasm_bindgen::prelude::*,
+};
+
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[cfg_attr(feature = "wasm", tsify(from_wasm_abi, into_wasm_abi))]
+#[derive(Clone, Copy, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct TransplantOptions {
+    /// Copy the EXIF block. Not supported for TIFF targets.
+    pub exif: bool,
+    /// Copy the embedded ICC colour profile.
+    pub icc: bool,
+    /// Copy the XMP packet. Not supported for TIFF targets.
+    pub xmp: bool,
+    /// Set the copied EXIF orientation to 1 (upright), for targets that were already rotated.
+    pub reset_orientation: bool,
+    /// Remove GPS tags from the copied EXIF.
+    pub strip_gps: bool,
+}
+
+impl Default for TransplantOptions {
+    fn default() -> Self {
+        Self {
+            exif: true,
+            icc: true,
+            xmp: true,
+            reset_orientation: false,
+            strip_gps: false,
+        }
+    }
+}
+
+struct SourceMetadata {
+    exif: Option<LittleExifMetadata>,
+    icc: Option<Vec<u8>>,
+    xmp: Option<Vec<u8>>,
+}
+
+fn read_source_metadata(
+    file: &[u8],
+    format: ImageFormat,
+    options: TransplantOptions,
+) -> Result<SourceMetadata, WasmImageError> {
+    let mut decoder = get_decoder(format, file)?;
+    let icc = if options.icc {
+        decoder.icc_profile().ok().flatten()
+    } else {
+        None
+    };
+    let xmp = if options.xmp {
+        decoder.xmp_metadata().ok().flatten()
+    } else {
+        None
+    };
+
+    let exif = options
+        .exif
+        .then(|| file_ext_for_format(format))
+        .flatten()
+        .and_then(|ext| LittleExifMetadata::new_from_vec(&file.to_vec(), ext).ok())
+        .map(|mut exif| {
+            if options.reset_orientation {
+                exif.set_tag(ExifTag::Orientation(vec![1]));
+            }
+            if options.strip_gps {
+                strip_gps_tags(&mut exif);
+            }
+            exif
+        });
+
+    Ok(SourceMetadata { exif, icc, xmp })
+}
+
+fn write_target_metadata(
+    target: &[u8],
+    format: ImageFormat,
+    file_ext: FileExtension,
+    metadata: SourceMetadata,
+) -> Result<Vec<u8>, WasmImageError> {
+    let SourceMetadata { exif, icc, xmp } = metadata;
+
+    if format == ImageFormat::Jpeg {
+        // Inject into a copy of the original bytes to avoid a lossy re-encode.
+        let mut output = target.to_vec();
+        if let Some(exif) = exif {
+            exif.write_to_vec(&mut output, file_ext)
+                .map_err(|e| WasmImageError::EncodingError(e.to_string()))?;
+        }
+        if let Some(icc) = icc {
+            output = set_jpeg_icc_profile(&output, &icc)?;
+        }
+        if let Some(xmp) = xmp {
+            output = set_jpeg_xmp(&output, &xmp)?;
+        }
+        return Ok(output);
+    }
+
+    // PNG and WebP are re-encoded (lossy WebP stays lossy) with EXIF and ICC set on the encoder.
+    // TIFF only keeps the ICC profile, see `encode_image_with_metadata_to_vec`.
+    let img = decode(target, format)?;
+    let exif_bytes = exif
+        .map(|exif| exif.encode())
+        .transpose()
+        .map_err(|e| WasmImageError::EncodingError(e.to_string()))?;
+    let output = encode_image_with_metadata_to_vec(
+        &img,
+        format,
+        reencode_settings(target),
+        exif_bytes,
+        icc,
+    )?;
+
+    match (format, xmp) {
+        (ImageFormat::Png, Some(xmp)) => set_png_xmp(&output, &xmp),
+        (ImageFormat::WebP, Some(xmp)) => {
+            let has_alpha = img.color().has_alpha();
+            set_webp_xmp(&output, &xmp, img.width(), img.height(), has_alpha)
+        }
+        _ => Ok(output),
+    }
+}
+
+fn transplant(
+    source: &[u8],
+    source_type: &str,
+    target: &[u8],
+    target_type: &str,
+    options: TransplantOptions,
+) -> Result<Vec<u8>, WasmImageError> {
+    let source_mime = resolve_mime_type(source, source_type)?;
+    let source_format = ImageFormat::from_mime_type(source_mime).ok_or_else(|| {
+        WasmImageError::UnknownFileType(
+            Some(source_mime.to_string()),
+            format!("Metadata can't be read from {source_mime}"),
+        )
+    })?;
+    let (target_format, target_ext) = editable_format(target, target_type)?;
+
+    let metadata = read_source_metadata(source, source_format, options)?;
+    write_target_metadata(target, target_format, target_ext, metadata)
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen(js_name = transplantMetadata)]
+#[allow(clippy::needless_pass_by_value)]
+/// Copy EXIF, ICC and XMP metadata from one image onto another.
+/// # Arguments
+/// * `source_file` - The image to copy metadata from, as a `Uint8Array` or a `Blob`.
+/// * `source_type` - The MIME type of the source image.
+/// * `target_file` - The image to copy metadata onto, as a `Uint8Array` or a `Blob`.
+/// * `target_type` - The MIME type of the target image (JPEG, PNG, WebP or TIFF).
+/// * `options` - Which metadata to copy, and whether to reset orientation or strip GPS.
+/// * `cb` - A callback function to report progress.
+/// # Errors
+/// Returns an error if either image cannot be read or the target format does not support
+/// metadata editing.
+pub fn transplant_metadata(
+    source_file: &ImageInput,
+    source_type: &str,
+    target_file: &ImageInput,
+    target_type: &str,
+    options: Option<TransplantOptions>,
+    cb: &js_sys::Function,
+) -> Result<Uint8Array, JsValue> {
+    crate::progress::report(cb, 10.0, "Starting metadata transplant");
+
+    let source_file = Input::new(source_file)?.read()?;
+    let target_file = Input::new(target_file)?.read()?;
+
+    crate::progress::report(cb, 40.0, "Copying metadata");
+
+    let output = transplant(
+        &source_file,
+        source_type,
+        &target_file,
+        target_type,
+        options.unwrap_or_default(),
+    )
+    .map_err(JsValue::from)?;
+
+    crate::progress::report(cb, 100.0, "Metadata transplant complete");
+
+    Ok(Uint8Array::from(output.as_slice()))
+}
+
+#[cfg(not(feature = "wasm"))]
+/// Copy EXIF, ICC and XMP metadata from one image onto another.
+/// # Arguments
+/// * `source_file` - The image to copy metadata from.
+/// * `source_type` - The MIME type of the source image.
+/// * `target_file` - The image to copy metadata onto.
+/// * `target_type` - The MIME type of the target image (JPEG, PNG, WebP or TIFF).
+/// * `options` - Which metadata to copy, and whether to reset orientation or strip GPS.
+/// # Errors
+/// Returns an error if either image cannot be read or the target format does not support
+/// metadata editing.
+pub fn transplant_metadata(
+    source_file: &[u8],
+    source_type: &str,
+    target_file: &[u8],
+    target_type: &str,
+    options: &TransplantOptions,
+) -> Result<Vec<u8>, WasmImageError> {
+    transplant(source_file, source_type, target_file, target_type, *options)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{ImageDecoder, RgbImage};
+
+    use super::*;
+
+    const XMP: &[u8] = b"<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"></x:xmpmeta>";
+    const ICC: &[u8] = b"not a real profile, but the containers don't care";
+
+    fn encode(format: ImageFormat) -> Vec<u8> {
+        let img = image::DynamicImage::ImageRgb8(RgbImage::new(8, 4));
+        let mut output = Vec::new();
+        img.write_to(&mut Cursor::new(&mut output), format).unwrap();
+        output
+    }
+
+    fn source_metadata() -> SourceMetadata {
+        let file = include_bytes!("../../assets/test.jpeg");
+        let mut metadata =
+            read_source_metadata(file, ImageFormat::Jpeg, TransplantOptions::default()).unwrap();
+        metadata.icc = Some(ICC.to_vec());
+        metadata.xmp = Some(XMP.to_vec());
+        metadata
+    }
+
+    #[test]
+    fn transplants_onto_every_editable_format() {
+        for format in [ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::WebP] {
+            let target = encode(format);
+            let ext = file_ext_for_format(format).unwrap();
+            let output = write_target_metadata(&target, format, ext, source_metadata()).unwrap();
+
+            let mut decoder = get_decoder(format, &output).unwrap();
+            assert_eq!(
+                decoder.icc_profile().unwrap().as_deref(),
+                Some(ICC),
+                "{format:?}"
+            );
+            assert_eq!(
+                decoder.xmp_metadata().unwrap().as_deref(),
+                Some(XMP),
+                "{format:?}"
+            );
+            assert!(decoder.exif_metadata().unwrap().is_some(), "{format:?}");
+            assert!(image::load_from_memory_with_format(&output, format).is_ok());
+        }
+    }
+
+    #[test]
+    fn writes_large_xmp_as_extended_xmp() {
+        let xmp = format!("<x:xmpmeta>{}</x:xmpmeta>", "x".repeat(100_000)).into_bytes();
+        let metadata = SourceMetadata {
+            xmp: Some(xmp.clone()),
+            ..source_metadata()
+        };
+        let output = write_target_metadata(
+            &encode(ImageFormat::Jpeg),
+            ImageFormat::Jpeg,
+            FileExtension::JPEG,
+            metadata,
+        )
+        .unwrap();
+
+        let mut decoder = get_decoder(ImageFormat::Jpeg, &output).unwrap();
+        assert_eq!(decoder.icc_profile().unwrap().as_deref(), Some(ICC));
+        assert!(decoder.exif_metadata().unwrap().is_some());
+        let standard = String::from_utf8(decoder.xmp_metadata().unwrap().unwrap()).unwrap();
+        let guid = format!("{:X}", md5::compute(&xmp));
+        assert!(standard.contains(&format!("xmpNote:HasExtendedXMP=\"{guid}\"")));
+
+        // Reassemble the extension chunks from their offsets.
+        let signature = b"http://ns.adobe.com/xmp/extension/\0";
+        let mut extended = vec![0; xmp.len()];
+        let mut chunks = 0;
+        for start in (0..output.len()).filter(|&i| output[i..].starts_with(signature)) {
+            let len = usize::from(u16::from_be_bytes([output[start - 2], output[start - 1]]));
+            let header = start + signature.len() + 32;
+            assert_eq!(&output[start + signature.len()..header], guid.as_bytes());
+            let offset = u32::from_be_bytes(output[header + 4..header + 8].try_into().unwrap());
+            let chunk = &output[header + 8..start - 2 + len];
+            extended[offset as usize..offset as usize + chunk.len()].copy_from_slice(chunk);
+            chunks += 1;
+        }
+        assert_eq!(chunks, 2);
+        assert_eq!(extended, xmp);
+    }
+
+    #[test]
+    fn resets_orientation_and_strips_gps() {
+        let file = include_bytes!("../../assets/test.jpeg");
+        let options = TransplantOptions {
+            reset_orientation: true,
+            strip_gps: true,
+            ..Default::default()
+        };
+        let output = transplant(
+            file,
+            "image/jpeg",
+            &encode(ImageFormat::Png),
+            "image/png",
+            options,
+        )
+        .unwrap();
+
+        let exif = exif::Reader::new()
+            .read_from_container(&mut std::io::BufReader::new(Cursor::new(&output)))
+            .unwrap();
+        let orientation = exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY);
+        assert_eq!(orientation.and_then(|f| f.value.get_uint(0)), Some(1));
+        assert!(exif.fields().all(|f| f.tag.context() != exif::Context::Gps));
+    }
+}
@@ -0,0 +1,410 @@
+//! Structural checks of image files: PNG chunks, JPEG marker segments, EXIF blocks, the
+//! declared MIME type and whether the image decodes, reported as issues with their offsets.
+
+use std::io::{BufReader, Cursor};
+
+use image::ImageFormat;
+
+use super::container::{jpeg_header_segments, read_jpeg_segment, PNG_SIGNATURE};
+use super::extract::{get_decoder, get_exif_errors};
+use crate::detect::{resolve_mime_type, sniff_mime_type};
+use crate::error::WasmImageError;
+use crate::load::load_image;
+use crate::source_type::SourceType;
+
+#[cfg(feature = "wasm")]
+use {
+    crate::input::{ImageInput, Input},
+    wasm_bindgen::prelude::*,
+};
+
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Harmless, e.g. zero padding after the image data.
+    Info,
+    /// The image decodes, but the file is not well-formed.
+    Warning,
+    /// The image is corrupt or can't be decoded.
+    Error,
+}
+
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[derive(Clone, PartialEq, Eq, Debug, serde::Serialize)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    pub message: String,
+    /// Byte offset of the problem in the file, if it is tied to one.
+    pub offset: Option<u32>,
+}
+
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[cfg_attr(feature = "wasm", tsify(into_wasm_abi))]
+#[derive(Clone, PartialEq, Eq, Debug, serde::Serialize)]
+pub struct ValidationReport {
+    /// Whether no issue has the `Error` severity.
+    pub valid: bool,
+    /// MIME type of the file, as detected from its content.
+    pub format: Option<String>,
+    pub issues: Vec<ValidationIssue>,
+}
+
+#[derive(Default)]
+struct Issues(Vec<ValidationIssue>);
+
+impl Issues {
+    fn push(&mut self, severity: Severity, offset: Option<usize>, message: impl Into<String>) {
+        self.0.push(ValidationIssue {
+            severity,
+            message: message.into(),
+            offset: offset.map(|offset| offset as u32),
+        });
+    }
+
+    fn error(&mut self, offset: usize, message: impl Into<String>) {
+        self.push(Severity::Error, Some(offset), message);
+    }
+
+    /// Reports the bytes after the end of the image data, from `end`.
+    fn trailing(&mut self, file: &[u8], end: usize, marker: &str) {
+        let trailing = &file[end.min(file.len())..];
+        if trailing.is_empty() {
+            return;
+        }
+        let (severity, kind) = if trailing.iter().all(|&b| b == 0) {
+            (Severity::Info, "zero padding")
+        } else {
+            (Severity::Warning, "trailing data")
+        };
+        self.push(
+            severity,
+            Some(end),
+            format!("{} bytes of {kind} after {marker}", trailing.len()),
+        );
+    }
+}
+
+/// Checks the chunk sequence and CRCs of a PNG file. Returns the end of the IEND chunk.
+fn check_png(png: &[u8], issues: &mut Issues) -> Option<usize> {
+    if !png.starts_with(PNG_SIGNATURE) {
+        issues.error(0, "Invalid PNG signature");
+        return None;
+    }
+
+    let mut pos = PNG_SIGNATURE.len();
+    loop {
+        let Some(header) = png.get(pos..pos + 8) else {
+            issues.error(pos, "The file ends before the IEND chunk");
+            return None;
+        };
+        let len = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let chunk_type = &header[4..8];
+        if !chunk_type.iter().all(u8::is_ascii_alphabetic) {
+            issues.error(pos, "Invalid chunk type");
+            return None;
+        }
+        let name = String::from_utf8_lossy(chunk_type);
+        if pos == PNG_SIGNATURE.len() && chunk_type != b"IHDR" {
+            issues.error(pos, format!("The first chunk is {name}, not IHDR"));
+        }
+
+        // The length is untrusted and can overflow `usize` on 32-bit targets.
+        let Some(data_end) = len
+            .checked_add(pos + 8)
+            .filter(|&data_end| data_end <= png.len())
+        else {
+            issues.error(pos, format!("The {name} chunk length exceeds the file"));
+            return None;
+        };
+        let Some(crc) = png.get(data_end..data_end + 4) else {
+            issues.error(pos, format!("The {name} chunk is truncated"));
+            return None;
+        };
+        if crc32fast::hash(&png[pos + 4..data_end]).to_be_bytes() != crc {
+            issues.error(pos, format!("CRC mismatch in the {name} chunk"));
+        }
+        pos = data_end + 4;
+
+        if chunk_type == b"IEND" {
+            issues.trailing(png, pos, "the IEND chunk");
+            return Some(pos);
+        }
+    }
+}
+
+/// Skips entropy-coded scan data, up to the next marker other than a restart marker.
+fn skip_scan(jpeg: &[u8], mut pos: usize) -> usize {
+    while pos + 1 < jpeg.len() {
+        if jpeg[pos] == 0xFF && !matches!(jpeg[pos + 1], 0x00 | 0xD0..=0xD7 | 0xFF) {
+            return pos;
+        }
+        pos += 1;
+    }
+    jpeg.len()
+}
+
+/// Checks the marker sequence of a JPEG file, from SOI through the scans to EOI. Returns the end
+/// of the EOI marker.
+fn check_jpeg(jpeg: &[u8], issues: &mut Issues) -> Option<usize> {
+    let (header, scan_start) = match jpeg_header_segments(jpeg) {
+        Ok(header) => header,
+        Err(e) => {
+            issues.error(e.pos(), e.to_string());
+            return None;
+        }
+    };
+    if let Some(segment) = header.iter().find(|s| matches!(s.marker, 0xD8 | 0x00)) {
+        let marker = segment.marker;
+        issues.error(segment.start, format!("Unexpected marker 0x{marker:02X}"));
+        return None;
+    }
+    // Start of frame, except DHT, JPG and DAC.
+    let is_frame = |marker| matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+    let mut frame = header.iter().any(|segment| is_frame(segment.marker));
+
+    let mut pos = scan_start;
+    let mut scans = 0;
+    loop {
+        let segment = match read_jpeg_segment(jpeg, pos) {
+            Ok(segment) => segment,
+            Err(e) => {
+                issues.error(e.pos(), e.to_string());
+                return None;
+            }
+        };
+        let start = segment.start;
+        pos = segment.end;
+
+        match segment.marker {
+            0xD9 => {
+                if scans == 0 {
+                    issues.error(start, "End-of-image marker before any scan");
+                }
+                issues.trailing(jpeg, pos, "the end-of-image marker");
+                return Some(pos);
+            }
+            marker @ (0xD8 | 0x00) => {
+                issues.error(start, format!("Unexpected marker 0x{marker:02X}"));
+                return None;
+            }
+            0xDA => {
+                if !frame {
+                    issues.error(start, "Start of scan before the frame header");
+                }
+                scans += 1;
+                pos = skip_scan(jpeg, pos);
+            }
+            marker if is_frame(marker) => frame = true,
+            _ => {}
+        }
+    }
+}
+
+/// Reads the EXIF block the way `load_metadata` does, reporting broken offsets and entries.
+fn check_exif(file: &[u8], format: ImageFormat, issues: &mut Issues) {
+    let mut reader = exif::Reader::new();
+    reader.continue_on_error(true);
+
+    let result = match format {
+        ImageFormat::Tiff
+        | ImageFormat::Jpeg
+        | ImageFormat::Avif
+        | ImageFormat::Png
+        | ImageFormat::WebP => reader.read_from_container(&mut BufReader::new(Cursor::new(file))),
+        _ => {
+            let exif = get_decoder(format, file)
+                .ok()
+                .and_then(|mut decoder| decoder.exif_metadata().ok().flatten());
+            match exif {
+                Some(exif) => reader.read_raw(exif),
+                None => return,
+            }
+        }
+    };
+
+    match result.map_err(get_exif_errors) {
+        Ok(_) | Err(Err(WasmImageError::ExifError(exif::Error::NotFound(_)))) => {}
+        Err(Ok(errors)) => {
+            for error in errors {
+                issues.push(
+                    Severity::Warning,
+                    None,
+                    format!("Invalid EXIF data: {error}"),
+                );
+            }
+        }
+        Err(Err(e)) => issues.push(Severity::Warning, None, e.to_string()),
+    }
+}
+
+fn validate(file: &[u8], src_type: &str) -> ValidationReport {
+    let mut issues = Issues::default();
+    let format = match resolve_mime_type(file, src_type) {
+        Ok(format) => format,
+        Err(e) => {
+            issues.push(Severity::Error, None, e.to_string());
+            return ValidationReport {
+                valid: false,
+                format: sniff_mime_type(file).map(str::to_string),
+                issues: issues.0,
+            };
+        }
+    };
+    if format != src_type {
+        issues.push(
+            Severity::Warning,
+            None,
+            format!("The file was declared as {src_type} but is {format}"),
+        );
+    }
+
+    let source_type = SourceType::from_mime_type(format);
+    if let Some(SourceType::Raster(image_format)) = source_type {
+        let end = match image_format {
+            ImageFormat::Png => check_png(file, &mut issues),
+            ImageFormat::Jpeg => check_jpeg(file, &mut issues),
+            _ => None,
+        };
+        // Trailing data is already reported, and would break the EXIF container parser.
+        check_exif(
+            &file[..end.unwrap_or(file.len())],
+            image_format,
+            &mut issues,
+        );
+    }
+
+    if let Err(e) = load_image(file, source_type.as_ref()).and_then(|img| img.rasterize(None)) {
+        issues.push(Severity::Error, None, format!("Decoding failed: {e}"));
+    }
+
+    ValidationReport {
+        valid: !issues
+            .0
+            .iter()
+            .any(|issue| issue.severity == Severity::Error),
+        format: sniff_mime_type(file).map(str::to_string),
+        issues: issues.0,
+    }
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen(js_name = validateImage)]
+/// Check the integrity of an image file without producing any output.
+/// # Arguments
+/// * `file` - The image file bytes, or a `Blob`.
+/// * `src_type` - The MIME type of the file.
+/// # Returns
+/// The issues found: structural problems (PNG CRCs and chunk order, JPEG marker sequence),
+/// trailing data, broken EXIF entries and decoding errors.
+/// # Errors
+/// Returns an error if the file can't be read.
+pub fn validate_image(file: &ImageInput, src_type: &str) -> Result<ValidationReport, JsValue> {
+    Ok(validate(&Input::new(file)?.read()?, src_type))
+}
+
+#[cfg(not(feature = "wasm"))]
+/// Check the integrity of an image file without producing any output.
+/// # Arguments
+/// * `file` - The image file bytes.
+/// * `src_type` - The MIME type of the file.
+/// # Returns
+/// The issues found: structural problems (PNG CRCs and chunk order, JPEG marker sequence),
+/// trailing data, broken EXIF entries and decoding errors.
+#[must_use]
+pub fn validate_image(file: &[u8], src_type: &str) -> ValidationReport {
+    validate(file, src_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png() -> Vec<u8> {
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgb8(4, 4)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        png
+    }
+
+    fn severities(report: &ValidationReport) -> Vec<Severity> {
+        report.issues.iter().map(|issue| issue.severity).collect()
+    }
+
+    #[test]
+    fn accepts_valid_images() {
+        let report = validate(&png(), "image/png");
+        assert!(report.valid);
+        assert!(report.issues.is_empty());
+        assert_eq!(report.format.as_deref(), Some("image/png"));
+
+        let jpeg = include_bytes!("../../assets/test.jpeg");
+        let report = validate(jpeg, "image/jpeg");
+        assert!(report.valid, "{:?}", report.issues);
+    }
+
+    #[test]
+    fn reports_png_problems() {
+        let mut png = png();
+        png.extend_from_slice(b"garbage");
+        let report = validate(&png, "image/png");
+        assert!(report.valid);
+        assert_eq!(severities(&report), [Severity::Warning]);
+
+        // Corrupt a byte of the IHDR data.
+        png[20] ^= 0xFF;
+        let report = validate(&png, "image/png");
+        assert!(!report.valid);
+        assert_eq!(report.issues[0].message, "CRC mismatch in the IHDR chunk");
+        assert_eq!(report.issues[0].offset, Some(8));
+    }
+
+    #[test]
+    fn reports_oversized_png_chunks() {
+        let mut png = png();
+        // Claim the largest possible IHDR length.
+        png[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        let report = validate(&png, "image/png");
+        assert!(!report.valid);
+        assert_eq!(
+            report.issues[0].message,
+            "The IHDR chunk length exceeds the file"
+        );
+        assert_eq!(report.issues[0].offset, Some(8));
+    }
+
+    #[test]
+    fn reports_jpeg_problems() {
+        let jpeg = include_bytes!("../../assets/test.jpeg");
+        let eoi = jpeg.len() - 2;
+        assert_eq!(jpeg[eoi..], [0xFF, 0xD9]);
+
+        let mut padded = jpeg.to_vec();
+        padded.extend_from_slice(&[0, 0, 0]);
+        let report = validate(&padded, "image/jpeg");
+        assert_eq!(severities(&report), [Severity::Info]);
+
+        let report = validate(&jpeg[..eoi], "image/jpeg");
+        assert!(!report.valid);
+        assert!(report.issues[0].message.contains("end-of-image"));
+
+        // Cut inside the first header segment, which starts right after SOI.
+        let report = validate(&jpeg[..8], "image/jpeg");
+        assert!(report.issues[0]
+            .message
+            .ends_with("segment extends past the end of the file"));
+        assert_eq!(report.issues[0].offset, Some(2));
+
+        let report = validate(jpeg, "image/png");
+        assert!(report.valid);
+        assert_eq!(severities(&report), [Severity::Warning]);
+    }
+
+    #[test]
+    fn rejects_undecodable_files() {
+        let report = validate(b"not an image", "image/png");
+        assert!(!report.valid);
+        assert_eq!(report.format, None);
+    }
+}
@@ -0,0 +1,238 @@
+//! Page access for multi-page TIFF files. `image`'s `TiffDecoder` only reads the first IFD,
+//! so other pages are decoded from a copy of the file whose header points at their IFD.
+
+use std::borrow::Cow;
+
+use image::ImageFormat;
+
+use crate::convert::{encode_target, settings::Settings};
+use crate::detect::resolve_mime_type;
+use crate::error::WasmImageError;
+use crate::load::load_image;
+use crate::source_type::SourceType;
+use crate::tiff::{TiffReader, TAG_IMAGE_LENGTH, TAG_IMAGE_WIDTH};
+
+#[cfg(feature = "wasm")]
+use {
+    crate::convert::settings::SettingsInput,
+    crate::input::{ImageInput, Input},
+    js_sys::{Array, Uint8Array},
+    wasm_bindgen::prelude::*,
+};
+
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[cfg_attr(feature = "wasm", tsify(from_wasm_abi, into_wasm_abi))]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, serde::Deserialize, serde::Serialize)]
+pub struct PageInfo {
+    pub width: u32,
+    pub height: u32,
+}
+
+fn malformed_tiff() -> WasmImageError {
+    WasmImageError::DecoderError("TIFF".to_string(), "Malformed IFD chain".to_string())
+}
+
+/// IFD offsets and sizes of all pages in the main IFD chain.
+fn pages(reader: &TiffReader) -> Option<Vec<(usize, PageInfo)>> {
+    reader
+        .chain()?
+        .iter()
+        .map(|ifd| {
+            let size = |tag| u32::try_from(reader.value(ifd, tag)?).ok();
+            let info = PageInfo {
+                width: size(TAG_IMAGE_WIDTH)?,
+                height: size(TAG_IMAGE_LENGTH)?,
+            };
+            Some((ifd.offset, info))
+        })
+        .collect()
+}
+
+/// Lists the pages of a TIFF file.
+pub(crate) fn tiff_pages(file: &[u8]) -> Result<Vec<PageInfo>, WasmImageError> {
+    let reader = TiffReader::new(file).ok_or_else(malformed_tiff)?;
+    let pages = pages(&reader).ok_or_else(malformed_tiff)?;
+    Ok(pages.into_iter().map(|(_, info)| info).collect())
+}
+
+/// Returns the file with `page` as its first page, so it can be decoded as a single image.
+/// Page 0 is returned as is, and is the only page of non-TIFF formats.
+pub(crate) fn select_page<'a>(
+    file: &'a [u8],
+    source_type: Option<&SourceType>,
+    page: Option<u32>,
+) -> Result<Cow<'a, [u8]>, WasmImageError> {
+    let page = page.unwrap_or(0) as usize;
+    if page == 0 {
+        return Ok(Cow::Borrowed(file));
+    }
+    if !matches!(source_type, Some(SourceType::Raster(ImageFormat::Tiff))) {
+        return Err(WasmImageError::DecoderError(
+            "Page".to_string(),
+            "Only TIFF files have more than one page".to_string(),
+        ));
+    }
+
+    let reader = TiffReader::new(file).ok_or_else(malformed_tiff)?;
+    let pages = pages(&reader).ok_or_else(malformed_tiff)?;
+    let (offset, _) = pages.get(page).ok_or_else(|| {
+        WasmImageError::DecoderError(
+            "Page".to_string(),
+            format!(
+                "Page {page} out of range, the file has {} pages",
+                pages.len()
+            ),
+        )
+    })?;
+
+    let (pos, len) = (reader.header_offset_pos(), reader.offset_size());
+    let bytes = (*offset as u64).to_be_bytes();
+    let mut patched = file.to_vec();
+    let target = &mut patched[pos..pos + len];
+    target.copy_from_slice(&bytes[8 - len..]);
+    if !reader.big_endian {
+        target.reverse();
+    }
+    Ok(Cow::Owned(patched))
+}
+
+/// Converts every page of a file to `target_type`, in page order.
+fn split(
+    file: &[u8],
+    src_type: &str,
+    target_type: &str,
+    settings: Option<&Settings>,
+) -> Result<Vec<Vec<u8>>, WasmImageError> {
+    let src_type = resolve_mime_type(file, src_type)?;
+    let source_type = SourceType::from_mime_type(src_type);
+    let page_count = match source_type {
+        Some(SourceType::Raster(ImageFormat::Tiff)) => tiff_pages(file)?.len(),
+        _ => 1,
+    };
+
+    (0..page_count)
+        .map(|page| {
+            let page_file = select_page(file, source_type.as_ref(), Some(page as u32))?;
+            let img = load_image(&page_file, source_type.as_ref())?;
+            encode_target(&img, &page_file, src_type, target_type, settings)
+        })
+        .collect()
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen(js_name = splitPages)]
+#[allow(clippy::needless_pass_by_value)]
+/// Convert every page of a multi-page file (TIFF) into a separate image.
+/// # Arguments
+/// * `file` - The image file bytes, or a `Blob`.
+/// * `src_type` - The MIME type of the source image.
+/// * `target_type` - The MIME type of the pages.
+/// * `cb` - A callback function to report progress.
+/// * `convert_settings` - Settings for the conversion.
+/// # Returns
+/// An array with one `Uint8Array` per page.
+/// # Errors
+/// Returns an error if a page cannot be loaded or converted.
+pub fn split_pages(
+    file: &ImageInput,
+    src_type: &str,
+    target_type: &str,
+    cb: &js_sys::Function,
+    convert_settings: Option<SettingsInput>,
+) -> Result<Array, JsValue> {
+    crate::progress::report(cb, 10.0, "Starting page split");
+
+    let convert_settings = convert_settings
+        .as_ref()
+        .map(Settings::from_input)
+        .transpose()?;
+    let file = Input::new(file)?.read()?;
+
+    crate::progress::report(cb, 35.0, "Converting pages");
+
+    let pages =
+        split(&file, src_type, target_type, convert_settings.as_ref()).map_err(JsValue::from)?;
+
+    crate::progress::report(cb, 100.0, "Page split complete");
+
+    Ok(pages
+        .iter()
+        .map(|page| Uint8Array::from(page.as_slice()))
+        .collect())
+}
+
+#[cfg(not(feature = "wasm"))]
+/// Convert every page of a multi-page file (TIFF) into a separate image.
+/// # Arguments
+/// * `file` - The image file bytes.
+/// * `src_type` - The MIME type of the source image.
+/// * `target_type` - The MIME type of the pages.
+/// * `convert_settings` - Settings for the conversion.
+/// # Returns
+/// One encoded image per page. Single-page formats yield one image.
+/// # Errors
+/// Returns an error if a page cannot be loaded or converted.
+pub fn split_pages(
+    file: &[u8],
+    src_type: &str,
+    target_type: &str,
+    convert_settings: &Option<Settings>,
+) -> Result<Vec<Vec<u8>>, WasmImageError> {
+    split(file, src_type, target_type, convert_settings.as_ref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+    use tiff::encoder::{colortype, TiffEncoder};
+
+    const SIZES: [(u32, u32); 3] = [(4, 3), (2, 5), (4, 3)];
+
+    fn three_page_tiff() -> Vec<u8> {
+        let mut output = std::io::Cursor::new(Vec::new());
+        let mut encoder = TiffEncoder::new(&mut output).unwrap();
+        for (width, height) in SIZES {
+            let pixels = vec![128u8; (width * height * 3) as usize];
+            encoder
+                .write_image::<colortype::RGB8>(width, height, &pixels)
+                .unwrap();
+        }
+        output.into_inner()
+    }
+
+    #[test]
+    fn lists_and_selects_pages() {
+        let tiff = three_page_tiff();
+        let pages = tiff_pages(&tiff).unwrap();
+        assert_eq!(pages.len(), 3);
+        assert_eq!(
+            pages[1],
+            PageInfo {
+                width: 2,
+                height: 5
+            }
+        );
+
+        let source_type = SourceType::from_mime_type("image/tiff");
+        let second = select_page(&tiff, source_type.as_ref(), Some(1)).unwrap();
+        let img = image::load_from_memory_with_format(&second, ImageFormat::Tiff).unwrap();
+        assert_eq!(img.dimensions(), SIZES[1]);
+
+        assert!(select_page(&tiff, source_type.as_ref(), Some(3)).is_err());
+        let jpeg = SourceType::from_mime_type("image/jpeg");
+        assert!(select_page(&tiff, jpeg.as_ref(), Some(1)).is_err());
+    }
+
+    #[test]
+    fn splits_pages() {
+        let tiff = three_page_tiff();
+        let pages = split(&tiff, "image/tiff", "image/png", None).unwrap();
+        assert_eq!(pages.len(), 3);
+        let dimensions: Vec<_> = pages
+            .iter()
+            .map(|page| image::load_from_memory(page).unwrap().dimensions())
+            .collect();
+        assert_eq!(dimensions, SIZES);
+    }
+}
@@ -0,0 +1,90 @@
+//! Downscaled previews for displaying images, e.g. on a screen-sized canvas. JPEGs are decoded at
+//! 1/2, 1/4 or 1/8 of their size by scaling the inverse DCT, which is much faster and needs less
+//! memory than decoding at full size. Other formats are decoded in full and resized.
+
+use image::{DynamicImage, GrayImage, RgbImage};
+use jpeg_decoder::{Decoder, PixelFormat};
+
+use crate::error::{ErrorStage, WasmImageError};
+use crate::limits::check_dimensions;
+
+/// A JPEG decoded at reduced size.
+pub(crate) struct ScaledJpeg {
+    pub(crate) img: DynamicImage,
+    /// Size of the full image.
+    pub(crate) original: (u32, u32),
+}
+
+/// Decodes a JPEG at the smallest DCT scale whose long side is at least `max_dimension`.
+/// Returns `None` if the JPEG is small enough already or has a pixel format left to the
+/// regular decoder (CMYK, 16-bit grey).
+pub(crate) fn decode_scaled_jpeg(
+    file: &[u8],
+    max_dimension: u32,
+) -> Result<Option<ScaledJpeg>, WasmImageError> {
+    let mut decoder = Decoder::new(file);
+    decoder.read_info().map_err(|e| jpeg_error(&e))?;
+    let Some(info) = decoder.info() else {
+        return Ok(None);
+    };
+    let original = (u32::from(info.width), u32::from(info.height));
+    check_dimensions(original.0, original.1, ErrorStage::Decode)?;
+    if original.0.max(original.1) <= max_dimension
+        || !matches!(info.pixel_format, PixelFormat::L8 | PixelFormat::RGB24)
+    {
+        return Ok(None);
+    }
+
+    let requested = u16::try_from(max_dimension).unwrap_or(u16::MAX);
+    let (width, height) = decoder
+        .scale(requested, requested)
+        .map_err(|e| jpeg_error(&e))?;
+    let (width, height) = (u32::from(width), u32::from(height));
+
+    let pixels = decoder.decode().map_err(|e| jpeg_error(&e))?;
+    let img = match info.pixel_format {
+        PixelFormat::L8 => GrayImage::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8),
+        _ => RgbImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8),
+    }
+    .ok_or_else(|| {
+        WasmImageError::DecoderError("JPEG".to_string(), "Scaled image is too short".to_string())
+    })?;
+    Ok(Some(ScaledJpeg { img, original }))
+}
+
+fn jpeg_error(e: &jpeg_decoder::Error) -> WasmImageError {
+    WasmImageError::DecoderError("JPEG".to_string(), e.to_string())
+}
+
+/// Scales an image down to fit in `max_dimension` × `max_dimension`, keeping its aspect ratio.
+/// Smaller images are returned as they are.
+pub(crate) fn fit(img: DynamicImage, max_dimension: u32) -> DynamicImage {
+    if img.width() > max_dimension || img.height() > max_dimension {
+        img.thumbnail(max_dimension, max_dimension)
+    } else {
+        img
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_jpeg_at_reduced_size() {
+        let file = include_bytes!("../assets/test.jpeg");
+        let full = image::load_from_memory(file).unwrap();
+
+        // A quarter of the 100x68 image is the smallest scale at least 16 pixels wide.
+        let scaled = decode_scaled_jpeg(file, 16).unwrap().unwrap();
+        assert_eq!(scaled.original, (full.width(), full.height()));
+        assert_eq!((scaled.img.width(), scaled.img.height()), (25, 17));
+
+        let img = fit(scaled.img, 16);
+        assert_eq!((img.width(), img.height()), (16, 11));
+
+        assert!(decode_scaled_jpeg(file, full.width().max(full.height()))
+            .unwrap()
+            .is_none());
+    }
+}
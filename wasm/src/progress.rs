@@ -1,4 +1,3 @@
-use js_sys;
 use wasm_bindgen::prelude::*;
 
 /// Calls the progress callback with a `{ progress, message }` JS object.
@@ -0,0 +1,458 @@
+//! Camera RAW support (DNG, CR2, NEF, ARW), enabled by the `raw` feature. Files are either
+//! shown through the JPEG preview the camera embeds, or developed from the sensor data decoded
+//! by the pure Rust `rawloader`, with a basic demosaic, white balance and exposure.
+
+use std::io::Cursor;
+
+use image::{metadata::Orientation, DynamicImage, ImageBuffer, ImageFormat, Rgb};
+use rawloader::{RawImage, RawImageData};
+
+use crate::convert::settings::{RawMode, RawSettings};
+use crate::error::{ErrorStage, WasmImageError};
+use crate::limits::{check_alloc, decode};
+use crate::tiff::{TiffReader, TAG_IMAGE_LENGTH, TAG_IMAGE_WIDTH};
+
+const TAG_COMPRESSION: u16 = 0x0103;
+const TAG_STRIP_OFFSETS: u16 = 0x0111;
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_SAMPLES_PER_PIXEL: u16 = 0x0115;
+const TAG_STRIP_BYTE_COUNTS: u16 = 0x0117;
+const TAG_SUB_IFDS: u16 = 0x014a;
+const TAG_JPEG_OFFSET: u16 = 0x0201;
+const TAG_JPEG_LENGTH: u16 = 0x0202;
+const TAG_EXIF_IFD: u16 = 0x8769;
+
+/// Linear sRGB from CIE XYZ (D65).
+const XYZ_TO_SRGB: [[f32; 3]; 3] = [
+    [3.240_454_2, -1.537_138_5, -0.498_531_4],
+    [-0.969_266, 1.876_010_8, 0.041_556],
+    [0.055_643_4, -0.204_025_9, 1.057_225_2],
+];
+
+fn raw_error(e: &impl ToString) -> WasmImageError {
+    WasmImageError::DecoderError("RAW".to_string(), e.to_string())
+}
+
+fn malformed_raw() -> WasmImageError {
+    raw_error(&"Not a TIFF-based RAW file")
+}
+
+/// Embedded JPEGs, from the `JPEGInterchangeFormat` tags and from single-strip IFDs with JPEG
+/// compression, largest first. Lossless JPEG sensor data (CR2, DNG) is included too, but
+/// fails to decode as a preview.
+fn embedded_jpegs(file: &[u8]) -> Result<Vec<&[u8]>, WasmImageError> {
+    let reader = TiffReader::new(file).ok_or_else(malformed_raw)?;
+    let ifds = reader
+        .all_ifds(&[TAG_SUB_IFDS, TAG_EXIF_IFD])
+        .ok_or_else(malformed_raw)?;
+
+    let mut jpegs: Vec<&[u8]> = ifds
+        .iter()
+        .filter_map(|ifd| {
+            let (offset, len) = match (
+                reader.value(ifd, TAG_JPEG_OFFSET),
+                reader.value(ifd, TAG_JPEG_LENGTH),
+            ) {
+                (Some(offset), Some(len)) => (offset, len),
+                _ if matches!(reader.value(ifd, TAG_COMPRESSION), Some(6 | 7)) => {
+                    let offsets = reader.values(ifd.entry(TAG_STRIP_OFFSETS)?)?;
+                    let lens = reader.values(ifd.entry(TAG_STRIP_BYTE_COUNTS)?)?;
+                    match (offsets.as_slice(), lens.as_slice()) {
+                        ([offset], [len]) => (*offset, *len),
+                        _ => return None,
+                    }
+                }
+                _ => return None,
+            };
+            let start = usize::try_from(offset).ok()?;
+            let end = start.checked_add(usize::try_from(len).ok()?)?;
+            file.get(start..end)
+                .filter(|jpeg| jpeg.starts_with(&[0xff, 0xd8]))
+        })
+        .collect();
+    jpegs.sort_by_key(|jpeg| std::cmp::Reverse(jpeg.len()));
+    jpegs.dedup();
+    Ok(jpegs)
+}
+
+/// The EXIF orientation of the main image, from the first IFD.
+fn orientation(file: &[u8]) -> Orientation {
+    TiffReader::new(file)
+        .and_then(|reader| {
+            let ifd = reader.chain()?.into_iter().next()?;
+            reader.value(&ifd, TAG_ORIENTATION)
+        })
+        .and_then(|value| Orientation::from_exif(u8::try_from(value).ok()?))
+        .unwrap_or(Orientation::NoTransforms)
+}
+
+/// Decodes the largest embedded JPEG preview, or `None` if the file has none.
+pub(crate) fn extract_preview(file: &[u8]) -> Result<Option<DynamicImage>, WasmImageError> {
+    Ok(embedded_jpegs(file)?
+        .into_iter()
+        .find_map(|jpeg| decode(jpeg, ImageFormat::Jpeg).ok()))
+}
+
+/// Size and samples per pixel of the largest image in the file, usually the sensor data.
+fn largest_image(file: &[u8]) -> Result<(u32, u32, u64), WasmImageError> {
+    let reader = TiffReader::new(file).ok_or_else(malformed_raw)?;
+    reader
+        .all_ifds(&[TAG_SUB_IFDS])
+        .ok_or_else(malformed_raw)?
+        .iter()
+        .filter_map(|ifd| {
+            let width = u32::try_from(reader.value(ifd, TAG_IMAGE_WIDTH)?).ok()?;
+            let height = u32::try_from(reader.value(ifd, TAG_IMAGE_LENGTH)?).ok()?;
+            let samples = reader.value(ifd, TAG_SAMPLES_PER_PIXEL).unwrap_or(1);
+            Some((width, height, samples))
+        })
+        .max_by_key(|(width, height, _)| u64::from(*width) * u64::from(*height))
+        .ok_or_else(malformed_raw)
+}
+
+/// Size of the largest image in the file, usually the sensor data.
+pub(crate) fn raw_dimensions(file: &[u8]) -> Result<(u32, u32), WasmImageError> {
+    let (width, height, _) = largest_image(file)?;
+    Ok((width, height))
+}
+
+/// Peak memory of developing sensor data: the samples decoded by `rawloader`, their `f32` copy
+/// in [`Samples`] and the 16-bit RGB output.
+fn develop_bytes(samples: u64, sample_size: u64, output_pixels: u64) -> u64 {
+    samples
+        .saturating_mul(sample_size + 4)
+        .saturating_add(output_pixels.saturating_mul(6))
+}
+
+/// Sensor samples scaled to `0.0..=1.0` between the black and white levels.
+struct Samples {
+    data: Vec<f32>,
+    width: usize,
+    height: usize,
+    cpp: usize,
+}
+
+impl Samples {
+    fn new(raw: &RawImage) -> Self {
+        let data = match &raw.data {
+            RawImageData::Integer(data) => data
+                .iter()
+                .enumerate()
+                .map(|(i, &value)| {
+                    let channel = if raw.cpp == 1 {
+                        let (row, col) = (i / raw.width, i % raw.width);
+                        raw.cfa.color_at(row, col).min(3)
+                    } else {
+                        i % raw.cpp
+                    };
+                    let black = f32::from(raw.blacklevels[channel]);
+                    let white = f32::from(raw.whitelevels[channel]);
+                    ((f32::from(value) - black) / (white - black).max(1.0)).max(0.0)
+                })
+                .collect(),
+            RawImageData::Float(data) => data.clone(),
+        };
+        Self {
+            data,
+            width: raw.width,
+            height: raw.height,
+            cpp: raw.cpp,
+        }
+    }
+
+    fn get(&self, row: usize, col: usize, channel: usize) -> f32 {
+        self.data[(row * self.width + col) * self.cpp + channel]
+    }
+}
+
+/// Camera colour channels of a CFA pixel, interpolated from the same-coloured neighbours in the
+/// surrounding 3x3 block. This is bilinear for Bayer sensors and also covers X-Trans.
+fn demosaic(raw: &RawImage, samples: &Samples, row: usize, col: usize) -> [f32; 4] {
+    let own = raw.cfa.color_at(row, col).min(3);
+    let mut sums = [0.0f32; 4];
+    let mut counts = [0u32; 4];
+    for r in row.saturating_sub(1)..=(row + 1).min(samples.height - 1) {
+        for c in col.saturating_sub(1)..=(col + 1).min(samples.width - 1) {
+            let color = raw.cfa.color_at(r, c).min(3);
+            sums[color] += samples.get(r, c, 0);
+            counts[color] += 1;
+        }
+    }
+    let mut cam = [0.0; 4];
+    for (color, value) in cam.iter_mut().enumerate() {
+        if counts[color] > 0 {
+            *value = sums[color] / counts[color] as f32;
+        }
+    }
+    cam[own] = samples.get(row, col, 0);
+    cam
+}
+
+/// Camera white balance multipliers, relative to green.
+fn white_balance(raw: &RawImage, settings: &RawSettings) -> [f32; 4] {
+    let coeffs = match settings.white_balance {
+        Some([red, green, blue]) => [red, green, blue, green],
+        None if raw.wb_coeffs[..3].iter().all(|c| c.is_finite() && *c > 0.0) => raw.wb_coeffs,
+        None => raw.neutralwb(),
+    };
+    let green = if coeffs[1] > 0.0 { coeffs[1] } else { 1.0 };
+    let mut wb = coeffs.map(|c| if c.is_finite() { c / green } else { 1.0 });
+    if wb[3].is_nan() || wb[3] <= 0.0 {
+        wb[3] = wb[1];
+    }
+    wb
+}
+
+/// Camera RGB to linear sRGB, with rows normalized so that white stays white. `None` if the
+/// camera has no colour matrix.
+fn camera_to_srgb(raw: &RawImage) -> Option<[[f32; 4]; 3]> {
+    if raw.xyz_to_cam.iter().flatten().all(|c| *c == 0.0) {
+        return None;
+    }
+    let cam_to_xyz = raw.cam_to_xyz_normalized();
+    let mut matrix = [[0.0f32; 4]; 3];
+    for (i, row) in matrix.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| XYZ_TO_SRGB[i][k] * cam_to_xyz[k][j]).sum();
+        }
+        let sum: f32 = row.iter().sum();
+        if sum.abs() > f32::EPSILON {
+            for value in row.iter_mut() {
+                *value /= sum;
+            }
+        }
+    }
+    Some(matrix)
+}
+
+/// Encodes linear light as 16-bit sRGB.
+fn srgb_gamma(c: f32) -> u16 {
+    let c = c.clamp(0.0, 1.0);
+    let encoded = if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * f32::from(u16::MAX)).round() as u16
+}
+
+/// Develops decoded sensor data: black/white level scaling, demosaic, white balance, camera
+/// to sRGB conversion and exposure.
+fn develop_image(raw: &RawImage, settings: &RawSettings) -> Result<DynamicImage, WasmImageError> {
+    let [top, right, bottom, left] = raw.crops;
+    let width = raw.width.saturating_sub(left + right);
+    let height = raw.height.saturating_sub(top + bottom);
+    let (width, height, top, left) = if width == 0 || height == 0 {
+        (raw.width, raw.height, 0, 0)
+    } else {
+        (width, height, top, left)
+    };
+    let out_width = u32::try_from(width).map_err(|e| raw_error(&e))?;
+    let out_height = u32::try_from(height).map_err(|e| raw_error(&e))?;
+
+    let (samples, sample_size) = match &raw.data {
+        RawImageData::Integer(data) => (data.len(), 2),
+        RawImageData::Float(data) => (data.len(), 4),
+    };
+    check_alloc(
+        develop_bytes(
+            samples as u64,
+            sample_size,
+            u64::from(out_width) * u64::from(out_height),
+        ),
+        ErrorStage::Decode,
+    )?;
+
+    let samples = Samples::new(raw);
+    let wb = white_balance(raw, settings);
+    let matrix = camera_to_srgb(raw);
+    let gain = settings.exposure.exp2();
+    let monochrome = raw.is_monochrome();
+
+    let img = ImageBuffer::from_fn(out_width, out_height, |x, y| {
+        let (row, col) = (y as usize + top, x as usize + left);
+        let cam = match (raw.cpp, monochrome) {
+            (1, true) => [samples.get(row, col, 0); 4],
+            (1, false) => demosaic(raw, &samples, row, col),
+            _ => {
+                let channel = |c: usize| samples.get(row, col, c.min(samples.cpp - 1));
+                [channel(0), channel(1), channel(2), channel(1)]
+            }
+        };
+        let cam: [f32; 4] = std::array::from_fn(|c| cam[c] * wb[c]);
+        let rgb = match &matrix {
+            Some(matrix) if !monochrome => {
+                matrix.map(|row| row.iter().zip(cam).map(|(m, c)| m * c).sum::<f32>())
+            }
+            _ => [cam[0], cam[1], cam[2]],
+        };
+        Rgb(rgb.map(|c| srgb_gamma(c * gain)))
+    });
+    Ok(DynamicImage::ImageRgb16(img))
+}
+
+/// Develops the sensor data of a RAW file into a 16-bit sRGB image.
+pub(crate) fn develop(file: &[u8], settings: &RawSettings) -> Result<DynamicImage, WasmImageError> {
+    // Checked from the TIFF tags before `rawloader` allocates anything, and again with the
+    // actual sensor layout in `develop_image`.
+    let (width, height, samples_per_pixel) = largest_image(file)?;
+    let pixels = u64::from(width) * u64::from(height);
+    check_alloc(
+        develop_bytes(pixels.saturating_mul(samples_per_pixel), 2, pixels),
+        ErrorStage::Decode,
+    )?;
+    let raw = rawloader::decode(&mut Cursor::new(file)).map_err(|e| raw_error(&e))?;
+    develop_image(&raw, settings)
+}
+
+/// Decodes a RAW file in the mode chosen by `settings`, upright.
+pub(crate) fn decode_raw(
+    file: &[u8],
+    settings: &RawSettings,
+) -> Result<DynamicImage, WasmImageError> {
+    let preview = match settings.mode {
+        RawMode::Preview => extract_preview(file)?,
+        RawMode::Develop => None,
+    };
+    let mut img = match preview {
+        Some(preview) => preview,
+        None => develop(file, settings)?,
+    };
+    img.apply_orientation(orientation(file));
+    Ok(img)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+    use tiff::encoder::{colortype, TiffEncoder};
+
+    /// A TIFF whose first IFD holds a small image and points at a JPEG preview.
+    fn tiff_with_preview() -> (Vec<u8>, Vec<u8>) {
+        let mut jpeg = Vec::new();
+        DynamicImage::ImageRgb8(image::RgbImage::from_pixel(24, 16, Rgb([200, 30, 30])))
+            .write_to(&mut Cursor::new(&mut jpeg), ImageFormat::Jpeg)
+            .unwrap();
+
+        let mut output = Cursor::new(Vec::new());
+        let mut encoder = TiffEncoder::new(&mut output).unwrap();
+        let mut image = encoder.new_image::<colortype::RGB8>(4, 2).unwrap();
+        let tags = [
+            (TAG_JPEG_OFFSET, 0),
+            (TAG_JPEG_LENGTH, jpeg.len() as u32),
+            (TAG_ORIENTATION, 6),
+        ];
+        for (tag, value) in tags {
+            image
+                .encoder()
+                .write_tag(tiff::tags::Tag::Unknown(tag), value)
+                .unwrap();
+        }
+        image.write_data(&[0; 4 * 2 * 3]).unwrap();
+
+        let mut file = output.into_inner();
+        let offset = u32::try_from(file.len()).unwrap();
+        file.extend_from_slice(&jpeg);
+
+        // Point the `JPEGInterchangeFormat` tag at the appended preview.
+        let ifd = u32::from_le_bytes(file[4..8].try_into().unwrap()) as usize;
+        let count = u16::from_le_bytes([file[ifd], file[ifd + 1]]) as usize;
+        for entry in (0..count).map(|i| ifd + 2 + i * 12) {
+            if u16::from_le_bytes([file[entry], file[entry + 1]]) == TAG_JPEG_OFFSET {
+                file[entry + 8..entry + 12].copy_from_slice(&offset.to_le_bytes());
+            }
+        }
+        (file, jpeg)
+    }
+
+    /// A 4x4 RGGB sensor where every photosite reads the same, with a 2x red and 1.5x blue
+    /// white balance and no colour matrix.
+    fn flat_raw(value: u16) -> RawImage {
+        RawImage {
+            make: String::new(),
+            model: String::new(),
+            clean_make: String::new(),
+            clean_model: String::new(),
+            width: 4,
+            height: 4,
+            cpp: 1,
+            wb_coeffs: [2.0, 1.0, 1.5, f32::NAN],
+            whitelevels: [1000; 4],
+            blacklevels: [0; 4],
+            xyz_to_cam: [[0.0; 3]; 4],
+            cfa: rawloader::CFA::new("RGGB"),
+            crops: [0, 0, 1, 1],
+            blackareas: Vec::new(),
+            orientation: rawloader::Orientation::Normal,
+            data: RawImageData::Integer(vec![value; 16]),
+        }
+    }
+
+    #[test]
+    fn extracts_preview() {
+        let (file, jpeg) = tiff_with_preview();
+        assert_eq!(embedded_jpegs(&file).unwrap(), vec![jpeg.as_slice()]);
+        assert_eq!(raw_dimensions(&file).unwrap(), (4, 2));
+
+        // The preview is rotated by the orientation of the main image.
+        let img = decode_raw(&file, &RawSettings::default()).unwrap();
+        assert_eq!(img.dimensions(), (16, 24));
+        let [red, green, _, _] = img.get_pixel(8, 12).0;
+        assert!(red > 150 && green < 80);
+    }
+
+    #[test]
+    fn develops_sensor_data() {
+        let raw = flat_raw(250);
+        let img = develop_image(&raw, &RawSettings::default()).unwrap();
+        assert_eq!(img.dimensions(), (3, 3));
+        let [red, green, blue] = img.to_rgb16().get_pixel(1, 1).0;
+        assert!(red > green && blue > green && red > blue);
+
+        let neutral = RawSettings {
+            white_balance: Some([1.0, 1.0, 1.0]),
+            ..RawSettings::default()
+        };
+        let [red, green, blue] = develop_image(&raw, &neutral)
+            .unwrap()
+            .to_rgb16()
+            .get_pixel(1, 1)
+            .0;
+        assert_eq!((red, green), (green, blue));
+
+        let brighter = RawSettings {
+            exposure: 1.0,
+            ..neutral
+        };
+        let bright = develop_image(&raw, &brighter)
+            .unwrap()
+            .to_rgb16()
+            .get_pixel(1, 1)
+            .0;
+        assert!(bright[1] > green);
+    }
+
+    #[test]
+    fn checks_development_memory() {
+        // 16-bit samples, their `f32` copy and 16-bit RGB output: 12 bytes per photosite.
+        assert_eq!(develop_bytes(100, 2, 100), 1200);
+
+        let huge = RawImage {
+            width: 100_000,
+            height: 100_000,
+            crops: [0; 4],
+            ..flat_raw(250)
+        };
+        assert!(matches!(
+            develop_image(&huge, &RawSettings::default()),
+            Err(WasmImageError::LimitExceeded(ErrorStage::Decode, _))
+        ));
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        let jpeg = include_bytes!("../assets/test.jpeg");
+        assert!(decode_raw(jpeg, &RawSettings::default()).is_err());
+    }
+}
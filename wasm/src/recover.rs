@@ -0,0 +1,187 @@
+//! Lenient decoding of truncated or corrupt raster images. Instead of failing, the pixels that
+//! were decoded are kept, the rest is filled with a placeholder colour, and the problems are
+//! returned as warnings, the same way EXIF errors are collected by `load_metadata`.
+
+use image::{
+    ColorType, DynamicImage, GenericImage, ImageDecoder, ImageError, ImageFormat, ImageResult, Rgba,
+};
+
+use crate::error::WasmImageError;
+use crate::limits::{decoder, limit_error};
+
+/// Fill of the pixels that couldn't be decoded. Mid grey, like JPEG decoders use for missing scans.
+pub(crate) const PLACEHOLDER: Rgba<u8> = Rgba([128, 128, 128, 255]);
+
+/// Pixel data that was already decoded, handed to [`DynamicImage::from_decoder`] to build an
+/// image of the original colour type.
+struct Decoded {
+    width: u32,
+    height: u32,
+    color: ColorType,
+    bytes: Vec<u8>,
+}
+
+impl ImageDecoder for Decoded {
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.color
+    }
+
+    fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+        buf.copy_from_slice(&self.bytes);
+        Ok(())
+    }
+
+    fn read_image_boxed(self: Box<Self>, buf: &mut [u8]) -> ImageResult<()> {
+        (*self).read_image(buf)
+    }
+}
+
+/// Decodes the image into a buffer filled with `initial` beforehand, so bytes the decoder never
+/// wrote keep that value.
+fn read_into(
+    file: &[u8],
+    format: ImageFormat,
+    initial: u8,
+) -> Result<(Decoded, ImageResult<()>), WasmImageError> {
+    let reader = decoder(file, format)?;
+    let (width, height) = reader.dimensions();
+    let color = reader.color_type();
+    let mut bytes = vec![initial; reader.total_bytes() as usize];
+    let result = reader.read_image(&mut bytes);
+    let decoded = Decoded {
+        width,
+        height,
+        color,
+        bytes,
+    };
+    Ok((decoded, result))
+}
+
+/// Whether a JPEG file ends with its end-of-image marker. Truncated JPEGs still decode, with the
+/// missing scans filled in by the decoder.
+fn jpeg_is_complete(file: &[u8]) -> bool {
+    let end = file
+        .iter()
+        .rposition(|&b| b != 0)
+        .map_or(0, |last| last + 1);
+    file[..end].ends_with(&[0xff, 0xd9])
+}
+
+/// Describes which part of the image was decoded.
+fn coverage_warning(written: &[bool], width: u32, height: u32) -> String {
+    let width = width as usize;
+    let rows = written
+        .chunks(width)
+        .take_while(|row| row.iter().all(|&w| w))
+        .count();
+    let written_count = written.iter().filter(|&&w| w).count();
+    if written_count == rows * width {
+        format!("Only the first {rows} of {height} rows could be decoded")
+    } else {
+        let percent = written_count * 100 / written.len();
+        format!("Only {percent}% of the pixels could be decoded")
+    }
+}
+
+/// Decodes an image, keeping what was decoded if the data is truncated or corrupt.
+/// # Errors
+/// Returns an error if the header can't be read, a limit is exceeded, or no pixel was decoded.
+pub(crate) fn decode_partial(
+    file: &[u8],
+    format: ImageFormat,
+) -> Result<(DynamicImage, Vec<String>), WasmImageError> {
+    let mut warnings = Vec::new();
+    if format == ImageFormat::Jpeg && !jpeg_is_complete(file) {
+        warnings.push(
+            "The JPEG data is truncated, missing parts are filled in by the decoder".to_string(),
+        );
+    }
+
+    let (decoded, result) = read_into(file, format, 0x00)?;
+    let error = match result {
+        Ok(()) => return Ok((DynamicImage::from_decoder(decoded)?, warnings)),
+        Err(e @ ImageError::Limits(_)) => return Err(limit_error(e)),
+        Err(e) => e,
+    };
+
+    // Bytes the decoder wrote are the same whatever the buffer was filled with.
+    let (filled, _) = read_into(file, format, 0xff)?;
+    let bytes_per_pixel = usize::from(decoded.color.bytes_per_pixel());
+    let written: Vec<bool> = decoded
+        .bytes
+        .chunks_exact(bytes_per_pixel)
+        .zip(filled.bytes.chunks_exact(bytes_per_pixel))
+        .map(|(a, b)| a == b)
+        .collect();
+    if !written.contains(&true) {
+        return Err(error.into());
+    }
+
+    let (width, height) = (decoded.width, decoded.height);
+    warnings.push(format!("The image data is incomplete: {error}"));
+    warnings.push(coverage_warning(&written, width, height));
+
+    let mut img = DynamicImage::from_decoder(decoded)?;
+    for (i, _) in written.iter().enumerate().filter(|(_, &w)| !w) {
+        let (x, y) = ((i % width as usize) as u32, (i / width as usize) as u32);
+        img.put_pixel(x, y, PLACEHOLDER);
+    }
+    Ok((img, warnings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient_png() -> Vec<u8> {
+        let img =
+            image::RgbImage::from_fn(16, 64, |x, y| image::Rgb([x as u8 * 16, y as u8 * 4, 200]));
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(img)
+            .write_to(&mut std::io::Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+        png
+    }
+
+    #[test]
+    fn recovers_truncated_png() {
+        let png = gradient_png();
+        let truncated = &png[..png.len() * 3 / 5];
+        assert!(image::load_from_memory_with_format(truncated, ImageFormat::Png).is_err());
+
+        let (img, warnings) = decode_partial(truncated, ImageFormat::Png).unwrap();
+        assert_eq!((img.width(), img.height()), (16, 64));
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[1].starts_with("Only the first"));
+
+        let img = img.to_rgba8();
+        assert_eq!(img.get_pixel(1, 0), &Rgba([16, 0, 200, 255]));
+        assert_eq!(img.get_pixel(1, 63), &PLACEHOLDER);
+    }
+
+    #[test]
+    fn keeps_complete_images() {
+        let png = gradient_png();
+        let (img, warnings) = decode_partial(&png, ImageFormat::Png).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(img.to_rgb8().get_pixel(1, 63), &image::Rgb([16, 252, 200]));
+
+        let jpeg = include_bytes!("../assets/test.jpeg");
+        assert!(decode_partial(jpeg, ImageFormat::Jpeg)
+            .unwrap()
+            .1
+            .is_empty());
+        let (_, warnings) = decode_partial(&jpeg[..jpeg.len() * 4 / 5], ImageFormat::Jpeg).unwrap();
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn fails_without_pixel_data() {
+        let png = gradient_png();
+        assert!(decode_partial(&png[..40], ImageFormat::Png).is_err());
+    }
+}
@@ -1,12 +1,40 @@
 use std::io::Cursor;
 
-use crate::error::WasmImageError;
+use crate::convert::settings::{Settings, SvgSettings};
+use crate::detect::resolve_mime_type;
+use crate::error::{ErrorStage, WasmImageError};
+use crate::limits::check_dimensions;
 use crate::load::{load_image, SourceImage};
 use crate::source_type::SourceType;
-use image::{imageops::FilterType, ImageFormat};
+use image::{
+    imageops::{self, FilterType},
+    DynamicImage, ImageFormat, RgbaImage,
+};
 
 #[cfg(feature = "wasm")]
-use {js_sys::Uint8Array, wasm_bindgen::prelude::*};
+use {
+    crate::input::{ImageInput, Input},
+    js_sys::Uint8Array,
+    wasm_bindgen::prelude::*,
+};
+
+/// Filter used for every resize, including generated mipmaps.
+const FILTER: FilterType = FilterType::Lanczos3;
+
+/// The image as RGBA, followed by its mipmaps if `mipmaps` is set: each level half the size of
+/// the previous one, down to 1x1.
+pub(crate) fn mip_chain(img: &DynamicImage, mipmaps: bool) -> Vec<RgbaImage> {
+    let full = img.to_rgba8();
+    if !mipmaps {
+        return vec![full];
+    }
+    std::iter::successors(Some(full), |level| {
+        let (width, height) = level.dimensions();
+        (width > 1 || height > 1)
+            .then(|| imageops::resize(level, (width / 2).max(1), (height / 2).max(1), FILTER))
+    })
+    .collect()
+}
 
 fn resize_and_write(
     img: &SourceImage,
@@ -15,15 +43,26 @@ fn resize_and_write(
     height: u32,
 ) -> Result<Vec<u8>, WasmImageError> {
     if width == 0 || height == 0 {
-        return Err(WasmImageError::EncodingError(
+        return Err(WasmImageError::InvalidSettings(
             "Width and height must be greater than 0".to_string(),
         ));
     }
-    let img = img.rasterize(None)?;
+    check_dimensions(width, height, ErrorStage::Resize)?;
+    // Render SVGs close to the target size instead of scaling up their intrinsic size.
+    let settings = Settings::Svg(SvgSettings {
+        width: Some(width),
+        height: Some(height),
+        ..SvgSettings::default()
+    });
+    let img = img.rasterize(Some(&settings))?;
 
-    let resized = img.resize_exact(width, height, FilterType::Lanczos3);
+    let resized = img.resize_exact(width, height, FILTER);
 
     let format = source_type.unwrap_or(ImageFormat::Png);
+    // `image` can't write DDS, resized textures are stored uncompressed with new mipmaps.
+    if format == ImageFormat::Dds {
+        return crate::texture::encode_dds(&mip_chain(&resized, true));
+    }
 
     let mut output: Vec<u8> = Vec::new();
 
@@ -37,7 +76,7 @@ fn resize_and_write(
 #[allow(clippy::needless_pass_by_value)]
 /// Resize an image to exact dimensions, preserving the source format.
 /// # Arguments
-/// * `file` - The image file bytes.
+/// * `file` - The image file bytes, or a `Blob`.
 /// * `src_type` - The MIME type of the source image.
 /// * `width` - Target width in pixels.
 /// * `height` - Target height in pixels.
@@ -45,27 +84,26 @@ fn resize_and_write(
 /// # Errors
 /// Returns an error if loading, resizing, or encoding fails.
 pub fn resize_image(
-    file: &Uint8Array,
+    file: &ImageInput,
     src_type: &str,
     width: u32,
     height: u32,
     cb: &js_sys::Function,
 ) -> Result<Uint8Array, JsValue> {
-    let src_mime_type = SourceType::from_mime_type(src_type);
-
     crate::progress::report(cb, 10.0, "Starting resize");
 
-    let file = file.to_vec();
+    let file = Input::new(file)?.read()?;
+    let src_type = resolve_mime_type(&file, src_type).map_err(JsValue::from)?;
+    let src_mime_type = SourceType::from_mime_type(src_type);
 
     crate::progress::report(cb, 35.0, "Loading image");
 
-    let img = load_image(&file, src_mime_type.as_ref())
-        .map_err(|e| JsValue::from_str(e.to_string().as_str()))?;
+    let img = load_image(&file, src_mime_type.as_ref()).map_err(JsValue::from)?;
 
     crate::progress::report(cb, 60.0, "Resizing image");
 
     let output = resize_and_write(&img, ImageFormat::from_mime_type(src_type), width, height)
-        .map_err(|e| JsValue::from_str(e.to_string().as_str()))?;
+        .map_err(JsValue::from)?;
 
     crate::progress::report(cb, 100.0, "Resize complete");
 
@@ -87,6 +125,7 @@ pub fn resize_image(
     width: u32,
     height: u32,
 ) -> Result<Vec<u8>, WasmImageError> {
+    let src_type = resolve_mime_type(file, src_type)?;
     let src_mime_type = SourceType::from_mime_type(src_type);
     let img = load_image(file, src_mime_type.as_ref())?;
     resize_and_write(&img, ImageFormat::from_mime_type(src_type), width, height)
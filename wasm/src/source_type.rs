@@ -3,6 +3,15 @@ use image::ImageFormat;
 pub enum SourceType {
     Raster(ImageFormat),
     Svg,
+    /// HEIC/HEIF, decoded outside of `image`.
+    #[cfg(feature = "heic")]
+    Heif,
+    /// JPEG XL, decoded outside of `image`.
+    #[cfg(feature = "jxl")]
+    Jxl,
+    /// TIFF-based camera RAW (DNG, CR2, NEF, ARW).
+    #[cfg(feature = "raw")]
+    Raw,
 }
 
 impl SourceType {
@@ -17,10 +26,22 @@ impl SourceType {
 /// This function is used to handle types that don't usually have an associated mime type
 fn from_custom_mime_type(mime_type: &str) -> Option<SourceType> {
     match mime_type {
-        // SVG
-        "image/svg+xml" => Some(SourceType::Svg),
+        // SVG, and gzip-compressed SVGZ
+        "image/svg+xml" | "image/svg+xml-compressed" => Some(SourceType::Svg),
         // Farbfeld (often application/octet-stream is not inferred by image-rs, see https://docs.rs/image/0.25.5/src/image/image.rs.html#166-193)
         "image/farbfeld" => Some(SourceType::Raster(ImageFormat::Farbfeld)),
+        // HEIC/HEIF (iPhone photos)
+        #[cfg(feature = "heic")]
+        "image/heic" | "image/heif" | "image/heic-sequence" | "image/heif-sequence" => {
+            Some(SourceType::Heif)
+        }
+        // JPEG XL
+        #[cfg(feature = "jxl")]
+        "image/jxl" => Some(SourceType::Jxl),
+        // Camera RAW
+        #[cfg(feature = "raw")]
+        "image/x-adobe-dng" | "image/dng" | "image/x-canon-cr2" | "image/x-nikon-nef"
+        | "image/x-sony-arw" => Some(SourceType::Raw),
         _ => None,
     }
 }
@@ -0,0 +1,27 @@
+use image::ImageFormat;
+
+pub enum TargetType {
+    Raster(ImageFormat),
+    Svg,
+    Pdf,
+    /// Lossless JPEG XL, encoded outside of `image`.
+    #[cfg(feature = "jxl-encode")]
+    Jxl,
+    /// Uncompressed DDS or KTX2 texture with a mipmap chain.
+    Dds,
+    Ktx2,
+}
+
+impl TargetType {
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        match mime_type {
+            "image/svg+xml" => Some(Self::Svg),
+            "application/pdf" => Some(Self::Pdf),
+            "image/vnd-ms.dds" => Some(Self::Dds),
+            "image/ktx2" => Some(Self::Ktx2),
+            #[cfg(feature = "jxl-encode")]
+            "image/jxl" => Some(Self::Jxl),
+            _ => ImageFormat::from_mime_type(mime_type).map(Self::Raster),
+        }
+    }
+}
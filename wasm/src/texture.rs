@@ -0,0 +1,434 @@
+//! GPU texture containers. `image` only decodes block-compressed DDS (DXT1/3/5) and encodes no
+//! texture format, so uncompressed DDS, the header details and DDS/KTX2 export live here.
+
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+
+use crate::error::WasmImageError;
+use crate::limits::decode;
+
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+const DDS_HEADER_END: usize = 128;
+const DX10_HEADER_END: usize = DDS_HEADER_END + 20;
+
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PITCH: u32 = 0x8;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDSD_MIPMAPCOUNT: u32 = 0x2_0000;
+
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x2_0000;
+
+const DDSCAPS_COMPLEX: u32 = 0x8;
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+const DDSCAPS_MIPMAP: u32 = 0x40_0000;
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xab, 0x4b, 0x54, 0x58, 0x20, 0x32, 0x30, 0xbb, 0x0d, 0x0a, 0x1a, 0x0a,
+];
+const VK_FORMAT_R8G8B8A8_SRGB: u32 = 43;
+
+/// Texture details of a DDS file.
+#[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
+#[cfg_attr(feature = "wasm", tsify(from_wasm_abi, into_wasm_abi))]
+#[derive(Clone, PartialEq, Eq, Debug, serde::Deserialize, serde::Serialize)]
+pub struct TextureInfo {
+    /// Number of mipmap levels, including the full-size image.
+    pub mip_count: u32,
+    /// Pixel format, e.g. `DXT5`, `BC7 (DX10)` or `Uncompressed RGBA32`.
+    pub format: String,
+}
+
+enum PixelFormat {
+    /// Block-compressed, identified by its four-character code or DXGI format name.
+    Compressed(String),
+    /// Packed pixels of `bits` bits, with one mask per channel (red, green, blue, alpha).
+    /// Luminance files have the same mask for all three colour channels.
+    Masked { bits: u32, masks: [u32; 4] },
+}
+
+struct DdsHeader {
+    width: u32,
+    height: u32,
+    mip_count: u32,
+    format: PixelFormat,
+    data_offset: usize,
+}
+
+fn malformed_dds(message: &str) -> WasmImageError {
+    WasmImageError::DecoderError("DDS".to_string(), message.to_string())
+}
+
+fn read_u32(file: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(file.get(pos..pos + 4)?.try_into().ok()?))
+}
+
+/// Name of a DXGI format of the DX10 extension header, or its packed layout.
+fn dxgi_format(format: u32) -> PixelFormat {
+    const RGBA: [u32; 4] = [0xff, 0xff00, 0xff_0000, 0xff00_0000];
+    const BGRA: [u32; 4] = [0xff_0000, 0xff00, 0xff, 0xff00_0000];
+    let name = match format {
+        28 | 29 => {
+            return PixelFormat::Masked {
+                bits: 32,
+                masks: RGBA,
+            }
+        }
+        87 | 91 => {
+            return PixelFormat::Masked {
+                bits: 32,
+                masks: BGRA,
+            }
+        }
+        70..=72 => "BC1",
+        73..=75 => "BC2",
+        76..=78 => "BC3",
+        79..=81 => "BC4",
+        82..=84 => "BC5",
+        94..=96 => "BC6H",
+        97..=99 => "BC7",
+        _ => return PixelFormat::Compressed(format!("DXGI format {format} (DX10)")),
+    };
+    PixelFormat::Compressed(format!("{name} (DX10)"))
+}
+
+fn parse_dds(file: &[u8]) -> Result<DdsHeader, WasmImageError> {
+    if !file.starts_with(DDS_MAGIC) {
+        return Err(malformed_dds("DDS signature not found"));
+    }
+    let field = |pos| read_u32(file, pos).ok_or_else(|| malformed_dds("Truncated header"));
+    if field(4)? != 124 || field(76)? != 32 {
+        return Err(malformed_dds("Invalid header size"));
+    }
+
+    let flags = field(80)?;
+    let (format, data_offset) = if flags & DDPF_FOURCC != 0 {
+        let fourcc = file
+            .get(84..88)
+            .ok_or_else(|| malformed_dds("Truncated header"))?;
+        if fourcc == b"DX10" {
+            (dxgi_format(field(DDS_HEADER_END)?), DX10_HEADER_END)
+        } else {
+            let name = String::from_utf8_lossy(fourcc)
+                .trim_end_matches('\0')
+                .to_string();
+            (PixelFormat::Compressed(name), DDS_HEADER_END)
+        }
+    } else if flags & (DDPF_RGB | DDPF_LUMINANCE) != 0 {
+        let alpha = if flags & DDPF_ALPHAPIXELS != 0 {
+            field(104)?
+        } else {
+            0
+        };
+        let red = field(92)?;
+        let masks = if flags & DDPF_LUMINANCE != 0 {
+            [red, red, red, alpha]
+        } else {
+            [red, field(96)?, field(100)?, alpha]
+        };
+        let bits = field(88)?;
+        if !matches!(bits, 8 | 16 | 24 | 32) {
+            return Err(malformed_dds("Unsupported bit count"));
+        }
+        (PixelFormat::Masked { bits, masks }, DDS_HEADER_END)
+    } else {
+        return Err(malformed_dds("Unsupported pixel format"));
+    };
+
+    Ok(DdsHeader {
+        width: field(16)?,
+        height: field(12)?,
+        mip_count: field(28)?.max(1),
+        format,
+        data_offset,
+    })
+}
+
+/// Size of the top mip level of a DDS file.
+pub(crate) fn dds_dimensions(file: &[u8]) -> Result<(u32, u32), WasmImageError> {
+    let header = parse_dds(file)?;
+    Ok((header.width, header.height))
+}
+
+/// Mip count and pixel format of a DDS file.
+pub(crate) fn dds_info(file: &[u8]) -> Result<TextureInfo, WasmImageError> {
+    let header = parse_dds(file)?;
+    let format = match header.format {
+        PixelFormat::Compressed(name) => name,
+        PixelFormat::Masked { bits, masks } => {
+            let channels = match (masks[0] == masks[1], masks[3] != 0) {
+                (true, false) => "L",
+                (true, true) => "LA",
+                (false, false) => "RGB",
+                (false, true) => "RGBA",
+            };
+            format!("Uncompressed {channels}{bits}")
+        }
+    };
+    Ok(TextureInfo {
+        mip_count: header.mip_count,
+        format,
+    })
+}
+
+/// Scales the bits of `mask` in `value` to 8 bits.
+fn channel(value: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    let max = u64::from(mask >> mask.trailing_zeros());
+    let v = u64::from((value & mask) >> mask.trailing_zeros());
+    ((v * 255 + max / 2) / max) as u8
+}
+
+/// Decodes the top mip level of a DDS file. Block-compressed files go through `image`.
+pub(crate) fn decode_dds(file: &[u8]) -> Result<DynamicImage, WasmImageError> {
+    let header = parse_dds(file)?;
+    let PixelFormat::Masked { bits, masks } = header.format else {
+        return decode(file, ImageFormat::Dds);
+    };
+
+    let bytes = (bits / 8) as usize;
+    let (width, height) = (header.width as usize, header.height as usize);
+    let len = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(bytes))
+        .ok_or_else(|| malformed_dds("Image too large"))?;
+    let data = file
+        .get(header.data_offset..)
+        .and_then(|data| data.get(..len))
+        .ok_or_else(|| malformed_dds("Truncated pixel data"))?;
+
+    let img = RgbaImage::from_fn(header.width, header.height, |x, y| {
+        let pos = (y as usize * width + x as usize) * bytes;
+        let value = data[pos..pos + bytes]
+            .iter()
+            .rev()
+            .fold(0u32, |acc, &b| (acc << 8) | u32::from(b));
+        let alpha = if masks[3] == 0 {
+            255
+        } else {
+            channel(value, masks[3])
+        };
+        Rgba([
+            channel(value, masks[0]),
+            channel(value, masks[1]),
+            channel(value, masks[2]),
+            alpha,
+        ])
+    });
+    Ok(DynamicImage::ImageRgba8(img))
+}
+
+/// Encodes the levels of a mip chain as an uncompressed 32-bit BGRA DDS file.
+pub(crate) fn encode_dds(levels: &[RgbaImage]) -> Result<Vec<u8>, WasmImageError> {
+    let (width, height) = levels[0].dimensions();
+    let pitch = width
+        .checked_mul(4)
+        .ok_or_else(|| WasmImageError::EncodingError("Image too wide for DDS".to_string()))?;
+    let mip_count = levels.len() as u32;
+
+    let mut flags = DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PITCH | DDSD_PIXELFORMAT;
+    let mut caps = DDSCAPS_TEXTURE;
+    if mip_count > 1 {
+        flags |= DDSD_MIPMAPCOUNT;
+        caps |= DDSCAPS_COMPLEX | DDSCAPS_MIPMAP;
+    }
+
+    let mut header = [0u32; 31];
+    header[0] = 124;
+    header[1] = flags;
+    header[2] = height;
+    header[3] = width;
+    header[4] = pitch;
+    header[6] = mip_count;
+    // Pixel format, starting after the 11 reserved fields.
+    header[18] = 32;
+    header[19] = DDPF_RGB | DDPF_ALPHAPIXELS;
+    header[21] = 32;
+    header[22..26].copy_from_slice(&[0xff_0000, 0xff00, 0xff, 0xff00_0000]);
+    header[26] = caps;
+
+    let mut output = DDS_MAGIC.to_vec();
+    output.extend(header.iter().flat_map(|value| value.to_le_bytes()));
+    for level in levels {
+        output.extend(
+            level
+                .pixels()
+                .flat_map(|Rgba([r, g, b, a])| [*b, *g, *r, *a]),
+        );
+    }
+    Ok(output)
+}
+
+/// Data format descriptor of 8-bit sRGB RGBA, with linear alpha.
+fn ktx2_rgba8_dfd() -> Vec<u8> {
+    const SAMPLES: [(u32, u32); 4] = [(0, 0), (8, 1), (16, 2), (24, 15 | 0x10)];
+    let block_size = 24 + 16 * SAMPLES.len() as u32;
+
+    let mut words = vec![
+        4 + block_size,
+        // Khronos vendor, basic descriptor type.
+        0,
+        // Version 1.3 (2), then the block size.
+        2 | (block_size << 16),
+        // RGBSDA colour model, BT.709 primaries, sRGB transfer, straight alpha.
+        1 | (1 << 8) | (2 << 16),
+        // 1x1x1x1 texel blocks.
+        0,
+        // Four bytes in plane 0.
+        4,
+        0,
+    ];
+    for (offset, channel) in SAMPLES {
+        words.extend([offset | (7 << 16) | (channel << 24), 0, 0, 255]);
+    }
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+/// Encodes the levels of a mip chain as an uncompressed RGBA8 (sRGB) KTX2 file.
+pub(crate) fn encode_ktx2(levels: &[RgbaImage]) -> Vec<u8> {
+    let (width, height) = levels[0].dimensions();
+    let level_count = levels.len();
+
+    let dfd = ktx2_rgba8_dfd();
+    let dfd_offset = 80 + 24 * level_count;
+    let data_offset = dfd_offset + dfd.len();
+
+    let mut output = KTX2_IDENTIFIER.to_vec();
+    // vkFormat, typeSize, size, depth, layers, faces, levels and no supercompression.
+    let header = [VK_FORMAT_R8G8B8A8_SRGB, 1, width, height, 0, 0, 1];
+    output.extend(header.iter().flat_map(|value| value.to_le_bytes()));
+    output.extend((level_count as u32).to_le_bytes());
+    output.extend(0u32.to_le_bytes());
+    // Index: DFD, no key/value data, no supercompression global data.
+    output.extend((dfd_offset as u32).to_le_bytes());
+    output.extend((dfd.len() as u32).to_le_bytes());
+    output.extend([0; 8 + 16]);
+
+    // Level data is stored smallest first, the index lists the full-size level first.
+    let mut offsets = vec![0; level_count];
+    let mut offset = data_offset;
+    for (i, level) in levels.iter().enumerate().rev() {
+        offsets[i] = offset;
+        offset += level.as_raw().len();
+    }
+    for (level, offset) in levels.iter().zip(offsets) {
+        let len = level.as_raw().len() as u64;
+        for value in [offset as u64, len, len] {
+            output.extend(value.to_le_bytes());
+        }
+    }
+
+    output.extend(dfd);
+    for level in levels.iter().rev() {
+        output.extend_from_slice(level.as_raw());
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GenericImageView;
+
+    fn levels() -> Vec<RgbaImage> {
+        let full = RgbaImage::from_fn(4, 2, |x, y| Rgba([x as u8 * 60, y as u8 * 200, 30, 128]));
+        vec![
+            full,
+            RgbaImage::from_pixel(2, 1, Rgba([1, 2, 3, 4])),
+            RgbaImage::new(1, 1),
+        ]
+    }
+
+    #[test]
+    fn round_trips_uncompressed_dds() {
+        let levels = levels();
+        let dds = encode_dds(&levels).unwrap();
+        assert_eq!(
+            dds_info(&dds).unwrap(),
+            TextureInfo {
+                mip_count: 3,
+                format: "Uncompressed RGBA32".to_string()
+            }
+        );
+        assert_eq!(dds.len(), DDS_HEADER_END + (8 + 2 + 1) * 4);
+
+        let decoded = decode_dds(&dds).unwrap();
+        assert_eq!(decoded.dimensions(), (4, 2));
+        assert_eq!(decoded.to_rgba8(), levels[0]);
+    }
+
+    #[test]
+    fn decodes_dxt1_through_image() {
+        let mut dds = encode_dds(&[RgbaImage::new(4, 4)]).unwrap();
+        dds.truncate(DDS_HEADER_END);
+        dds[80..84].copy_from_slice(&DDPF_FOURCC.to_le_bytes());
+        dds[84..88].copy_from_slice(b"DXT1");
+        // One 4x4 block: two colours and the indices.
+        dds.extend([0xff, 0xff, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(dds_info(&dds).unwrap().format, "DXT1");
+        let decoded = decode_dds(&dds).unwrap();
+        assert_eq!(
+            decoded.to_rgba8().get_pixel(0, 0),
+            &Rgba([255, 255, 255, 255])
+        );
+    }
+
+    #[test]
+    fn writes_ktx2_levels() {
+        let levels = levels();
+        let ktx2 = encode_ktx2(&levels);
+        assert_eq!(&ktx2[..12], KTX2_IDENTIFIER);
+        assert_eq!(read_u32(&ktx2, 40), Some(3));
+
+        // The full-size level is the last in the file.
+        let offset = u64::from_le_bytes(ktx2[80..88].try_into().unwrap()) as usize;
+        assert_eq!(&ktx2[offset..], levels[0].as_raw().as_slice());
+        let dfd_offset = read_u32(&ktx2, 48).unwrap() as usize;
+        assert_eq!(read_u32(&ktx2, dfd_offset), Some(92));
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    #[test]
+    fn converts_to_and_from_dds() {
+        let jpeg = include_bytes!("../assets/test.jpeg");
+        let (width, height) = image::load_from_memory(jpeg).unwrap().dimensions();
+
+        let dds =
+            crate::convert_image(jpeg, "image/jpeg", "image/vnd-ms.dds", &None, None).unwrap();
+        let metadata = crate::load_metadata(&dds, "image/vnd-ms.dds").unwrap();
+        assert_eq!((metadata.width, metadata.height), (width, height));
+        let mip_count = 32 - width.max(height).leading_zeros();
+        assert_eq!(metadata.texture.unwrap().mip_count, mip_count);
+
+        let png = crate::convert_image(&dds, "image/vnd-ms.dds", "image/png", &None, None).unwrap();
+        let decoded = image::load_from_memory(&png).unwrap();
+        assert_eq!(decoded.dimensions(), (width, height));
+
+        let resized = crate::resize_image(&dds, "image/vnd-ms.dds", 8, 4).unwrap();
+        assert_eq!(dds_info(&resized).unwrap().mip_count, 4);
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    #[test]
+    fn exports_ktx2_without_mipmaps() {
+        use crate::convert::settings::{Settings, TextureSettings};
+
+        let jpeg = include_bytes!("../assets/test.jpeg");
+        let settings = Some(Settings::Texture(TextureSettings { mipmaps: false }));
+        let ktx2 = crate::convert_image(jpeg, "image/jpeg", "image/ktx2", &settings, None).unwrap();
+        assert_eq!(&ktx2[..12], KTX2_IDENTIFIER);
+        assert_eq!(read_u32(&ktx2, 40), Some(1));
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        let jpeg = include_bytes!("../assets/test.jpeg");
+        assert!(dds_info(jpeg).is_err());
+        assert!(decode_dds(jpeg).is_err());
+    }
+}
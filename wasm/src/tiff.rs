@@ -0,0 +1,187 @@
+//! Minimal reader for the TIFF structure, for what `image`'s decoder doesn't expose: the IFD
+//! chain of multi-page files and the sub-IFDs of TIFF-based camera RAW files.
+
+/// Upper bound on the number of IFDs followed, to stop on malformed files that loop.
+const MAX_IFDS: usize = 10_000;
+
+pub(crate) const TAG_IMAGE_WIDTH: u16 = 256;
+pub(crate) const TAG_IMAGE_LENGTH: u16 = 257;
+
+/// One IFD entry. The value is read on demand with [`TiffReader::values`].
+pub(crate) struct IfdEntry {
+    pub(crate) tag: u16,
+    field_type: u16,
+    count: u64,
+    /// Position of the value, or of its offset if it doesn't fit in the entry.
+    value_pos: usize,
+}
+
+pub(crate) struct Ifd {
+    pub(crate) offset: usize,
+    pub(crate) entries: Vec<IfdEntry>,
+    /// Offset of the next IFD in the chain, 0 at the end.
+    pub(crate) next: usize,
+}
+
+impl Ifd {
+    pub(crate) fn entry(&self, tag: u16) -> Option<&IfdEntry> {
+        self.entries.iter().find(|entry| entry.tag == tag)
+    }
+}
+
+pub(crate) struct TiffReader<'a> {
+    data: &'a [u8],
+    pub(crate) big_endian: bool,
+    big_tiff: bool,
+}
+
+impl<'a> TiffReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Option<Self> {
+        let big_endian = match data.get(..2)? {
+            b"II" => false,
+            b"MM" => true,
+            _ => return None,
+        };
+        let mut reader = Self {
+            data,
+            big_endian,
+            big_tiff: false,
+        };
+        reader.big_tiff = match reader.uint(2, 2)? {
+            42 => false,
+            43 => true,
+            _ => return None,
+        };
+        Some(reader)
+    }
+
+    fn uint(&self, pos: usize, len: usize) -> Option<u64> {
+        let bytes = self.data.get(pos..pos.checked_add(len)?)?;
+        let fold = |acc: u64, &b: &u8| (acc << 8) | u64::from(b);
+        Some(if self.big_endian {
+            bytes.iter().fold(0, fold)
+        } else {
+            bytes.iter().rev().fold(0, fold)
+        })
+    }
+
+    pub(crate) fn offset_size(&self) -> usize {
+        if self.big_tiff {
+            8
+        } else {
+            4
+        }
+    }
+
+    /// Position of the first IFD offset in the header.
+    pub(crate) fn header_offset_pos(&self) -> usize {
+        if self.big_tiff {
+            8
+        } else {
+            4
+        }
+    }
+
+    /// Reads the IFD at `offset`.
+    pub(crate) fn ifd(&self, offset: usize) -> Option<Ifd> {
+        let (count_size, entry_size) = if self.big_tiff { (8, 20) } else { (2, 12) };
+        let count = usize::try_from(self.uint(offset, count_size)?).ok()?;
+        let start = offset.checked_add(count_size)?;
+
+        let entries = (0..count)
+            .map(|i| {
+                let entry = start.checked_add(i.checked_mul(entry_size)?)?;
+                Some(IfdEntry {
+                    tag: self.uint(entry, 2)? as u16,
+                    field_type: self.uint(entry + 2, 2)? as u16,
+                    count: self.uint(entry + 4, self.offset_size())?,
+                    value_pos: entry + 4 + self.offset_size(),
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let next_pos = start.checked_add(count.checked_mul(entry_size)?)?;
+        let next = usize::try_from(self.uint(next_pos, self.offset_size())?).ok()?;
+        Some(Ifd {
+            offset,
+            entries,
+            next,
+        })
+    }
+
+    /// The values of an integer entry (`BYTE`, `SHORT`, `LONG`, `LONG8` or `IFD` offsets).
+    pub(crate) fn values(&self, entry: &IfdEntry) -> Option<Vec<u64>> {
+        let size = match entry.field_type {
+            1 | 7 => 1,
+            3 => 2,
+            4 | 13 => 4,
+            16 | 18 => 8,
+            _ => return None,
+        };
+        let count = usize::try_from(entry.count).ok()?;
+        let len = count.checked_mul(size)?;
+        if len > self.data.len() {
+            return None;
+        }
+        let pos = if len <= self.offset_size() {
+            entry.value_pos
+        } else {
+            usize::try_from(self.uint(entry.value_pos, self.offset_size())?).ok()?
+        };
+        (0..count)
+            .map(|i| self.uint(pos + i * size, size))
+            .collect()
+    }
+
+    /// The first value of `tag` in an IFD.
+    pub(crate) fn value(&self, ifd: &Ifd, tag: u16) -> Option<u64> {
+        self.values(ifd.entry(tag)?)?.first().copied()
+    }
+
+    /// All IFDs of the main chain, in file order.
+    pub(crate) fn chain(&self) -> Option<Vec<Ifd>> {
+        let offset = self.uint(self.header_offset_pos(), self.offset_size())?;
+        let mut offset = usize::try_from(offset).ok()?;
+        let mut ifds: Vec<Ifd> = Vec::new();
+        while offset != 0 {
+            if ifds.len() >= MAX_IFDS || ifds.iter().any(|ifd| ifd.offset == offset) {
+                return None;
+            }
+            let ifd = self.ifd(offset)?;
+            offset = ifd.next;
+            ifds.push(ifd);
+        }
+        Some(ifds)
+    }
+
+    /// The main chain and, depth first, the IFDs its entries `sub_ifd_tags` point to (such as
+    /// `SubIFDs`, where camera RAW files keep their previews and raw data).
+    #[cfg(feature = "raw")]
+    pub(crate) fn all_ifds(&self, sub_ifd_tags: &[u16]) -> Option<Vec<Ifd>> {
+        let mut pending: Vec<usize> = self.chain()?.iter().rev().map(|ifd| ifd.offset).collect();
+        let mut ifds: Vec<Ifd> = Vec::new();
+        while let Some(offset) = pending.pop() {
+            if ifds.len() >= MAX_IFDS {
+                return None;
+            }
+            if ifds.iter().any(|ifd| ifd.offset == offset) {
+                continue;
+            }
+            let Some(ifd) = self.ifd(offset) else {
+                continue;
+            };
+            for tag in sub_ifd_tags {
+                if let Some(offsets) = ifd.entry(*tag).and_then(|entry| self.values(entry)) {
+                    pending.extend(
+                        offsets
+                            .iter()
+                            .rev()
+                            .filter_map(|&offset| usize::try_from(offset).ok()),
+                    );
+                }
+            }
+            ifds.push(ifd);
+        }
+        Some(ifds)
+    }
+}
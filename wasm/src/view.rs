@@ -1,88 +1,319 @@
-use crate::{load::load_image, source_type::SourceType};
+use image::{DynamicImage, ImageFormat};
+
+use crate::{
+    detect::resolve_mime_type,
+    error::WasmImageError,
+    load::{load_image, load_image_lenient},
+    pages::select_page,
+    preview::{decode_scaled_jpeg, fit, ScaledJpeg},
+    source_type::SourceType,
+};
 
 #[cfg(feature = "wasm")] 
 use {
-    js_sys::Uint8Array,
+    crate::input::{ImageInput, Input},
+    js_sys::Uint8ClampedArray,
     wasm_bindgen::prelude::*,
 };
 
-#[cfg(not(feature = "wasm"))]
-use crate::error::WasmImageError;
-
 #[cfg_attr(feature = "wasm", derive(tsify::Tsify))]
 #[cfg_attr(feature = "wasm", tsify(from_wasm_abi, into_wasm_abi))]
 #[derive(serde::Serialize, serde::Deserialize, Default)]
 pub struct ImageData {
     pub width: u32,
     pub height: u32,
+    /// Width of the decoded image, larger than `width` when scaled down to `max_dimension`.
+    pub original_width: u32,
+    pub original_height: u32,
     pub aspect_ratio: f32,
     pub color_depth: u16,
     pub pixels: Vec<u8>,
+    /// Problems found when decoding leniently, e.g. the image data being truncated.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[cfg_attr(feature = "wasm", tsify(optional))]
+    pub warnings: Vec<String>,
 }
 
-#[cfg(feature = "wasm")]
-#[wasm_bindgen(js_name = getPixels)]
-/// Convert an image file to a raw RGBA image.
-/// # Arguments
-/// * `file` - The image file to convert.
-/// * `src_type` - The MIME type of the source image.
-/// # Returns
-/// The raw RGBA image data.
-/// # Errors
-/// Returns an error if the image could not be loaded or rasterized.
-pub fn get_pixels(file: &Uint8Array, src_type: &str) -> Result<ImageData, JsValue> {
-    let src_mime_type = SourceType::from_mime_type(src_type);
+/// RGBA pixels of an image, without copying them through serde. In JavaScript the pixels are a
+/// `Uint8ClampedArray` owned by JavaScript, ready for `new ImageData(pixels, width, height)`.
+#[cfg_attr(feature = "wasm", wasm_bindgen(getter_with_clone))]
+pub struct PixelBuffer {
+    /// Width of the pixel data, smaller than `original_width` for previews.
+    pub width: u32,
+    pub height: u32,
+    /// Width of the decoded image.
+    pub original_width: u32,
+    pub original_height: u32,
+    pub aspect_ratio: f32,
+    pub color_depth: u16,
+    #[cfg(feature = "wasm")]
+    pub pixels: Uint8ClampedArray,
+    #[cfg(not(feature = "wasm"))]
+    pub pixels: Vec<u8>,
+    /// Problems found when decoding leniently, e.g. the image data being truncated.
+    pub warnings: Vec<String>,
+}
+
+/// A decoded image as shown by the viewer functions.
+struct View {
+    img: DynamicImage,
+    /// Size of the decoded image, before scaling it down.
+    original: (u32, u32),
+    color_depth: u16,
+    warnings: Vec<String>,
+}
 
-    let file = file.to_vec();
+fn view_image(
+    file: &[u8],
+    src_type: &str,
+    page: Option<u32>,
+    lenient: Option<bool>,
+    max_dimension: Option<u32>,
+) -> Result<View, WasmImageError> {
+    if max_dimension == Some(0) {
+        return Err(WasmImageError::InvalidSettings(
+            "The maximum dimension must be greater than zero".to_string(),
+        ));
+    }
+    let src_mime_type = SourceType::from_mime_type(resolve_mime_type(file, src_type)?);
 
-    let img = load_image(&file, src_mime_type.as_ref())
-        .map_err(|e| JsValue::from_str(e.to_string().as_str()))?
-        .rasterize(None)
-        .map_err(|e| JsValue::from_str(e.to_string().as_str()))?;
+    let file = select_page(file, src_mime_type.as_ref(), page)?;
 
-    let pixels = img.to_rgba8().into_vec();
+    let lenient = lenient.unwrap_or(false);
+
+    // Errors of the scaled decoder are left to the regular decoders to report. It can't report
+    // what it skipped over, so lenient decoding always goes through `load_image_lenient`.
+    if let (Some(SourceType::Raster(ImageFormat::Jpeg)), Some(max_dimension), false) =
+        (src_mime_type.as_ref(), max_dimension, lenient)
+    {
+        if let Ok(Some(ScaledJpeg { img, original })) = decode_scaled_jpeg(&file, max_dimension) {
+            let color_depth = img.color().bits_per_pixel();
+            return Ok(View {
+                img: fit(img, max_dimension),
+                original,
+                color_depth,
+                warnings: Vec::new(),
+            });
+        }
+    }
+
+    let (img, warnings) = if lenient {
+        load_image_lenient(&file, src_mime_type.as_ref())?
+    } else {
+        (load_image(&file, src_mime_type.as_ref())?, Vec::new())
+    };
+    let img = img.rasterize(None)?;
 
     let color_depth = match src_mime_type {
         Some(SourceType::Svg) => 32,
         _ => img.color().bits_per_pixel(),
     };
+    let original = (img.width(), img.height());
+    let img = match max_dimension {
+        Some(max_dimension) => fit(img, max_dimension),
+        None => img,
+    };
 
-    Ok(ImageData {
-        width: img.width(),
-        height: img.height(),
-        aspect_ratio: img.width() as f32 / img.height() as f32,
+    Ok(View {
+        img,
+        original,
         color_depth,
-        pixels,
+        warnings,
     })
 }
 
+impl View {
+    fn into_image_data(self) -> ImageData {
+        let (original_width, original_height) = self.original;
+        ImageData {
+            width: self.img.width(),
+            height: self.img.height(),
+            original_width,
+            original_height,
+            aspect_ratio: original_width as f32 / original_height as f32,
+            color_depth: self.color_depth,
+            pixels: self.img.into_rgba8().into_vec(),
+            warnings: self.warnings,
+        }
+    }
+
+    fn into_pixel_buffer(self) -> PixelBuffer {
+        let (original_width, original_height) = self.original;
+        let (width, height) = (self.img.width(), self.img.height());
+        let pixels = self.img.into_rgba8().into_vec();
+
+        PixelBuffer {
+            width,
+            height,
+            original_width,
+            original_height,
+            aspect_ratio: original_width as f32 / original_height as f32,
+            color_depth: self.color_depth,
+            #[cfg(feature = "wasm")]
+            pixels: Uint8ClampedArray::from(pixels.as_slice()),
+            #[cfg(not(feature = "wasm"))]
+            pixels,
+            warnings: self.warnings,
+        }
+    }
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen(js_name = getPixels)]
+/// Convert an image file to a raw RGBA image.
+/// # Arguments
+/// * `file` - The image file to convert, as a `Uint8Array` or a `Blob`.
+/// * `src_type` - The MIME type of the source image.
+/// * `page` - Zero-based page of a multi-page TIFF to show. Defaults to the first page.
+/// * `lenient` - Return the decoded part of truncated or corrupt images, with the rest filled
+///   with grey, instead of an error. The problems are listed in the `warnings`.
+/// * `max_dimension` - Scale the image down to fit in a square of this size, e.g. for a canvas.
+///   JPEGs are decoded at reduced size. Smaller images are returned as they are.
+/// # Returns
+/// The raw RGBA image data, with the size of the decoded image in `original_width` and
+/// `original_height`.
+/// # Errors
+/// Returns an error if the image could not be loaded or rasterized, the page does not exist, or
+/// the maximum dimension is zero.
+pub fn get_pixels(
+    file: &ImageInput,
+    src_type: &str,
+    page: Option<u32>,
+    lenient: Option<bool>,
+    max_dimension: Option<u32>,
+) -> Result<ImageData, JsValue> {
+    let file = Input::new(file)?.read()?;
+    Ok(view_image(&file, src_type, page, lenient, max_dimension)?.into_image_data())
+}
+
 #[cfg(not(feature = "wasm"))]
 /// Convert an image file to a raw RGBA image.
 /// # Arguments
 /// * `file` - The image file to convert.
 /// * `src_type` - The MIME type of the source image.
+/// * `page` - Zero-based page of a multi-page TIFF to show. Defaults to the first page.
+/// * `lenient` - Return the decoded part of truncated or corrupt images, with the rest filled
+///   with grey, instead of an error. The problems are listed in the `warnings`.
+/// * `max_dimension` - Scale the image down to fit in a square of this size, e.g. for a canvas.
+///   JPEGs are decoded at reduced size. Smaller images are returned as they are.
+/// # Returns
+/// The raw RGBA image data, with the size of the decoded image in `original_width` and
+/// `original_height`.
+/// # Errors
+/// Returns an error if the image could not be loaded or rasterized, the page does not exist, or
+/// the maximum dimension is zero.
+pub fn get_pixels(
+    file: &[u8],
+    src_type: &str,
+    page: Option<u32>,
+    lenient: Option<bool>,
+    max_dimension: Option<u32>,
+) -> Result<ImageData, WasmImageError> {
+    Ok(view_image(file, src_type, page, lenient, max_dimension)?.into_image_data())
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen(js_name = getPixelBuffer)]
+/// Convert an image file to raw RGBA pixels in a `Uint8ClampedArray`, copied once into
+/// JavaScript memory instead of being serialized like [`get_pixels`] does.
+/// # Arguments
+/// * `file` - The image file to convert, as a `Uint8Array` or a `Blob`.
+/// * `src_type` - The MIME type of the source image.
+/// * `page` - Zero-based page of a multi-page TIFF to show. Defaults to the first page.
+/// * `lenient` - Return the decoded part of truncated or corrupt images, with the rest filled
+///   with grey, instead of an error. The problems are listed in the `warnings`.
+/// * `max_dimension` - Scale the image down to fit in a square of this size. JPEGs are decoded
+///   at reduced size. Smaller images are returned as they are.
+/// # Returns
+/// The pixels with their dimensions. Call `free()` once the pixels have been used.
+/// # Errors
+/// Returns an error if the image could not be loaded or rasterized, the page does not exist, or
+/// the maximum dimension is zero.
+pub fn get_pixel_buffer(
+    file: &ImageInput,
+    src_type: &str,
+    page: Option<u32>,
+    lenient: Option<bool>,
+    max_dimension: Option<u32>,
+) -> Result<PixelBuffer, JsValue> {
+    let file = Input::new(file)?.read()?;
+    Ok(view_image(&file, src_type, page, lenient, max_dimension)?.into_pixel_buffer())
+}
+
+#[cfg(not(feature = "wasm"))]
+/// Convert an image file to raw RGBA pixels, optionally scaled down to a preview.
+/// # Arguments
+/// * `file` - The image file to convert.
+/// * `src_type` - The MIME type of the source image.
+/// * `page` - Zero-based page of a multi-page TIFF to show. Defaults to the first page.
+/// * `lenient` - Return the decoded part of truncated or corrupt images, with the rest filled
+///   with grey, instead of an error. The problems are listed in the `warnings`.
+/// * `max_dimension` - Scale the image down to fit in a square of this size. JPEGs are decoded
+///   at reduced size. Smaller images are returned as they are.
 /// # Returns
-/// The raw RGBA image data.
+/// The pixels with their dimensions and those of the decoded image.
 /// # Errors
-/// Returns an error if the image could not be loaded or rasterized.
-pub fn get_pixels(file: &[u8], src_type: &str) -> Result<ImageData, WasmImageError> {
-    let src_mime_type = SourceType::from_mime_type(src_type);
+/// Returns an error if the image could not be loaded or rasterized, the page does not exist, or
+/// the maximum dimension is zero.
+pub fn get_pixel_buffer(
+    file: &[u8],
+    src_type: &str,
+    page: Option<u32>,
+    lenient: Option<bool>,
+    max_dimension: Option<u32>,
+) -> Result<PixelBuffer, WasmImageError> {
+    Ok(view_image(file, src_type, page, lenient, max_dimension)?.into_pixel_buffer())
+}
 
-    let img = load_image(file, src_mime_type.as_ref())?
-        .rasterize(None)?;
+#[cfg(all(test, not(feature = "wasm")))]
+mod tests {
+    use super::*;
 
-    let pixels = img.to_rgba8().into_vec();
+    #[test]
+    fn scales_previews_down() {
+        let file = include_bytes!("../assets/test.jpeg");
+        let full = get_pixels(file, "image/jpeg", None, None, None).unwrap();
+        assert_eq!(
+            (full.original_width, full.original_height),
+            (full.width, full.height)
+        );
 
-    let color_depth = match src_mime_type {
-        Some(SourceType::Svg) => 32,
-        _ => img.color().bits_per_pixel(),
-    };
+        let buffer = get_pixel_buffer(file, "image/jpeg", None, None, None).unwrap();
+        assert_eq!((buffer.width, buffer.height), (full.width, full.height));
+        assert_eq!(buffer.pixels, full.pixels);
 
-    Ok(ImageData {
-        width: img.width(),
-        height: img.height(),
-        aspect_ratio: img.width() as f32 / img.height() as f32,
-        color_depth,
-        pixels,
-    })
+        let preview = get_pixel_buffer(file, "image/jpeg", None, None, Some(16)).unwrap();
+        assert_eq!((preview.width, preview.height), (16, 11));
+        assert_eq!(
+            (preview.original_width, preview.original_height),
+            (full.width, full.height)
+        );
+        assert_eq!(
+            preview.pixels.len(),
+            (preview.width * preview.height * 4) as usize
+        );
+        assert!(get_pixel_buffer(file, "image/jpeg", None, None, Some(0)).is_err());
+    }
+
+    #[test]
+    fn resizes_other_formats() {
+        let file = include_bytes!("../assets/exif.webp");
+        let full = get_pixels(file, "image/webp", None, None, None).unwrap();
+        let preview = get_pixels(file, "image/webp", None, None, Some(8)).unwrap();
+        assert_eq!((preview.width, preview.height), (8, 4));
+        assert_eq!(
+            (preview.original_width, preview.original_height),
+            (full.width, full.height)
+        );
+        assert!((preview.aspect_ratio - full.aspect_ratio).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn warns_about_truncated_previews() {
+        let file = include_bytes!("../assets/test.jpeg");
+        let truncated = &file[..file.len() * 4 / 5];
+        let preview = get_pixels(truncated, "image/jpeg", None, Some(true), Some(16)).unwrap();
+        assert_eq!((preview.width, preview.height), (16, 11));
+        assert!(!preview.warnings.is_empty());
+    }
 }
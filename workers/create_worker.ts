@@ -1,5 +1,5 @@
 import type { WorkerProgress } from './shared_types'
-import { parseWorkerError, WorkerMessageType } from './shared_types'
+import { toWorkerError, WorkerMessageType } from './shared_types'
 
 type ProgressCallback = (update: WorkerProgress) => void
 
@@ -17,7 +17,7 @@ export function createWorker<TRequest, TResult>(
       globalThis.postMessage({ type: WorkerMessageType.DONE, payload: { success: true, data: result } })
     }
     catch (err) {
-      globalThis.postMessage({ type: WorkerMessageType.ERROR, payload: { success: false, error: parseWorkerError(err) } })
+      globalThis.postMessage({ type: WorkerMessageType.ERROR, payload: { success: false, error: toWorkerError(err) } })
     }
   })
 }
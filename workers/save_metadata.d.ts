@@ -1,4 +1,4 @@
-import type { MetadataChanges } from '#image/wasm/pkg/bundler/refilelabs_image'
+import type { MetadataChanges, ThumbnailAction } from '#image/wasm/pkg/bundler/refilelabs_image'
 import type { WorkerMessage, WorkerRequest, WorkerResponse } from './shared_types'
 
 export type SaveMetadataWorkerResponse = WorkerResponse<Uint8Array>
@@ -8,4 +8,5 @@ export interface SaveMetadataWorkerRequest extends WorkerRequest {
   changes: MetadataChanges
   stripAll: boolean
   stripGps: boolean
+  thumbnail?: ThumbnailAction
 }
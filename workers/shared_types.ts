@@ -1,15 +1,20 @@
+import type { ErrorInfo } from '#image/wasm/pkg/bundler/refilelabs_image'
+
 export enum WorkerMessageType {
   PROGRESS,
   DONE,
   ERROR,
 }
 
+/** An error raised in a worker. Errors from the wasm module keep their `code`, `format` and `stage`. */
+export type WorkerError = Pick<ErrorInfo, 'message'> & Partial<ErrorInfo>
+
 export type WorkerResponse<T> = {
   success: true
   data: T
 } | {
   success: false
-  error: string
+  error: WorkerError
 }
 
 export interface WorkerProgress {
@@ -29,13 +34,22 @@ export type WorkerMessage<T extends WorkerResponse<unknown>> = {
 }
 
 export interface WorkerRequest {
-  inputFile: Uint8Array
+  /** File bytes, or a `Blob` (e.g. a `File`) that the worker reads on demand */
+  inputFile: Uint8Array | Blob
   inputType: string
 }
 
 const errRegex = /^Error: /
 
+export function isWasmError(e: unknown): e is ErrorInfo {
+  return typeof e === 'object' && e !== null && 'code' in e && 'message' in e
+}
+
 export function parseWorkerError(e: unknown): string {
+  if (isWasmError(e)) {
+    return e.message
+  }
+
   let msg: string
   if (e instanceof Error) {
     msg = e.message
@@ -45,3 +59,11 @@ export function parseWorkerError(e: unknown): string {
   }
   return msg.replace(errRegex, '')
 }
+
+export function toWorkerError(e: unknown): WorkerError {
+  if (isWasmError(e)) {
+    const { code, message, format, stage } = e
+    return { code, message, format, stage }
+  }
+  return { message: parseWorkerError(e) }
+}